@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::custom;
+
+
+pub const BOOKS_DIR: &str = "books";
+pub const PAGE_WORDS: usize = 60;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Document {
+    pub name: String,
+    pub path: String,
+    pub page: usize,
+    pub pages: usize,
+    pub pages_done: usize,
+    pub words_typed: usize,
+    pub chars_typed: usize,
+    pub correct_chars: usize,
+    pub seconds: f64,
+    pub last_opened: u64,
+}
+
+impl Document {
+    pub fn wpm(&self) -> f64 {
+        if self.seconds > 0.0 {
+            self.words_typed as f64 / (self.seconds / 60.0)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        if self.chars_typed > 0 {
+            self.correct_chars as f64 / self.chars_typed as f64 * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn record_page(&mut self, seconds: f64, correct_words: usize, correct_chars: usize, chars_typed: usize) {
+        self.seconds += seconds;
+        self.words_typed += correct_words;
+        self.correct_chars += correct_chars;
+        self.chars_typed += chars_typed;
+        self.pages_done += 1;
+        self.page += 1;
+    }
+}
+
+// FNV-1a, stable between runs so the same file always maps to the same progress file.
+fn path_hash(path: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in path.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn document_file(path: &str) -> String {
+    format!("{}/{:016x}.json", BOOKS_DIR, path_hash(path))
+}
+
+pub fn open(path: &Path, pages: usize) -> Document {
+    let full_path = fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string();
+    let mut document: Document = fs::read_to_string(document_file(&full_path))
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+    document.name = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| full_path.clone());
+    document.path = full_path;
+    document.pages = pages;
+    document.last_opened = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    save(&document);
+    document
+}
+
+pub fn save(document: &Document) {
+    fs::create_dir_all(BOOKS_DIR).ok();
+    if let Ok(json) = serde_json::to_string_pretty(document) {
+        fs::write(document_file(&document.path), json).ok();
+    }
+}
+
+pub fn pages(text: &str) -> Vec<String> {
+    custom::split_chunks(text, PAGE_WORDS)
+}
+
+pub fn import(path: &Path) -> Result<String, String> {
+    let extension = path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if extension == "epub" {
+        return Err("EPUB files are zip archives, extract the chapters first and pass the .xhtml or .txt files".to_string());
+    }
+    let content = fs::read_to_string(path).map_err(|_| "Error reading file".to_string())?;
+    let text = match extension.as_str() {
+        "md" | "markdown" => strip_markdown(&content),
+        "html" | "htm" | "xhtml" => strip_html(&content),
+        _ => content,
+    };
+    Ok(custom::normalize(&to_ascii_punctuation(&text)))
+}
+
+// Typographic characters from books and ebook converters that are not on a regular keyboard.
+fn to_ascii_punctuation(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201B}' | '\u{2032}' => result.push('\''),
+            '\u{201C}' | '\u{201D}' | '\u{201F}' | '\u{00AB}' | '\u{00BB}' => result.push('"'),
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => result.push('-'),
+            '\u{2026}' => result.push_str("..."),
+            '\u{00A0}' | '\u{2009}' | '\u{200A}' | '\u{202F}' => result.push(' '),
+            '\u{00AD}' | '\u{200B}' | '\u{FEFF}' => {}
+            '\u{000C}' => result.push('\n'),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+pub fn strip_markdown(content: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_code_block = false;
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block || trimmed.starts_with("<!--") || trimmed.chars().all(|c| "-=*_| ".contains(c)) {
+            continue;
+        }
+        let mut line = trimmed.trim_start_matches('#').trim_start_matches('>').trim_start();
+        for marker in ["- [ ] ", "- [x] ", "- ", "* ", "+ "] {
+            if let Some(rest) = line.strip_prefix(marker) {
+                line = rest;
+                break;
+            }
+        }
+        let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits > 0 && line[digits..].starts_with(". ") {
+            line = &line[digits + 2..];
+        }
+        lines.push(strip_inline_markdown(line));
+    }
+    lines.join("\n")
+}
+
+fn strip_inline_markdown(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '!' if chars.get(i + 1) == Some(&'[') => i += 1,
+            '[' => {
+                // [text](url) keeps only the text
+                if let Some(close) = chars[i..].iter().position(|&c| c == ']').map(|p| p + i)
+                    && chars.get(close + 1) == Some(&'(')
+                    && let Some(end) = chars[close..].iter().position(|&c| c == ')').map(|p| p + close)
+                {
+                    result.extend(&chars[i + 1..close]);
+                    i = end + 1;
+                    continue;
+                }
+                result.push('[');
+                i += 1;
+            }
+            '*' | '`' => i += 1,
+            '_' => {
+                let prev_alnum = i > 0 && chars[i - 1].is_alphanumeric();
+                let next_alnum = chars.get(i + 1).is_some_and(|c| c.is_alphanumeric());
+                if prev_alnum && next_alnum {
+                    result.push('_');
+                }
+                i += 1;
+            }
+            c => {
+                result.push(c);
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+pub fn strip_html(content: &str) -> String {
+    let mut result = String::new();
+    let mut rest = content;
+    let mut skip_until: Option<String> = None;
+    while let Some(start) = rest.find('<') {
+        if skip_until.is_none() {
+            result.push_str(&decode_entities(&rest[..start]));
+        }
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let tag = rest[start + 1..end].trim().to_lowercase();
+        let name: String = tag.trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_alphanumeric())
+            .collect();
+        if let Some(closing) = &skip_until {
+            if tag.starts_with('/') && name == *closing {
+                skip_until = None;
+            }
+        } else if ["head", "style", "script"].contains(&name.as_str()) && !tag.starts_with('/') && !tag.ends_with('/') {
+            skip_until = Some(name);
+        } else if ["p", "br", "div", "li", "h1", "h2", "h3", "h4", "h5", "h6", "tr", "blockquote"].contains(&name.as_str()) {
+            result.push('\n');
+        }
+        rest = &rest[end + 1..];
+    }
+    if skip_until.is_none() {
+        result.push_str(&decode_entities(rest));
+    }
+    result
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let end = after.find(';').filter(|&end| end <= 10);
+        let Some(end) = end else {
+            result.push('&');
+            rest = &after[1..];
+            continue;
+        };
+        let entity = &after[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "mdash" | "ndash" => Some('-'),
+            "hellip" => Some('\u{2026}'),
+            "lsquo" | "rsquo" => Some('\''),
+            "ldquo" | "rdquo" => Some('"'),
+            _ => entity.strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                result.push(c);
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &after[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,278 @@
+use std::fs;
+use std::path::Path;
+
+use crate::history;
+use crate::ui::gui::main::ROBOTO_MONO;
+
+
+pub const CARDS_DIR: &str = "cards";
+pub const FORMATS: [&str; 2] = ["png", "svg"];
+
+const WIDTH: f32 = 800.0;
+const HEIGHT: f32 = 400.0;
+// PNG cards are rendered at twice the SVG size so they stay sharp in chat previews.
+const PNG_SCALE: f32 = 2.0;
+
+const BACKGROUND: [u8; 4] = [15, 12, 10, 255];
+const MAIN: [u8; 4] = [255, 155, 0, 255];
+const LABEL: [u8; 4] = [255, 255, 255, 80];
+const TITLE: [u8; 4] = [255, 255, 255, 220];
+const GRID: [u8; 4] = [255, 255, 255, 25];
+const ERROR: [u8; 4] = [255, 50, 50, 255];
+
+#[derive(Debug, Clone, Default)]
+pub struct Card {
+    pub timestamp: u64,
+    pub mode: String,
+    pub punctuation: bool,
+    pub numbers: bool,
+    pub seconds: f64,
+    pub wpm: f64,
+    pub raw_wpm: f64,
+    pub accuracy: f64,
+    pub wpm_per_second: Vec<f64>,
+    pub errors_per_second: Vec<f64>,
+}
+
+enum Shape {
+    Rect { x: f32, y: f32, width: f32, height: f32, color: [u8; 4] },
+    Line { from: (f32, f32), to: (f32, f32), width: f32, color: [u8; 4] },
+    // y is the baseline, like draw_text_ex and SVG <text>
+    Text { x: f32, y: f32, size: f32, text: String, color: [u8; 4] },
+}
+
+// Roboto Mono advances every glyph by 0.6 em.
+fn text_width(text: &str, size: f32) -> f32 {
+    text.chars().count() as f32 * size * 0.6
+}
+
+fn text(x: f32, y: f32, size: f32, text: &str, color: [u8; 4]) -> Shape {
+    Shape::Text { x, y, size, text: text.to_string(), color }
+}
+
+// Same smoothing as the GUI chart, two seconds on each side.
+fn smooth(values: &[f64]) -> Vec<f64> {
+    (0..values.len())
+        .map(|i| {
+            let slice = &values[i.saturating_sub(2)..(i + 3).min(values.len())];
+            slice.iter().sum::<f64>() / slice.len() as f64
+        })
+        .collect()
+}
+
+fn layout(card: &Card) -> Vec<Shape> {
+    let mut shapes = vec![Shape::Rect { x: 0.0, y: 0.0, width: WIDTH, height: HEIGHT, color: BACKGROUND }];
+
+    shapes.push(text(40.0, 55.0, 28.0, "Type", MAIN));
+    shapes.push(text(40.0 + text_width("Type", 28.0), 55.0, 28.0, "Man", TITLE));
+    let date = history::date(card.timestamp);
+    shapes.push(text(WIDTH - 40.0 - text_width(&date, 18.0), 52.0, 18.0, &date, LABEL));
+
+    shapes.push(text(40.0, 110.0, 20.0, "wpm", LABEL));
+    shapes.push(text(40.0, 170.0, 60.0, &format!("{:.0}", card.wpm), MAIN));
+    shapes.push(text(40.0, 215.0, 20.0, "acc", LABEL));
+    shapes.push(text(40.0, 275.0, 60.0, &format!("{:.0}%", card.accuracy), MAIN));
+
+    let extras: Vec<&str> = [(card.punctuation, "punctuation"), (card.numbers, "numbers")]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect();
+    shapes.push(text(520.0 + text_width("mode ", 16.0), 335.0, 16.0, &extras.join(" "), MAIN));
+    for (i, (label, value)) in [
+        ("raw wpm", format!("{:.0}", card.raw_wpm)),
+        ("time", format!("{:.0}s", card.seconds)),
+        ("mode", card.mode.clone()),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let x = 220.0 + i as f32 * 150.0;
+        shapes.push(text(x, 335.0, 16.0, label, LABEL));
+        shapes.push(text(x, 365.0, 24.0, value, MAIN));
+    }
+
+    let (chart_x, chart_y, chart_width, chart_height) = (220.0, 90.0, WIDTH - 260.0, 200.0);
+    let speeds = smooth(&card.wpm_per_second);
+    let max_x = (speeds.len() as f64 - 1.0).max(5.0);
+    let max_y = 1.2 * speeds.iter().cloned().fold(50.0, f64::max);
+    let point = |second: f64, wpm: f64| {
+        (
+            chart_x + (second / max_x) as f32 * chart_width,
+            chart_y + chart_height - (wpm / max_y) as f32 * chart_height,
+        )
+    };
+
+    let mut wpm = 0.0;
+    while wpm <= max_y {
+        let (_, y) = point(0.0, wpm);
+        shapes.push(Shape::Line { from: (chart_x, y), to: (chart_x + chart_width, y), width: 1.0, color: GRID });
+        shapes.push(text(chart_x - 8.0 - text_width(&format!("{}", wpm), 12.0), y + 4.0, 12.0, &format!("{}", wpm), LABEL));
+        wpm += 20.0;
+    }
+    for pair in speeds.windows(2).enumerate().map(|(i, pair)| (point(i as f64, pair[0]), point(i as f64 + 1.0, pair[1]))) {
+        shapes.push(Shape::Line { from: pair.0, to: pair.1, width: 3.0, color: MAIN });
+    }
+    let (_, cross_y) = point(0.0, max_y / 7.0 / 1.2);
+    for (second, &errors) in card.errors_per_second.iter().enumerate().filter(|(_, errors)| **errors > 0.0) {
+        let (x, _) = point(second as f64, 0.0);
+        let size = if errors <= 1.0 { 3.0 } else if errors <= 2.0 { 4.5 } else { 6.0 };
+        shapes.push(Shape::Line { from: (x - size, cross_y - size), to: (x + size, cross_y + size), width: 2.0, color: ERROR });
+        shapes.push(Shape::Line { from: (x - size, cross_y + size), to: (x + size, cross_y - size), width: 2.0, color: ERROR });
+    }
+    shapes
+}
+
+fn svg_color(attribute: &str, color: [u8; 4]) -> String {
+    format!(
+        "{}=\"rgb({},{},{})\" {}-opacity=\"{:.2}\"",
+        attribute, color[0], color[1], color[2], attribute, color[3] as f32 / 255.0
+    )
+}
+
+pub fn svg(card: &Card) -> String {
+    let mut lines = vec![format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">",
+        WIDTH, HEIGHT
+    )];
+    for shape in layout(card) {
+        lines.push(match shape {
+            Shape::Rect { x, y, width, height, color } => {
+                format!("  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>", x, y, width, height, svg_color("fill", color))
+            }
+            Shape::Line { from, to, width, color } => format!(
+                "  <line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke-width=\"{}\" stroke-linecap=\"round\" {}/>",
+                from.0, from.1, to.0, to.1, width, svg_color("stroke", color)
+            ),
+            Shape::Text { x, y, size, text, color } => format!(
+                "  <text x=\"{:.1}\" y=\"{:.1}\" font-family=\"Roboto Mono, monospace\" font-size=\"{}\" {}>{}</text>",
+                x,
+                y,
+                size,
+                svg_color("fill", color),
+                text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+            ),
+        });
+    }
+    lines.push("</svg>".to_string());
+    lines.join("\n")
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    // The background is opaque, so only the colour channels need blending.
+    fn blend(&mut self, x: i64, y: i64, color: [u8; 4], coverage: f32) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        let alpha = coverage.clamp(0.0, 1.0) * color[3] as f32 / 255.0;
+        let index = (y as usize * self.width + x as usize) * 4;
+        for (pixel, &channel) in self.pixels[index..index + 3].iter_mut().zip(&color) {
+            *pixel = (*pixel as f32 + (channel as f32 - *pixel as f32) * alpha).round() as u8;
+        }
+        self.pixels[index + 3] = 255;
+    }
+
+    fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [u8; 4]) {
+        for py in y.round() as i64..(y + height).round() as i64 {
+            for px in x.round() as i64..(x + width).round() as i64 {
+                self.blend(px, py, color, 1.0);
+            }
+        }
+    }
+
+    // Antialiased by the distance of every pixel centre from the segment.
+    fn line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: [u8; 4]) {
+        let reach = width / 2.0 + 1.0;
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length = dx * dx + dy * dy;
+        for py in (from.1.min(to.1) - reach).floor() as i64..=(from.1.max(to.1) + reach).ceil() as i64 {
+            for px in (from.0.min(to.0) - reach).floor() as i64..=(from.0.max(to.0) + reach).ceil() as i64 {
+                let (cx, cy) = (px as f32 + 0.5, py as f32 + 0.5);
+                let t = if length > 0.0 { (((cx - from.0) * dx + (cy - from.1) * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+                let distance = ((cx - from.0 - t * dx).powi(2) + (cy - from.1 - t * dy).powi(2)).sqrt();
+                let coverage = width / 2.0 + 0.5 - distance;
+                if coverage > 0.0 {
+                    self.blend(px, py, color, coverage);
+                }
+            }
+        }
+    }
+
+    fn text(&mut self, font: &fontdue::Font, x: f32, y: f32, size: f32, text: &str, color: [u8; 4]) {
+        let mut pen_x = x;
+        for c in text.chars() {
+            let (metrics, bitmap) = font.rasterize(c, size);
+            let left = (pen_x + metrics.xmin as f32).round() as i64;
+            let top = (y - metrics.height as f32 - metrics.ymin as f32).round() as i64;
+            for (i, &coverage) in bitmap.iter().enumerate() {
+                if coverage > 0 {
+                    let (gx, gy) = ((i % metrics.width) as i64, (i / metrics.width) as i64);
+                    self.blend(left + gx, top + gy, color, coverage as f32 / 255.0);
+                }
+            }
+            pen_x += metrics.advance_width;
+        }
+    }
+}
+
+pub fn png(card: &Card) -> Result<Vec<u8>, String> {
+    let font = fontdue::Font::from_bytes(ROBOTO_MONO, fontdue::FontSettings::default())?;
+    let (width, height) = ((WIDTH * PNG_SCALE) as usize, (HEIGHT * PNG_SCALE) as usize);
+    let mut canvas = Canvas { width, height, pixels: vec![0; width * height * 4] };
+    let scale = |(x, y): (f32, f32)| (x * PNG_SCALE, y * PNG_SCALE);
+    for shape in layout(card) {
+        match shape {
+            Shape::Rect { x, y, width, height, color } => {
+                canvas.rect(x * PNG_SCALE, y * PNG_SCALE, width * PNG_SCALE, height * PNG_SCALE, color)
+            }
+            Shape::Line { from, to, width, color } => canvas.line(scale(from), scale(to), width * PNG_SCALE, color),
+            Shape::Text { x, y, size, text, color } => {
+                canvas.text(&font, x * PNG_SCALE, y * PNG_SCALE, size * PNG_SCALE, &text, color)
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(&canvas.pixels).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+// The format comes from the file extension.
+pub fn format_of(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    FORMATS.into_iter().find(|format| *format == extension)
+}
+
+pub fn save(card: &Card, path: &Path) -> Result<(), String> {
+    let bytes = match format_of(path) {
+        Some("svg") => svg(card).into_bytes(),
+        Some(_) => png(card)?,
+        None => return Err(format!("Unknown card format {:?}, use one of: {}.", path, FORMATS.join(", "))),
+    };
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    fs::write(path, bytes).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+// GUI cards go to cards/<timestamp>.<format>.
+// Numbered after the first one when several cards are saved in the same second.
+pub fn default_path(timestamp: u64, format: &str) -> String {
+    let mut path = format!("{}/{}.{}", CARDS_DIR, timestamp, format);
+    let mut n = 2;
+    while Path::new(&path).exists() {
+        path = format!("{}/{}-{}.{}", CARDS_DIR, timestamp, n, format);
+        n += 1;
+    }
+    path
+}
@@ -0,0 +1,201 @@
+use rand::Rng;
+use rand::prelude::IndexedRandom;
+
+
+pub const TAB_WIDTH: usize = 4;
+
+pub const LANGUAGES: [(&str, &str); 5] = [
+    ("rust", include_str!("../assets/code/rust.txt")),
+    ("python", include_str!("../assets/code/python.txt")),
+    ("javascript", include_str!("../assets/code/javascript.txt")),
+    ("go", include_str!("../assets/code/go.txt")),
+    ("c", include_str!("../assets/code/c.txt")),
+];
+
+const KEYWORDS: [(&str, &[&str]); 5] = [
+    ("rust", &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return",
+        "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use", "where", "while",
+    ]),
+    ("python", &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else",
+        "except", "False", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "None",
+        "nonlocal", "not", "or", "pass", "raise", "return", "True", "try", "while", "with", "yield",
+    ]),
+    ("javascript", &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "delete", "do",
+        "else", "export", "extends", "false", "finally", "for", "function", "if", "import", "in", "instanceof",
+        "let", "new", "null", "of", "return", "switch", "this", "throw", "true", "try", "typeof", "undefined",
+        "var", "while", "yield",
+    ]),
+    ("go", &[
+        "break", "case", "chan", "const", "continue", "default", "defer", "else", "fallthrough", "false", "for",
+        "func", "go", "goto", "if", "import", "interface", "map", "nil", "package", "range", "return", "select",
+        "struct", "switch", "true", "type", "var",
+    ]),
+    ("c", &[
+        "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern",
+        "float", "for", "if", "int", "long", "return", "short", "signed", "sizeof", "static", "struct",
+        "switch", "typedef", "union", "unsigned", "void", "while",
+    ]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    Number,
+    Bracket,
+}
+
+pub fn get_snippet(language: &str, rng: &mut impl Rng) -> String {
+    let source = LANGUAGES.iter()
+        .find(|(name, _)| *name == language)
+        .unwrap_or(&LANGUAGES[0])
+        .1;
+    let snippets: Vec<&str> = source.split("\n%%\n").collect();
+    let snippet = snippets.choose(rng).unwrap_or(&"");
+    normalize(snippet)
+}
+
+// Expands tabs and drops trailing whitespace so every character of the reference has to be typed.
+pub fn normalize(snippet: &str) -> String {
+    snippet
+        .lines()
+        .map(|line| line.replace('\t', &" ".repeat(TAB_WIDTH)).trim_end().to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
+}
+
+// Index of the first character after the indentation that starts at `pos`, if `pos` is at the start of a line.
+pub fn indentation_end(reference: &[char], pos: usize) -> usize {
+    if pos == 0 || reference.get(pos - 1) != Some(&'\n') {
+        return pos;
+    }
+    let mut end = pos;
+    while reference.get(end) == Some(&' ') {
+        end += 1;
+    }
+    end
+}
+
+// Number of spaces a Tab press covers when indentation is typed by hand.
+pub fn tab_spaces(reference: &[char], pos: usize) -> usize {
+    let line_start = reference[..pos].iter().rposition(|&c| c == '\n').map(|p| p + 1).unwrap_or(0);
+    let column = pos - line_start;
+    let mut count = 0;
+    while count < TAB_WIDTH - column % TAB_WIDTH && reference.get(pos + count) == Some(&' ') {
+        count += 1;
+    }
+    count
+}
+
+// Code keeps its own line breaks, long lines wrap every `width` characters. Every character is kept,
+// spaces and newlines included, so the indices of the lines match the reference.
+pub fn split_lines(reference: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let source_lines: Vec<&str> = reference.split('\n').collect();
+    let mut lines = Vec::new();
+    for (i, line) in source_lines.iter().enumerate() {
+        let mut chars: Vec<char> = line.chars().collect();
+        if i + 1 < source_lines.len() {
+            chars.push('\n');
+        }
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        for part in chars.chunks(width) {
+            lines.push(part.iter().collect());
+        }
+    }
+    lines
+}
+
+pub fn count_lines(reference: &str) -> usize {
+    reference.split('\n').count()
+}
+
+pub fn lines_done(reference: &str, pos: usize) -> usize {
+    reference.chars().take(pos).filter(|&c| c == '\n').count()
+}
+
+pub fn highlight(reference: &str, language: &str) -> Vec<Syntax> {
+    let keywords = KEYWORDS.iter()
+        .find(|(name, _)| *name == language)
+        .map(|(_, words)| *words)
+        .unwrap_or(&[]);
+    let line_comment = if language == "python" { "#" } else { "//" };
+    let chars: Vec<char> = reference.chars().collect();
+    let mut syntax = vec![Syntax::Plain; chars.len()];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let starts_with = |pattern: &str| pattern.chars().enumerate().all(|(j, p)| chars.get(i + j) == Some(&p));
+
+        if starts_with(line_comment) {
+            while i < chars.len() && chars[i] != '\n' {
+                syntax[i] = Syntax::Comment;
+                i += 1;
+            }
+        } else if language != "python" && starts_with("/*") {
+            let start = i;
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i = (i + 2).min(chars.len());
+            syntax[start..i].fill(Syntax::Comment);
+        } else if c == '"' || c == '`' || (c == '\'' && language != "rust") {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            syntax[start..i].fill(Syntax::String);
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.' || chars[i] == '_') {
+                syntax[i] = Syntax::Number;
+                i += 1;
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if keywords.contains(&word.as_str()) {
+                syntax[start..i].fill(Syntax::Keyword);
+            }
+        } else {
+            if "()[]{}<>".contains(c) {
+                syntax[i] = Syntax::Bracket;
+            }
+            i += 1;
+        }
+    }
+    syntax
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_lines_keeps_every_character() {
+        let reference = "fn main() {\n\n    let x = 1;\n}";
+        let lines = split_lines(reference, 8);
+        assert_eq!(lines, ["fn main(", ") {\n", "\n", "    let ", "x = 1;\n", "}"]);
+        assert_eq!(lines.concat(), reference);
+        assert_eq!(split_lines("", 8), [""]);
+    }
+}
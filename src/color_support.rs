@@ -0,0 +1,140 @@
+use std::env;
+use std::process::Command;
+use std::sync::Mutex;
+
+use crate::theme::Rgb;
+
+
+// How many colours the terminal can show, the TUI and CLI bring the theme down to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    NoColor,
+}
+
+// Detected on first use unless --color set it.
+static SUPPORT: Mutex<Option<ColorSupport>> = Mutex::new(None);
+
+// xterm's defaults for the 16 basic colours, terminals differ a little but not in hue.
+const ANSI16: [Rgb; 16] = [
+    Rgb(0, 0, 0),
+    Rgb(205, 0, 0),
+    Rgb(0, 205, 0),
+    Rgb(205, 205, 0),
+    Rgb(0, 0, 238),
+    Rgb(205, 0, 205),
+    Rgb(0, 205, 205),
+    Rgb(229, 229, 229),
+    Rgb(127, 127, 127),
+    Rgb(255, 0, 0),
+    Rgb(0, 255, 0),
+    Rgb(255, 255, 0),
+    Rgb(92, 92, 255),
+    Rgb(255, 0, 255),
+    Rgb(0, 255, 255),
+    Rgb(255, 255, 255),
+];
+// The steps of each channel in the 6x6x6 cube of the 256 colour palette.
+const CUBE_STEPS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+// A theme colour as the terminal can show it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TermColor {
+    Rgb(Rgb),
+    // 0-15 are the basic colours, 16-255 the cube and the greys
+    Indexed(u8),
+    // the terminal's own foreground or background
+    Default,
+}
+
+pub const CHOICES: [&str; 3] = ["auto", "always", "never"];
+
+// --color: auto detects the terminal, always uses full colour, never leaves the terminal's own colours.
+pub fn from_choice(name: &str) -> Result<Option<ColorSupport>, String> {
+    match name {
+        "auto" => Ok(None),
+        "always" => Ok(Some(ColorSupport::TrueColor)),
+        "never" => Ok(Some(ColorSupport::NoColor)),
+        _ => Err(format!("Unknown color choice '{}', use one of: {}.", name, CHOICES.join(", "))),
+    }
+}
+
+pub fn set(support: ColorSupport) {
+    *SUPPORT.lock().unwrap() = Some(support);
+}
+
+pub fn support() -> ColorSupport {
+    *SUPPORT.lock().unwrap().get_or_insert_with(detect)
+}
+
+// NO_COLOR, then COLORTERM, then the colour count terminfo gives for TERM.
+pub fn detect() -> ColorSupport {
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        return ColorSupport::NoColor;
+    }
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return ColorSupport::TrueColor;
+    }
+    let term = env::var("TERM").unwrap_or_default();
+    if term.is_empty() {
+        // the Windows console has no TERM but shows full colour
+        return if cfg!(windows) { ColorSupport::TrueColor } else { ColorSupport::Ansi16 };
+    }
+    if term == "dumb" {
+        return ColorSupport::NoColor;
+    }
+    match terminfo_colors(&term) {
+        Some(colors) if colors >= 1 << 24 => ColorSupport::TrueColor,
+        Some(colors) if colors >= 256 => ColorSupport::Ansi256,
+        Some(colors) if colors >= 8 => ColorSupport::Ansi16,
+        Some(_) => ColorSupport::NoColor,
+        // no terminfo to ask, go by the name
+        None if term.ends_with("-direct") => ColorSupport::TrueColor,
+        None if term.contains("256color") => ColorSupport::Ansi256,
+        None => ColorSupport::Ansi16,
+    }
+}
+
+fn terminfo_colors(term: &str) -> Option<i64> {
+    let output = Command::new("tput").args(["-T", term, "colors"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+fn distance(a: Rgb, b: Rgb) -> u32 {
+    let channel = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+fn nearest_ansi16(rgb: Rgb) -> u8 {
+    (0..ANSI16.len()).min_by_key(|&i| distance(rgb, ANSI16[i])).unwrap_or(0) as u8
+}
+
+// The closer of the nearest cube colour and the nearest grey, leaving out the basic 16 the user may have changed.
+fn nearest_ansi256(rgb: Rgb) -> u8 {
+    let step = |channel: u8| (0..CUBE_STEPS.len()).min_by_key(|&i| (CUBE_STEPS[i] as i32 - channel as i32).abs()).unwrap_or(0);
+    let (r, g, b) = (step(rgb.0), step(rgb.1), step(rgb.2));
+    let cube = Rgb(CUBE_STEPS[r], CUBE_STEPS[g], CUBE_STEPS[b]);
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let grey_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let grey_level = 8 + grey_index * 10;
+    if distance(rgb, Rgb(grey_level, grey_level, grey_level)) < distance(rgb, cube) {
+        232 + grey_index
+    } else {
+        16 + 36 * r as u8 + 6 * g as u8 + b as u8
+    }
+}
+
+pub fn reduce(rgb: Rgb) -> TermColor {
+    match support() {
+        ColorSupport::TrueColor => TermColor::Rgb(rgb),
+        ColorSupport::Ansi256 => TermColor::Indexed(nearest_ansi256(rgb)),
+        ColorSupport::Ansi16 => TermColor::Indexed(nearest_ansi16(rgb)),
+        ColorSupport::NoColor => TermColor::Default,
+    }
+}
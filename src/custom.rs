@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::book;
+
+
+pub const CUSTOM_DIR: &str = "custom_texts";
+pub const CHUNK_WORDS: usize = 50;
+pub const MAX_RECENT: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomText {
+    pub name: String,
+    pub text: String,
+    pub chunk: usize,
+}
+
+impl CustomText {
+    pub fn chunks(&self) -> Vec<String> {
+        split_chunks(&self.text, CHUNK_WORDS)
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks().len().max(1)
+    }
+
+    pub fn current_chunk(&self) -> String {
+        let chunks = self.chunks();
+        chunks.get(self.chunk % chunks.len().max(1)).cloned().unwrap_or_default()
+    }
+}
+
+fn recent_path() -> String {
+    format!("{}/recent.json", CUSTOM_DIR)
+}
+
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+pub fn split_chunks(text: &str, words: usize) -> Vec<String> {
+    let all_words: Vec<&str> = text.split_whitespace().collect();
+    all_words
+        .chunks(words.max(1))
+        .map(|chunk| chunk.join(" "))
+        .collect()
+}
+
+pub fn load_file(path: &Path) -> Result<CustomText, String> {
+    if !path.is_file() {
+        return Err(format!("Custom file does not exist or is not a file: {:?}", path));
+    }
+    let text = book::import(path)?;
+    if text.is_empty() {
+        return Err("Custom file is empty".to_string());
+    }
+    let name = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string());
+    Ok(CustomText { name, text, chunk: 0 })
+}
+
+pub fn from_pasted(content: &str) -> Result<CustomText, String> {
+    let text = normalize(content);
+    if text.is_empty() {
+        return Err("Pasted text is empty".to_string());
+    }
+    let preview: Vec<&str> = text.split_whitespace().take(4).collect();
+    let name = format!("pasted: {}...", preview.join(" "));
+    Ok(CustomText { name, text, chunk: 0 })
+}
+
+pub fn recent_texts() -> Vec<CustomText> {
+    match fs::read_to_string(recent_path()) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn save_recent(recent: &[CustomText]) {
+    fs::create_dir_all(CUSTOM_DIR).ok();
+    if let Ok(json) = serde_json::to_string_pretty(recent) {
+        fs::write(recent_path(), json).ok();
+    }
+}
+
+// Moves the text to the front of the recent list, keeping the saved progress if it was typed before.
+pub fn remember(custom: CustomText) -> CustomText {
+    let mut recent = recent_texts();
+    let mut custom = custom;
+    if let Some(idx) = recent.iter().position(|r| r.text == custom.text) {
+        custom.chunk = recent.remove(idx).chunk;
+    }
+    recent.insert(0, custom.clone());
+    recent.truncate(MAX_RECENT);
+    save_recent(&recent);
+    custom
+}
+
+pub fn save_progress(custom: &CustomText) {
+    let mut recent = recent_texts();
+    if let Some(entry) = recent.iter_mut().find(|r| r.text == custom.text) {
+        entry.chunk = custom.chunk;
+        save_recent(&recent);
+    }
+}
+
+// Marks the current chunk as done; after the last chunk it starts again from the beginning.
+pub fn advance(custom: &mut CustomText) {
+    custom.chunk = (custom.chunk + 1) % custom.chunk_count();
+    save_progress(custom);
+}
@@ -0,0 +1,73 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::history::{self, Record};
+use crate::utils;
+
+
+pub const MODE: &str = "daily";
+// Attempts after the first one of the day are kept in the history under this mode and don't count.
+pub const PRACTICE_MODE: &str = "daily practice";
+pub const WORDS: usize = 40;
+
+// Days since 1970-01-01 (UTC), the daily challenge changes at midnight UTC for everyone.
+pub fn today() -> u64 {
+    history::now() / 86400
+}
+
+pub fn seed(day: u64) -> u64 {
+    // spreads consecutive days over the whole seed range
+    day.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ 0x7479_7065_6D61_6E00
+}
+
+// Quotes on odd days, common words with punctuation on even days.
+pub fn reference(day: u64) -> String {
+    let mut rng = StdRng::seed_from_u64(seed(day));
+    if day % 2 == 1 {
+        utils::get_random_quote(&mut rng)
+    } else {
+        utils::get_reference(true, false, &utils::read_first_n_words(500), WORDS, &mut rng)
+    }
+}
+
+fn scored_days(records: &[Record]) -> Vec<u64> {
+    let mut days: Vec<u64> = records
+        .iter()
+        .filter(|record| record.mode == MODE)
+        .map(|record| record.timestamp / 86400)
+        .collect();
+    days.sort_unstable();
+    days.dedup();
+    days
+}
+
+// Mode to save a finished attempt under, only the first one of the day is scored.
+pub fn attempt_mode(records: &[Record], day: u64) -> &'static str {
+    if scored_days(records).contains(&day) { PRACTICE_MODE } else { MODE }
+}
+
+// Days in a row with a scored attempt. A streak that reached yesterday still counts until today is over.
+pub fn streak(records: &[Record], today: u64) -> usize {
+    let days = scored_days(records);
+    let mut day = if days.contains(&today) { today } else { today.saturating_sub(1) };
+    let mut streak = 0;
+    while days.contains(&day) {
+        streak += 1;
+        if day == 0 {
+            break;
+        }
+        day -= 1;
+    }
+    streak
+}
+
+// Shown next to the mode on the results screens, read after the attempt was saved.
+pub fn status(mode: &str) -> String {
+    let streak = streak(&history::load(), today());
+    let days = if streak == 1 { "day" } else { "days" };
+    if mode == PRACTICE_MODE {
+        format!("practice, streak {} {}", streak, days)
+    } else {
+        format!("streak {} {}", streak, days)
+    }
+}
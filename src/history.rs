@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::simulate::Keystroke;
+use crate::utils;
+
+
+pub const HISTORY_DIR: &str = "history";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Record {
+    pub timestamp: u64,
+    pub frontend: String,
+    pub mode: String,
+    pub punctuation: bool,
+    pub numbers: bool,
+    pub seed: Option<u64>,
+    pub seconds: f64,
+    pub wpm: f64,
+    pub raw_wpm: f64,
+    pub accuracy: f64,
+    pub sudden_death: bool,
+    pub min_accuracy: Option<f64>,
+    pub error_mode: String,
+    pub delete_word_on_error: bool,
+    pub failed: bool,
+    // the text and every key press, so the stats can be re-derived (see validate)
+    pub reference: String,
+    pub keys: Vec<Keystroke>,
+}
+
+fn history_path() -> String {
+    format!("{}/results.json", HISTORY_DIR)
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// YYYY-MM-DD (UTC) of a unix timestamp.
+pub fn date(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// The saved results for showing them, empty when there are none or the file can't be read.
+pub fn load() -> Vec<Record> {
+    read().unwrap_or_default()
+}
+
+// Tells a missing history apart from one that can't be read, which must not be written over.
+pub fn read() -> Result<Vec<Record>, String> {
+    match fs::read_to_string(history_path()) {
+        Ok(contents) => serde_json::from_str::<Vec<Record>>(&contents)
+            .map(|records| records.into_iter().map(|record| Record { mode: utils::Mode::canonical_name(&record.mode).to_string(), ..record }).collect())
+            .map_err(|e| format!("Failed to parse {}: {}", history_path(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", history_path(), e)),
+    }
+}
+
+pub fn save(records: &[Record]) -> Result<(), String> {
+    fs::create_dir_all(HISTORY_DIR).ok();
+    let json = serde_json::to_string_pretty(records).map_err(|e| e.to_string())?;
+    fs::write(history_path(), json).map_err(|e| format!("Failed to write {}: {}", history_path(), e))
+}
+
+// A history that can't be read is left as it is and the result isn't saved.
+// Returns the result as saved, with its timestamp, for the frontend to submit to the leaderboard.
+pub fn add(record: Record) -> Result<Record, String> {
+    let mut records = read().map_err(|err| format!("Result not saved: {}", err))?;
+    let record = Record {
+        timestamp: if record.timestamp == 0 { now() } else { record.timestamp },
+        ..record
+    };
+    records.push(record.clone());
+    save(&records)?;
+    Ok(record)
+}
@@ -0,0 +1,425 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::history::{self, Record};
+use crate::{utils, validate};
+
+
+pub const LEADERBOARD_DIR: &str = "leaderboard";
+pub const SIGNATURE_HEADER: &str = "X-Typeman-Signature";
+pub const DEFAULT_TOP: usize = 10;
+pub const DEFAULT_BIND: &str = "127.0.0.1";
+// The longest test the leaderboard takes, the request size cap is worked out from it.
+pub const MAX_SECONDS: f64 = 60.0 * 60.0;
+// Letters a second at MAX_WPM, the log may hold as many corrections again.
+const MAX_LETTERS_PER_SECOND: f64 = validate::MAX_WPM * 5.0 / 60.0;
+// The longest key as JSON, `{"at":3600000,"key":"ctrl+backspace"},`, and a reference letter at its worst, `\u001b`.
+const KEY_BYTES: f64 = 40.0;
+const LETTER_BYTES: f64 = 6.0;
+// The name, the stats and the rest of the batch past where the test ended.
+const OTHER_BYTES: f64 = 64.0 * 1024.0;
+const MAX_BODY: u64 = (MAX_SECONDS * MAX_LETTERS_PER_SECOND * (2.0 * KEY_BYTES + LETTER_BYTES) + OTHER_BYTES) as u64;
+
+// Submissions still being sent, waited for before the program exits.
+static PENDING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+// leaderboard/config.json, results are only submitted when `submit` is on.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub url: String,
+    pub name: String,
+    pub key: String,
+    pub submit: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Submission {
+    pub name: String,
+    pub record: Record,
+}
+
+fn config_path() -> String {
+    format!("{}/config.json", LEADERBOARD_DIR)
+}
+
+pub fn load_config() -> Option<Config> {
+    let contents = fs::read_to_string(config_path()).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+// HMAC-SHA256 of the request body with the team key, as lowercase hex.
+pub fn sign(key: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn verify(key: &str, body: &[u8], signature: &str) -> bool {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let Some(bytes) = (0..signature.len())
+        .step_by(2)
+        .map(|i| signature.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+    else {
+        return false;
+    };
+    mac.verify_slice(&bytes).is_ok()
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(Duration::from_secs(5)).build()
+}
+
+pub fn submit(config: &Config, record: &Record) -> Result<(), String> {
+    let submission = Submission { name: config.name.clone(), record: record.clone() };
+    let body = serde_json::to_vec(&submission).map_err(|e| e.to_string())?;
+    agent()
+        .post(&format!("{}/submit", config.url.trim_end_matches('/')))
+        .set("Content-Type", "application/json")
+        .set(SIGNATURE_HEADER, &sign(&config.key, &body))
+        .send_bytes(&body)
+        .map_err(|e| format!("Failed to submit to {}: {}", config.url, e))?;
+    Ok(())
+}
+
+fn too_long(record: &Record) -> String {
+    format!("the {:.0}s test is longer than the {:.0}s the leaderboard takes", record.seconds, MAX_SECONDS)
+}
+
+// What became of a submitted result, a message for the frontend to show.
+pub type Outcome = Result<String, String>;
+
+// Sends a saved result on its own thread, the outcome comes back on the returned channel.
+// Failed tests don't go on the leaderboard and results that fail validation are turned down right away.
+pub fn submit_in_background(config: &Config, record: &Record) -> Option<Receiver<Outcome>> {
+    if !config.submit || config.url.is_empty() || record.failed {
+        return None;
+    }
+    let (sender, receiver) = mpsc::channel();
+    if record.seconds > MAX_SECONDS {
+        sender.send(Err(format!("Not submitted to the leaderboard: {}", too_long(record)))).ok();
+        return Some(receiver);
+    }
+    let issues = validate::check(record);
+    if !issues.is_empty() {
+        sender.send(Err(format!("Not submitted to the leaderboard: {}", issues.join(", ")))).ok();
+        return Some(receiver);
+    }
+    let config = config.clone();
+    let record = record.clone();
+    let handle = thread::spawn(move || {
+        let outcome = submit(&config, &record).map(|()| format!("Submitted to the leaderboard at {}", config.url));
+        // nobody may be waiting any more
+        sender.send(outcome).ok();
+    });
+    PENDING.lock().unwrap().push(handle);
+    Some(receiver)
+}
+
+// A frontend's leaderboard config, read once when it starts, and the submission it's waiting on.
+#[derive(Debug, Default)]
+pub struct Submitter {
+    config: Option<Config>,
+    pending: Option<Receiver<Outcome>>,
+}
+
+impl Submitter {
+    pub fn new(config: Option<Config>) -> Self {
+        Submitter { config, pending: None }
+    }
+
+    // A new result replaces the one waited on before.
+    pub fn submit(&mut self, record: &Record) {
+        self.pending = self.config.as_ref().and_then(|config| submit_in_background(config, record));
+    }
+
+    // The outcome once it has arrived, for frontends that redraw.
+    pub fn outcome(&mut self) -> Option<Outcome> {
+        match self.pending.as_ref()?.try_recv() {
+            Ok(outcome) => {
+                self.pending = None;
+                Some(outcome)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.pending = None;
+                None
+            }
+        }
+    }
+
+    // Blocks until the outcome arrives, for the CLI that prints it and exits.
+    pub fn wait(&mut self) -> Option<Outcome> {
+        self.pending.take()?.recv().ok()
+    }
+}
+
+pub fn wait_for_submissions() {
+    let pending: Vec<JoinHandle<()>> = PENDING.lock().unwrap().drain(..).collect();
+    for handle in pending {
+        handle.join().ok();
+    }
+}
+
+pub fn fetch(url: &str, mode: Option<&str>, top: usize) -> Result<Vec<Submission>, String> {
+    let mut request = agent()
+        .get(&format!("{}/scores", url.trim_end_matches('/')))
+        .query("top", &top.to_string());
+    if let Some(mode) = mode {
+        request = request.query("mode", mode);
+    }
+    let response = request.call().map_err(|e| format!("Failed to reach {}: {}", url, e))?;
+    response.into_json().map_err(|e| format!("Invalid leaderboard from {}: {}", url, e))
+}
+
+// The best result of every player in each mode, fastest first.
+pub fn top_scores(submissions: &[Submission], mode: Option<&str>, top: usize) -> Vec<Submission> {
+    let mut best: BTreeMap<(&str, &str), &Submission> = BTreeMap::new();
+    for submission in submissions.iter().filter(|submission| mode.is_none_or(|mode| submission.record.mode == mode)) {
+        let key = (submission.record.mode.as_str(), submission.name.as_str());
+        if best.get(&key).is_none_or(|current| submission.record.wpm > current.record.wpm) {
+            best.insert(key, submission);
+        }
+    }
+    let mut by_mode: BTreeMap<&str, Vec<&Submission>> = BTreeMap::new();
+    for ((mode, _), submission) in best {
+        by_mode.entry(mode).or_default().push(submission);
+    }
+    by_mode
+        .into_values()
+        .flat_map(|mut entries| {
+            entries.sort_by(|a, b| b.record.wpm.total_cmp(&a.record.wpm));
+            entries.into_iter().take(top).cloned()
+        })
+        .collect()
+}
+
+pub fn render(submissions: &[Submission]) -> String {
+    let mut lines = Vec::new();
+    let mut mode = None;
+    let mut place = 0;
+    for submission in submissions {
+        if mode != Some(&submission.record.mode) {
+            if mode.is_some() {
+                lines.push(String::new());
+            }
+            mode = Some(&submission.record.mode);
+            place = 0;
+            lines.push(submission.record.mode.clone());
+        }
+        place += 1;
+        lines.push(format!(
+            "{:>3}. {:<20} {:>6.1} wpm {:>6.1}% {}",
+            place,
+            submission.name,
+            submission.record.wpm,
+            submission.record.accuracy,
+            history::date(submission.record.timestamp),
+        ));
+    }
+    if lines.is_empty() {
+        lines.push("No results on the leaderboard yet.".to_string());
+    }
+    lines.join("\n")
+}
+
+// Like the history, a missing file is an empty board and one that can't be read must not be written over.
+fn load_submissions(path: &Path) -> Result<Vec<Submission>, String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => serde_json::from_str::<Vec<Submission>>(&contents)
+            .map(|submissions| submissions.into_iter().map(canonical).collect())
+            .map_err(|e| format!("Failed to parse {:?}: {}", path, e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read {:?}: {}", path, e)),
+    }
+}
+
+// Results from older versions go on the same board as the new ones.
+fn canonical(submission: Submission) -> Submission {
+    let mode = utils::Mode::canonical_name(&submission.record.mode).to_string();
+    Submission { record: Record { mode, ..submission.record }, ..submission }
+}
+
+fn save_submissions(path: &Path, submissions: &[Submission]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    let json = serde_json::to_string_pretty(submissions).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+fn respond(request: tiny_http::Request, status: u16, body: String) {
+    let header = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = tiny_http::Response::from_string(body).with_status_code(status).with_header(header);
+    request.respond(response).ok();
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn handle_submit(mut request: tiny_http::Request, path: &Path, key: Option<&str>, require_log: bool) {
+    let mut body = Vec::new();
+    if request.as_reader().take(MAX_BODY + 1).read_to_end(&mut body).is_err() {
+        return respond(request, 400, error_body("Failed to read the request"));
+    }
+    if body.len() as u64 > MAX_BODY {
+        return respond(request, 413, error_body(&format!("The submission is over {} bytes", MAX_BODY)));
+    }
+    if let Some(key) = key {
+        let signature = request.headers().iter()
+            .find(|header| header.field.equiv(SIGNATURE_HEADER))
+            .map(|header| header.value.as_str().to_string())
+            .unwrap_or_default();
+        if !verify(key, &body, &signature) {
+            return respond(request, 401, error_body("Missing or wrong signature"));
+        }
+    }
+    let submission: Submission = match serde_json::from_slice(&body) {
+        Ok(submission) => canonical(submission),
+        Err(e) => return respond(request, 400, error_body(&format!("Invalid submission: {}", e))),
+    };
+    if submission.name.trim().is_empty() {
+        return respond(request, 400, error_body("The submission has no name"));
+    }
+    if submission.record.seconds > MAX_SECONDS {
+        return respond(request, 422, error_body(&format!("Rejected: {}", too_long(&submission.record))));
+    }
+    if require_log && submission.record.keys.is_empty() {
+        return respond(request, 422, error_body("The result has no keystroke log"));
+    }
+    let issues = validate::check(&submission.record);
+    if !issues.is_empty() {
+        return respond(request, 422, error_body(&format!("Rejected: {}", issues.join(", "))));
+    }
+    let mut submissions = match load_submissions(path) {
+        Ok(submissions) => submissions,
+        Err(err) => return respond(request, 500, error_body(&err)),
+    };
+    submissions.push(submission);
+    match save_submissions(path, &submissions) {
+        Ok(()) => respond(request, 201, "{}".to_string()),
+        Err(err) => respond(request, 500, error_body(&err)),
+    }
+}
+
+// A query string value: `+` is a space and `%XX` a byte of UTF-8, a stray `%` is kept as it is.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn handle_scores(request: tiny_http::Request, path: &Path) {
+    let query = request.url().split_once('?').map(|(_, query)| query.to_string()).unwrap_or_default();
+    let mut mode = None;
+    let mut top = DEFAULT_TOP;
+    for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        match name {
+            "mode" => mode = Some(utils::Mode::canonical_name(&percent_decode(value)).to_string()),
+            "top" => top = value.parse().unwrap_or(DEFAULT_TOP),
+            _ => {}
+        }
+    }
+    match load_submissions(path) {
+        Ok(submissions) => respond(request, 200, serde_json::to_string(&top_scores(&submissions, mode.as_deref(), top)).unwrap_or_default()),
+        Err(err) => respond(request, 500, error_body(&err)),
+    }
+}
+
+// POST /submit stores a signed Submission that passes validation, GET /scores?mode=&top= returns the best results per mode.
+// Listens on localhost unless `bind` says otherwise, a team server needs e.g. 0.0.0.0.
+pub fn serve(bind: &str, port: u16, path: &Path, key: Option<&str>, require_log: bool) -> Result<(), String> {
+    let server = tiny_http::Server::http((bind, port)).map_err(|e| format!("Failed to listen on {}:{}: {}", bind, port, e))?;
+    println!("Leaderboard listening on {}:{}, saving to {:?}", bind, port, path);
+    if key.is_none() {
+        println!("No --key given, unsigned submissions are accepted.");
+    }
+    for request in server.incoming_requests() {
+        let route = request.url().split('?').next().unwrap_or_default().to_string();
+        match (request.method(), route.as_str()) {
+            (tiny_http::Method::Post, "/submit") => handle_submit(request, path, key, require_log),
+            (tiny_http::Method::Get, "/scores") => handle_scores(request, path),
+            _ => respond(request, 404, error_body("Not found")),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::Keystroke;
+
+    #[test]
+    fn query_values_are_percent_decoded() {
+        assert_eq!(percent_decode("time"), "time");
+        assert_eq!(percent_decode("quote+test"), "quote test");
+        assert_eq!(percent_decode("quote%20test"), "quote test");
+        assert_eq!(percent_decode("a%2Bb%26c%3Dd"), "a+b&c=d");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn longest_test_fits_in_a_request() {
+        // every letter typed at the fastest speed allowed, half of the keys corrections
+        let letters = (MAX_SECONDS * MAX_LETTERS_PER_SECOND) as u64;
+        let keys = (0..letters * 2)
+            .map(|i| Keystroke { at: i * 1000 / (MAX_LETTERS_PER_SECOND as u64 * 2), key: if i % 2 == 0 { "ctrl+backspace".to_string() } else { "a".to_string() } })
+            .collect();
+        let record = Record {
+            seconds: MAX_SECONDS,
+            reference: "\u{1b}".repeat(letters as usize),
+            keys,
+            ..Default::default()
+        };
+        let submission = Submission { name: "a".repeat(100), record };
+        let body = serde_json::to_vec(&submission).unwrap();
+        assert!((body.len() as u64) < MAX_BODY, "{} of {}", body.len(), MAX_BODY);
+    }
+
+    #[test]
+    fn unreadable_submissions_are_an_error() {
+        let dir = std::env::temp_dir().join(format!("typeman-leaderboard-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("submissions.json");
+        assert_eq!(load_submissions(&path).unwrap().len(), 0);
+        fs::write(&path, "[{\"name\": \"ann\",").unwrap();
+        assert!(load_submissions(&path).is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,36 @@
+pub mod ui {
+    pub mod cli {
+        pub mod main;
+        pub mod output;
+    }
+    pub mod gui {
+        pub mod main;
+        pub mod results;
+        pub mod config;
+        pub mod practice;
+        pub mod custom;
+        pub mod hotseat;
+    }
+    pub mod tui {
+        pub mod app;
+        pub mod ui;
+        pub mod r#mod;
+        pub mod race;
+    }
+}
+pub mod practice;
+pub mod custom;
+pub mod book;
+pub mod code;
+pub mod history;
+pub mod card;
+pub mod simulate;
+pub mod daily;
+pub mod pace;
+pub mod theme;
+pub mod color_support;
+pub mod race;
+pub mod leaderboard;
+pub mod transfer;
+pub mod validate;
+pub mod utils;
@@ -1,31 +1,17 @@
-use clap::{Parser, ValueHint};
-use std::{path::PathBuf};
-use serde::Deserialize;
+use clap::{Parser, Subcommand, ValueHint};
+use std::path::{Path, PathBuf};
 
-use crate ::ui::cli::modes;
+use typeman::{book, card, custom, daily, history, leaderboard, pace, practice, race, simulate, theme, color_support, transfer, ui, utils, validate};
+use crate::ui::cli::output as cli_output;
+use crate::ui::tui::race as tui_race;
 
-mod ui {
-    pub mod cli {
-        pub mod main;
-        pub mod modes;
-    }
-    pub mod gui {
-        pub mod main;
-        pub mod results;
-        pub mod config;
-        pub mod practice;
-    }
-    pub mod tui {
-        pub mod app;
-        pub mod ui;
-        pub mod r#mod;
-    }
-}
-mod practice;
-mod utils;
+// The CLI modes read the parsed arguments, so they live with the binary rather than the library.
+#[path = "ui/cli/modes.rs"]
+mod modes;
 
 use crate::ui::tui::r#mod as tui_mod;
 use crate::ui::gui::main as gui;
+use crate::ui::gui::hotseat as gui_hotseat;
 
 
 #[derive(Parser)]
@@ -35,15 +21,36 @@ use crate::ui::gui::main as gui;
     version = "1.0",
     after_long_help = "Run examples:
 typeman --cli -c ./text.txt
+typeman --cli -b ./book.md
 typeman --cli -q
 typeman --cli -t=30 -n=500
 typeman --cli -w=50 -n=500
 typeman --cli -w=50 -n=500 -p -d
+typeman --cli -t=60 --sudden-death --min-accuracy=95
+typeman --cli -w=50 --errors=word
+typeman --cli -t=30 --output=json
+typeman --cli -t=30 --card=./result.png
+typeman --cli -w=50 --seed=42
+typeman --cli -t=60 --live=wpm,accuracy,progress
+typeman --cli --daily
+typeman export --format=csv --since=2024-01-01
+typeman import ./results.csv
+typeman simulate ./script.json --format=json
+typeman leaderboard --mode=time
+typeman serve-leaderboard --bind=0.0.0.0 --port=8080 --key=secret
+typeman race --host 7878
+typeman race --join 192.168.1.20:7878 --name alice
+typeman --tui --pace=best
+typeman --gui --theme=light
+typeman --tui --color=never
+typeman --tui --theme=deuteranopia --markers=underline
 typeman --gui
 typeman",
     long_about = "\n
 Run 'typeman --cli -c <path/to/your/file>' to test your typing on a specified text
+Run 'typeman --cli -b <path/to/your/document>' to type through a long document page by page (.txt, .md or .xhtml), resuming where you stopped
 Run 'typeman --cli -q' to test your typing on a random quote
+Run 'typeman --cli --daily' for today's daily challenge, the same text for everyone, only the first attempt of the day is scored
 Run 'typeman --cli -w=50 (-n=500 -p -d)' to test your typing on n most common English words, specify the -w for number of words (default is 50)
 Run 'typeman --cli (-t=30 -n=500 -p -d)' to test your typing on random words for t seconds; -t sets the time limit (default is 30 seconds)
 Run 'typeman (--tui)' to start the terminal-based interface
@@ -53,7 +60,21 @@ Optional:
   - Use -n to specify the number of words to type (default is 50, max is 500)
   - Use -t to set a time limit for the test (default is 30 seconds, use 0 for no limit)
   - Use -n to specify the number of top words to use (default is 500, max is 1000)
+  - Use --sudden-death to end the test on the first uncorrected error
+  - Use --min-accuracy=90 to fail the test when accuracy drops below the given percent
+  - Use --errors=letter to choose what a wrong key does: off, letter (stop on letter), word (stop on word) or no-backspace
+  - Use --delete-word-on-error to wipe the current word on every mistake
+  - Ctrl + Backspace or Alt + Backspace deletes the current word
+  - Use --output=json to print the full result record at the end of the test: json, csv or toml
+  - Use --card=result.png to save a shareable result card, .png or .svg
+  - Use --seed=42 to generate the same text every time, the seed of each test is saved with its result
+  - Use --live=wpm,accuracy,progress to show live stats while typing, any of the three
+  - Use --pace=80 in the TUI or GUI for a pace caret at 80 wpm, or --pace=avg / --pace=best for your average or best in the mode
+  - Use --theme=light for another colour theme: default, light, high-contrast, ocean, deuteranopia, tritanopia or one of your own in themes/NAME.json
+  - Use --color=never to keep the terminal's own colours in the TUI and CLI, or --color=always for full colour; auto (the default) detects 256 or 16 colour terminals
+  - Use --markers=underline or --markers=strike to mark wrong letters without relying on colour, corrected ones are bold; the deuteranopia and tritanopia themes are colour-blind safe
   - Use --gui for terminal-based interface
+  - Use --gui --hot-seat for two players taking turns on the same text in one window
   - Use --cli for terminal-based interface
 
 Default behavior for cli is to test typing on random words for 30 seconds with 500 most common English words.
@@ -61,12 +82,21 @@ Default mode is tui.
     "
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short = 'c', long = "custom", value_name = "FILE", value_hint = ValueHint::FilePath, conflicts_with_all = &["random_quote", "time_limit", "top_words", "word_number", "gui", "tui"])]
     custom_file: Option<PathBuf>,
 
+    #[arg(short = 'b', long = "book", value_name = "FILE", value_hint = ValueHint::FilePath, conflicts_with_all = &["custom_file", "random_quote", "time_limit", "top_words", "word_number", "gui", "tui"])]
+    book_file: Option<PathBuf>,
+
     #[arg(short = 'q', long = "quote", conflicts_with_all = &["custom_file", "time_limit", "top_words", "gui", "tui"])]
     random_quote: bool,
 
+    #[arg(long = "daily", conflicts_with_all = &["custom_file", "book_file", "random_quote", "time_limit", "word_number", "level", "seed", "gui", "tui"])]
+    daily: bool,
+
     #[arg(short = 'p', long = "punctuation", conflicts_with_all = &["custom_file", "random_quote", "gui", "tui"])]
     punctuation: bool,
 
@@ -85,9 +115,48 @@ struct Cli {
     #[arg(short = 'l', long = "level", conflicts_with_all = &["custom_file", "random_quote", "time_limit", "top_words"], conflicts_with_all = &["gui", "tui"])]
     level: Option<Option<usize>>,
 
+    #[arg(long = "sudden-death")]
+    sudden_death: bool,
+
+    #[arg(long = "min-accuracy", value_name = "PERCENT")]
+    min_accuracy: Option<f64>,
+
+    #[arg(long = "errors", value_name = "MODE")]
+    errors: Option<String>,
+
+    #[arg(long = "delete-word-on-error")]
+    delete_word_on_error: bool,
+
+    #[arg(long = "output", value_name = "FORMAT", conflicts_with_all = &["gui", "tui"])]
+    output: Option<String>,
+
+    #[arg(long = "seed", value_name = "SEED")]
+    seed: Option<u64>,
+
+    #[arg(long = "live", value_name = "INDICATORS")]
+    live: Option<String>,
+
+    #[arg(long = "pace", value_name = "PACE", conflicts_with = "cli")]
+    pace: Option<String>,
+
+    #[arg(long = "theme", value_name = "NAME")]
+    theme: Option<String>,
+
+    #[arg(long = "color", value_name = "WHEN")]
+    color: Option<String>,
+
+    #[arg(long = "markers", value_name = "MARKERS")]
+    markers: Option<String>,
+
+    #[arg(long = "card", value_name = "FILE", value_hint = ValueHint::FilePath, conflicts_with_all = &["gui", "tui"])]
+    card: Option<PathBuf>,
+
     #[arg(long = "gui", conflicts_with_all = &["custom_file", "random_quote", "time_limit", "top_words", "word_number", "level", "tui", "cli"])]
     gui: bool,
 
+    #[arg(long = "hot-seat", requires = "gui")]
+    hot_seat: bool,
+
     #[arg(long = "tui", conflicts_with_all = &["custom_file", "random_quote", "time_limit", "top_words", "word_number", "level", "gui", "cli"])]
     tui: bool,
 
@@ -95,36 +164,266 @@ struct Cli {
     cli: bool,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Quote {
-    author: String,
-    text: String,
+#[derive(Subcommand)]
+enum Command {
+    /// Print the results history as CSV or JSON
+    Export {
+        #[arg(long = "format", value_name = "FORMAT", default_value = "json")]
+        format: String,
+
+        #[arg(long = "since", value_name = "DATE")]
+        since: Option<String>,
+    },
+    /// Add monkeytype CSV exports or practice_results files to the history
+    Import {
+        #[arg(value_name = "FILE", value_hint = ValueHint::AnyPath)]
+        paths: Vec<PathBuf>,
+    },
+    /// Replay a JSON script of timed key presses without a terminal and print the result
+    Simulate {
+        #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+
+        #[arg(long = "format", value_name = "FORMAT", default_value = "json")]
+        format: String,
+    },
+    /// Check results for impossible stats and keystroke logs that don't add up, the history by default
+    Validate {
+        #[arg(value_name = "FILE", value_hint = ValueHint::FilePath)]
+        path: Option<PathBuf>,
+    },
+    /// Show the best results per mode from the leaderboard server in leaderboard/config.json
+    Leaderboard {
+        #[arg(long = "url", value_name = "URL")]
+        url: Option<String>,
+
+        #[arg(long = "mode", value_name = "MODE")]
+        mode: Option<String>,
+
+        #[arg(long = "top", value_name = "N", default_value_t = leaderboard::DEFAULT_TOP)]
+        top: usize,
+    },
+    /// Run a leaderboard server that stores submitted results in a local file
+    ServeLeaderboard {
+        #[arg(long = "bind", value_name = "ADDRESS", default_value = leaderboard::DEFAULT_BIND)]
+        bind: String,
+
+        #[arg(long = "port", value_name = "PORT", default_value_t = 8080)]
+        port: u16,
+
+        #[arg(long = "file", value_name = "FILE", value_hint = ValueHint::FilePath, default_value = "leaderboard/scores.json")]
+        file: PathBuf,
+
+        #[arg(long = "key", value_name = "KEY")]
+        key: Option<String>,
+
+        #[arg(long = "allow-no-log")]
+        allow_no_log: bool,
+    },
+    /// Race other players over TCP on the same text, one player hosts and the others join
+    Race {
+        #[arg(long = "host", value_name = "PORT", conflicts_with = "join", required_unless_present = "join")]
+        host: Option<u16>,
+
+        #[arg(long = "join", value_name = "ADDR")]
+        join: Option<String>,
+
+        #[arg(long = "name", value_name = "NAME")]
+        name: Option<String>,
+
+        #[arg(long = "words", value_name = "N", default_value_t = race::DEFAULT_WORDS, conflicts_with = "join")]
+        words: usize,
+
+        #[arg(long = "seed", value_name = "SEED", conflicts_with = "join")]
+        seed: Option<u64>,
+    },
 }
 
 
-pub fn gui_main() {
-    macroquad::Window::new("Hello World", async { gui::gui_main_async().await });
+pub fn gui_main(strictness: utils::Strictness, indicators: utils::Indicators, pace: pace::Pace, seed: Option<u64>, hot_seat: bool) {
+    if hot_seat {
+        macroquad::Window::new("Hello World", async move { gui_hotseat::hot_seat_async(strictness, seed).await });
+    } else {
+        macroquad::Window::new("Hello World", async move { gui::gui_main_async(strictness, indicators, pace, seed).await });
+    }
 }
 
-pub fn tui_main() {
-    if let Err(e) = tui_mod::main() {
+pub fn tui_main(strictness: utils::Strictness, indicators: utils::Indicators, pace: pace::Pace, seed: Option<u64>) {
+    if let Err(e) = tui_mod::main(strictness, indicators, pace, seed) {
         eprintln!("TUI error: {}", e);
         std::process::exit(1);
     }
 }
 
+fn run_command(command: &Command) -> Result<(), String> {
+    match command {
+        Command::Export { format, since } => {
+            let since = since.as_deref().map(transfer::parse_date).transpose()?;
+            println!("{}", transfer::export(format, since)?);
+        }
+        Command::Import { paths } => {
+            let paths: Vec<&Path> = if paths.is_empty() {
+                vec![Path::new(transfer::PRACTICE_DIR)]
+            } else {
+                paths.iter().map(PathBuf::as_path).collect()
+            };
+            let (added, skipped) = transfer::import(&paths)?;
+            println!("Imported {} results into {}, skipped {} already in the history.", added, history::HISTORY_DIR, skipped);
+        }
+        Command::Simulate { path, format } => {
+            let format = cli_output::Format::from_name(format)
+                .ok_or_else(|| format!("Unknown output format '{}', use one of: {}.", format, cli_output::Format::NAMES.join(", ")))?;
+            let report = simulate::run(&simulate::load(path)?)?;
+            println!("{}", cli_output::render(format, &report));
+        }
+        Command::Validate { path } => {
+            let records = match path {
+                Some(path) => {
+                    let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+                    serde_json::from_str(&contents).map_err(|e| format!("Invalid results {:?}: {}", path, e))?
+                }
+                None => history::load(),
+            };
+            println!("{}", validate::report(&records));
+        }
+        Command::Leaderboard { url, mode, top } => {
+            let url = match url {
+                Some(url) => url.clone(),
+                None => leaderboard::load_config()
+                    .map(|config| config.url)
+                    .filter(|url| !url.is_empty())
+                    .ok_or_else(|| format!("No leaderboard url, pass --url or set it in {}/config.json", leaderboard::LEADERBOARD_DIR))?,
+            };
+            let scores = leaderboard::fetch(&url, mode.as_deref(), *top)?;
+            println!("{}", leaderboard::render(&scores));
+        }
+        Command::ServeLeaderboard { bind, port, file, key, allow_no_log } => {
+            leaderboard::serve(bind, *port, file, key.as_deref(), !*allow_no_log)?;
+        }
+        Command::Race { host, join, name, words, seed } => {
+            if *words == 0 {
+                return Err("A race needs at least one word.".to_string());
+            }
+            // the host joins its own race through localhost
+            let (address, label, token) = match host {
+                Some(port) => {
+                    let hosted = race::host(*port, *words, *seed)?;
+                    (format!("127.0.0.1:{}", hosted.port), format!("port {}", hosted.port), Some(hosted.token))
+                }
+                None => {
+                    let address = join.clone().unwrap_or_default();
+                    (address.clone(), address, None)
+                }
+            };
+            let name = name.clone().unwrap_or_else(race::default_name);
+            let client = race::Client::connect(&address, &name, token.as_deref())?;
+            tui_race::main(client, label).map_err(|e| format!("TUI error: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
 fn main() {
     let args = Cli::parse();
 
-    if args.gui {
-        gui_main();
+    if let Some(command) = &args.command {
+        if let Err(err) = run_command(command) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.min_accuracy.is_some_and(|min| !(0.0..=100.0).contains(&min)) {
+        eprintln!("Minimum accuracy must be between 0 and 100.");
         return;
     }
-    if args.cli {
-        if let Some(path) = args.custom_file {
-            modes::custom_text(&path)
+    let error_mode = match args.errors.as_deref() {
+        Some(name) => match utils::ErrorMode::from_name(name) {
+            Some(error_mode) => error_mode,
+            None => {
+                eprintln!("Unknown error mode '{}', use one of: {}.", name, utils::ErrorMode::NAMES.join(", "));
+                return;
+            }
+        },
+        None => utils::ErrorMode::Off,
+    };
+    if let Some(name) = args.output.as_deref()
+        && cli_output::Format::from_name(name).is_none()
+    {
+        eprintln!("Unknown output format '{}', use one of: {}.", name, cli_output::Format::NAMES.join(", "));
+        return;
+    }
+    if let Some(path) = &args.card
+        && card::format_of(path).is_none()
+    {
+        eprintln!("Unknown card format {:?}, use one of: {}.", path, card::FORMATS.join(", "));
+        return;
+    }
+    let strictness = utils::Strictness {
+        sudden_death: args.sudden_death,
+        min_accuracy: args.min_accuracy,
+        error_mode,
+        delete_word_on_error: args.delete_word_on_error,
+    };
+    let indicators = match args.live.as_deref().map(utils::Indicators::from_names).transpose() {
+        Ok(indicators) => indicators.unwrap_or_default(),
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    let pace = match args.pace.as_deref().map(pace::Pace::from_name).transpose() {
+        Ok(pace) => pace.unwrap_or_default(),
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+    match args.color.as_deref().map(color_support::from_choice).transpose() {
+        Ok(support) => {
+            if let Some(support) = support.flatten() {
+                color_support::set(support);
+            }
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    }
+    match args.markers.as_deref().map(theme::Markers::from_name).transpose() {
+        Ok(markers) => theme::set_markers(markers.unwrap_or_default()),
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    }
+    if let Some(name) = &args.theme {
+        match theme::find(name) {
+            Ok((found, errors)) => {
+                for error in errors {
+                    eprintln!("{}", error);
+                }
+                theme::set(found);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        }
+    }
+
+    if args.gui {
+        gui_main(strictness, indicators, pace, args.seed, args.hot_seat);
+    } else if args.cli {
+        if let Some(path) = &args.custom_file {
+            modes::custom_text(&args, path)
+        } else if let Some(path) = &args.book_file {
+            modes::book(&args, path)
         } else if args.random_quote {
-            modes::quotes();
+            modes::quotes(&args);
+        } else if args.daily {
+            modes::daily(&args);
         } else if args.level.is_some() {
             modes::practice(&args);
         } else if args.word_number.is_some() && args.time_limit.is_none() {
@@ -132,7 +431,9 @@ fn main() {
         } else {
             modes::time_mode(&args);
         }
-        return;
+    } else {
+        tui_main(strictness, indicators, pace, args.seed);
     }
-    tui_main();    
+    // results are submitted in the background, let them finish
+    leaderboard::wait_for_submissions();
 }
@@ -0,0 +1,101 @@
+use crate::history::Record;
+
+
+pub const DEFAULT_WPM: f64 = 60.0;
+// The average pace is taken over this many of the latest results in the mode.
+const AVERAGE_OF: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PaceMode {
+    #[default]
+    Off,
+    Fixed,
+    Average,
+    Best,
+}
+
+// A second caret moving through the text at a target speed, set with --pace or in the config.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pace {
+    pub mode: PaceMode,
+    // the target of the fixed pace
+    pub wpm: f64,
+}
+
+impl Default for Pace {
+    fn default() -> Self {
+        Pace { mode: PaceMode::Off, wpm: DEFAULT_WPM }
+    }
+}
+
+impl Pace {
+    pub const NAMES: [&'static str; 4] = ["off", "avg", "best", "a number of wpm"];
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let mode = match name {
+            "off" => PaceMode::Off,
+            "avg" => PaceMode::Average,
+            "best" => PaceMode::Best,
+            _ => match name.parse::<f64>() {
+                Ok(wpm) if wpm > 0.0 && wpm.is_finite() => return Ok(Pace { mode: PaceMode::Fixed, wpm }),
+                _ => return Err(format!("Unknown pace '{}', use one of: {}.", name, Pace::NAMES.join(", "))),
+            },
+        };
+        Ok(Pace { mode, ..Pace::default() })
+    }
+
+    pub fn label(&self) -> String {
+        match self.mode {
+            PaceMode::Off => "pace off".to_string(),
+            PaceMode::Fixed => format!("pace {:.0}", self.wpm),
+            PaceMode::Average => "pace avg".to_string(),
+            PaceMode::Best => "pace best".to_string(),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let mode = match self.mode {
+            PaceMode::Off => PaceMode::Fixed,
+            PaceMode::Fixed => PaceMode::Average,
+            PaceMode::Average => PaceMode::Best,
+            PaceMode::Best => PaceMode::Off,
+        };
+        Pace { mode, ..self }
+    }
+
+    // The wpm to keep up with in `mode`, None when it's off or there are no results in the mode yet.
+    pub fn target(&self, records: &[Record], mode: &str) -> Option<f64> {
+        let results = records.iter().filter(|record| record.mode == mode && !record.failed && record.wpm > 0.0);
+        match self.mode {
+            PaceMode::Off => None,
+            PaceMode::Fixed => Some(self.wpm),
+            PaceMode::Average => {
+                let latest: Vec<f64> = results.rev().take(AVERAGE_OF).map(|record| record.wpm).collect();
+                (!latest.is_empty()).then(|| latest.iter().sum::<f64>() / latest.len() as f64)
+            }
+            PaceMode::Best => results.map(|record| record.wpm).reduce(f64::max),
+        }
+    }
+}
+
+// Characters of the text the pace caret has passed after `seconds`, a word of the text's average length at a time.
+// Every word counts with the space after it, the last one too, so the caret ends the text at the target wpm.
+pub fn position(wpm: f64, seconds: f64, reference: &str) -> usize {
+    let words = reference.split_whitespace().count().max(1);
+    let chars_per_word = (reference.chars().count() + 1) as f64 / words as f64;
+    (wpm / 60.0 * seconds * chars_per_word) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caret_ends_the_text_at_the_target_wpm() {
+        // three words at 60 wpm take three seconds
+        let reference = "the cat sat";
+        assert_eq!(position(60.0, 0.0, reference), 0);
+        assert_eq!(position(60.0, 1.0, reference), 4);
+        assert_eq!(position(60.0, 3.0, reference), reference.len() + 1);
+    }
+}
@@ -1,3 +1,4 @@
+use rand::Rng;
 use rand::prelude::IndexedRandom;
 use std::io::Write;
 use std::path::Path;
@@ -51,12 +52,12 @@ pub const TYPING_LEVELS: [(&str, &[char]); 32] = [
 ];
 
 
-pub fn create_words(chars: &[char], word_number: usize) -> String {
+pub fn create_words(chars: &[char], word_number: usize, rng: &mut impl Rng) -> String {
     let mut reference = String::new();
     for i in 0..word_number {
-        let word_length = rand::random::<u16>() % 5 + 2;
+        let word_length = rng.random::<u16>() % 5 + 2;
         let word: String = (0..word_length)
-            .map(|_| *chars.choose(&mut rand::rng()).unwrap())
+            .map(|_| *chars.choose(rng).unwrap())
             .collect();
         reference.push_str(&word);
         if i != word_number - 1 {
@@ -0,0 +1,454 @@
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::history::Record;
+use crate::simulate::{self, Keystroke, Script};
+use crate::{utils, validate};
+
+
+pub const DEFAULT_WORDS: usize = 30;
+// Seconds between the start message and the first key press that counts.
+pub const COUNTDOWN: u64 = 3;
+
+// One JSON object per line in both directions, `type` names the message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    // player -> host, the hosting player joins with the token the host was opened with
+    Join {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    Start,
+    Progress { position: usize, wpm: f64 },
+    Finish {
+        wpm: f64,
+        accuracy: f64,
+        #[serde(default)]
+        keys: Vec<Keystroke>,
+    },
+    // host -> players
+    Welcome {
+        id: usize,
+        #[serde(default)]
+        host: bool,
+    },
+    Lobby { players: Vec<String> },
+    Race { seed: u64, reference: String, countdown: u64 },
+    Players { players: Vec<Player> },
+    Results { players: Vec<Player> },
+    Error { message: String },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    pub id: usize,
+    pub name: String,
+    // characters of the reference typed so far
+    pub position: usize,
+    pub wpm: f64,
+    pub accuracy: f64,
+    // measured by the host from the end of the countdown
+    pub seconds: Option<f64>,
+    pub left: bool,
+    // why the host didn't believe the result, ranked after everyone who finished fairly
+    #[serde(default)]
+    pub flagged: Option<String>,
+}
+
+pub fn default_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "player".to_string())
+}
+
+fn send(mut stream: &TcpStream, message: &Message) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(message).map_err(std::io::Error::other)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+// Finished players by time, then everyone else by how far they got, flagged players and players who left last.
+pub fn rank(players: &[Player]) -> Vec<Player> {
+    let mut ranked = players.to_vec();
+    ranked.sort_by(|a, b| {
+        a.left.cmp(&b.left)
+            .then_with(|| a.flagged.is_some().cmp(&b.flagged.is_some()))
+            .then_with(|| match (a.seconds, b.seconds) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            })
+            .then_with(|| b.position.cmp(&a.position))
+    });
+    ranked
+}
+
+struct Host {
+    players: Vec<Player>,
+    // indexed by player id, each player's writer thread, None once the player left
+    outboxes: Vec<Option<mpsc::Sender<Message>>>,
+    // only the player who joined with it may start the race
+    token: String,
+    host_id: Option<usize>,
+    words: usize,
+    seed: Option<u64>,
+    reference: String,
+    go: Option<Instant>,
+    over: bool,
+}
+
+impl Host {
+    // Only queues the message, the writer threads send it once the lock is released.
+    fn broadcast(&self, message: &Message) {
+        for outbox in self.outboxes.iter().flatten() {
+            // a broken connection is noticed by its reader and the player leaves
+            let _ = outbox.send(message.clone());
+        }
+    }
+
+    fn broadcast_players(&self) {
+        if self.go.is_none() {
+            let players = self.players.iter().filter(|player| !player.left).map(|player| player.name.clone()).collect();
+            self.broadcast(&Message::Lobby { players });
+        } else {
+            self.broadcast(&Message::Players { players: self.players.clone() });
+        }
+    }
+
+    fn join(&mut self, name: String, token: Option<String>, outbox: mpsc::Sender<Message>) -> usize {
+        let id = self.players.len();
+        let host = self.host_id.is_none() && token.as_deref() == Some(self.token.as_str());
+        if host {
+            self.host_id = Some(id);
+        }
+        let _ = outbox.send(Message::Welcome { id, host });
+        self.players.push(Player { id, name, ..Default::default() });
+        self.outboxes.push(Some(outbox));
+        self.broadcast_players();
+        id
+    }
+
+    fn start(&mut self) {
+        if self.go.is_some() {
+            return;
+        }
+        let mut test_rng = utils::TestRng::new(self.seed);
+        let reference = utils::get_reference(false, false, &utils::read_first_n_words(500), self.words, test_rng.rng());
+        self.go = Some(Instant::now() + Duration::from_secs(COUNTDOWN));
+        self.broadcast(&Message::Race { seed: test_rng.seed, reference: reference.clone(), countdown: COUNTDOWN });
+        self.reference = reference;
+        self.broadcast_players();
+    }
+
+    fn progress(&mut self, id: usize, position: usize, wpm: f64) {
+        let player = &mut self.players[id];
+        if player.seconds.is_none() {
+            player.position = position;
+            player.wpm = wpm;
+        }
+        self.broadcast_players();
+    }
+
+    // The stats are worked out from the host's own clock and text and the player's keystroke log,
+    // what the player claims only decides whether the finish is flagged.
+    fn finish(&mut self, id: usize, wpm: f64, accuracy: f64, keys: Vec<Keystroke>) {
+        let Some(go) = self.go else {
+            return;
+        };
+        let player = &mut self.players[id];
+        if player.seconds.is_none() {
+            let seconds = go.elapsed().as_secs_f64();
+            let script = Script { reference: self.reference.clone(), keys: keys.clone(), ..Default::default() };
+            let replayed = simulate::replay(&script).map(|replay| {
+                let (wpm, raw_wpm) = if seconds > 0.0 { utils::calc_wpm(&self.reference, &replay.is_correct, seconds) } else { (0.0, 0.0) };
+                (wpm, raw_wpm, utils::calc_accuracy(&replay.is_correct))
+            });
+            let record = Record {
+                mode: "race".to_string(),
+                seconds,
+                wpm,
+                raw_wpm: replayed.as_ref().map_or(wpm, |&(_, raw_wpm, _)| raw_wpm),
+                accuracy,
+                reference: self.reference.clone(),
+                keys,
+                ..Default::default()
+            };
+            let mut issues = validate::check(&record);
+            match replayed {
+                Ok((wpm, _, accuracy)) => {
+                    player.wpm = wpm;
+                    player.accuracy = accuracy;
+                }
+                Err(err) => {
+                    issues.push(format!("the keystroke log can't be replayed: {}", err));
+                    player.wpm = 0.0;
+                    player.accuracy = 0.0;
+                }
+            }
+            player.seconds = Some(seconds);
+            player.flagged = (!issues.is_empty()).then(|| issues.join(", "));
+        }
+        self.broadcast_players();
+        self.check_over();
+    }
+
+    fn leave(&mut self, id: usize) {
+        self.players[id].left = true;
+        self.outboxes[id] = None;
+        self.broadcast_players();
+        self.check_over();
+    }
+
+    fn check_over(&mut self) {
+        let racing = self.players.iter().filter(|player| !player.left);
+        if self.go.is_some() && !self.over && racing.clone().all(|player| player.seconds.is_some()) {
+            self.over = true;
+            self.broadcast(&Message::Results { players: rank(&self.players) });
+        }
+    }
+}
+
+// Writes a player's messages in order, ends when the player leaves and the host drops the sender.
+fn write_messages(stream: TcpStream, messages: mpsc::Receiver<Message>) {
+    for message in messages {
+        if send(&stream, &message).is_err() {
+            return;
+        }
+    }
+}
+
+fn serve(host: Arc<Mutex<Host>>, stream: TcpStream) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    // before joining the connection writes its own errors, after that they go through its writer thread
+    let mut joined: Option<(usize, mpsc::Sender<Message>)> = None;
+    let reply = |joined: &Option<(usize, mpsc::Sender<Message>)>, message: Message| match joined {
+        Some((_, outbox)) => {
+            let _ = outbox.send(message);
+        }
+        None => {
+            let _ = send(&stream, &message);
+        }
+    };
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = match serde_json::from_str::<Message>(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                reply(&joined, Message::Error { message: format!("Invalid message: {}", e) });
+                continue;
+            }
+        };
+        let id = joined.as_ref().map(|(id, _)| *id);
+        let mut host = host.lock().unwrap();
+        match (message, id) {
+            (Message::Join { name, token }, None) => {
+                if host.go.is_some() {
+                    drop(host);
+                    reply(&joined, Message::Error { message: "The race has already started".to_string() });
+                    return;
+                }
+                let Ok(writer) = stream.try_clone() else {
+                    return;
+                };
+                let (outbox, messages) = mpsc::channel();
+                thread::spawn(move || write_messages(writer, messages));
+                joined = Some((host.join(name, token, outbox.clone()), outbox));
+            }
+            (Message::Start, Some(id)) if host.host_id == Some(id) => host.start(),
+            (Message::Start, Some(_)) => {
+                drop(host);
+                reply(&joined, Message::Error { message: "Only the host can start the race".to_string() });
+            }
+            (Message::Progress { position, wpm }, Some(id)) => host.progress(id, position, wpm),
+            (Message::Finish { wpm, accuracy, keys }, Some(id)) => host.finish(id, wpm, accuracy, keys),
+            (message, _) => {
+                drop(host);
+                reply(&joined, Message::Error { message: format!("Unexpected message: {:?}", message) });
+            }
+        }
+    }
+    if let Some((id, _)) = joined {
+        host.lock().unwrap().leave(id);
+    }
+}
+
+// An open race: the port it listens on and the token its host joins with.
+pub struct Hosted {
+    pub port: u16,
+    pub token: String,
+}
+
+// Listens on every interface in the background, the host then joins its own race with the token.
+// Port 0 takes any free port.
+pub fn host(port: u16, words: usize, seed: Option<u64>) -> Result<Hosted, String> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).map_err(|e| format!("Failed to listen on port {}: {}", port, e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    let token = format!("{:016x}", rand::random::<u64>());
+    let host = Arc::new(Mutex::new(Host {
+        players: Vec::new(),
+        outboxes: Vec::new(),
+        token: token.clone(),
+        host_id: None,
+        words,
+        seed,
+        reference: String::new(),
+        go: None,
+        over: false,
+    }));
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let host = Arc::clone(&host);
+            thread::spawn(move || serve(host, stream));
+        }
+    });
+    Ok(Hosted { port, token })
+}
+
+pub struct Client {
+    stream: TcpStream,
+    messages: mpsc::Receiver<Message>,
+}
+
+impl Client {
+    // The hosting player passes the token from `host`, everyone else None.
+    pub fn connect(address: &str, name: &str, token: Option<&str>) -> Result<Client, String> {
+        let stream = TcpStream::connect(address).map_err(|e| format!("Failed to connect to {}: {}", address, e))?;
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if let Ok(message) = serde_json::from_str(&line)
+                    && sender.send(message).is_err()
+                {
+                    return;
+                }
+            }
+            let _ = sender.send(Message::Error { message: "The host closed the connection".to_string() });
+        });
+        let client = Client { stream, messages };
+        client.send(&Message::Join { name: name.to_string(), token: token.map(str::to_string) })?;
+        Ok(client)
+    }
+
+    pub fn send(&self, message: &Message) -> Result<(), String> {
+        send(&self.stream, message).map_err(|e| format!("Failed to send to the host: {}", e))
+    }
+
+    // Everything received since the last call, without blocking.
+    pub fn poll(&self) -> Vec<Message> {
+        self.messages.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // Skips messages until `pick` finds the one the test is waiting for.
+    fn wait_for<T>(client: &Client, mut pick: impl FnMut(Message) -> Option<T>) -> T {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match client.messages.recv_timeout(left) {
+                Ok(message) => {
+                    if let Some(value) = pick(message) {
+                        return value;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        panic!("no such message from the host");
+    }
+
+    #[test]
+    fn race_on_localhost() {
+        let hosted = host(0, 3, Some(7)).unwrap();
+        let address = format!("127.0.0.1:{}", hosted.port);
+
+        // a player who connects before the host doesn't get to start the race
+        let bob = Client::connect(&address, "bob", None).unwrap();
+        assert_eq!(wait_for(&bob, |message| match message {
+            Message::Welcome { id, host } => Some((id, host)),
+            _ => None,
+        }), (0, false));
+        let alice = Client::connect(&address, "alice", Some(&hosted.token)).unwrap();
+        assert_eq!(wait_for(&alice, |message| match message {
+            Message::Welcome { id, host } => Some((id, host)),
+            _ => None,
+        }), (1, true));
+        wait_for(&alice, |message| match message {
+            Message::Lobby { players } if players == ["bob", "alice"] => Some(()),
+            _ => None,
+        });
+
+        bob.send(&Message::Start).unwrap();
+        let error = wait_for(&bob, |message| match message {
+            Message::Error { message } => Some(message),
+            _ => None,
+        });
+        assert_eq!(error, "Only the host can start the race");
+
+        alice.send(&Message::Start).unwrap();
+        let pick_race = |message| match message {
+            Message::Race { reference, countdown, .. } => Some((reference, countdown)),
+            _ => None,
+        };
+        let (reference, countdown) = wait_for(&alice, pick_race);
+        assert_eq!(wait_for(&bob, pick_race).0, reference);
+
+        // both type the text without a mistake, keys are timed from the end of the countdown
+        let mut at = 0;
+        let keys: Vec<Keystroke> = reference
+            .chars()
+            .enumerate()
+            .map(|(i, key)| {
+                at += 60 + (i as u64 * 37) % 50;
+                Keystroke { at, key: simulate::key_name(key) }
+            })
+            .collect();
+        thread::sleep(Duration::from_secs(countdown) + Duration::from_millis(at));
+
+        alice.send(&Message::Finish { wpm: 10.0, accuracy: 100.0, keys: keys.clone() }).unwrap();
+        wait_for(&bob, |message| match message {
+            Message::Players { players } if players[1].seconds.is_some() => Some(()),
+            _ => None,
+        });
+        // bob claims far more than the log shows
+        bob.send(&Message::Finish { wpm: 280.0, accuracy: 100.0, keys }).unwrap();
+
+        let ranked = wait_for(&alice, |message| match message {
+            Message::Results { players } => Some(players),
+            _ => None,
+        });
+        let names: Vec<&str> = ranked.iter().map(|player| player.name.as_str()).collect();
+        assert_eq!(names, ["alice", "bob"]);
+        let (alice, bob) = (&ranked[0], &ranked[1]);
+        assert_eq!(alice.flagged, None);
+        assert!(bob.flagged.as_deref().is_some_and(|issues| issues.contains("280.0 wpm claimed")), "{:?}", bob.flagged);
+        // the host's numbers, not the claimed ones, they come through JSON so only close to what's computed here
+        let seconds = alice.seconds.unwrap();
+        let is_correct = VecDeque::from(vec![2; reference.chars().count()]);
+        let wpm = utils::calc_wpm(&reference, &is_correct, seconds).0;
+        assert!((alice.wpm - wpm).abs() < 1e-6, "{} != {}", alice.wpm, wpm);
+        assert_eq!(alice.accuracy, 100.0);
+        assert!(bob.wpm < 280.0 && bob.wpm > 0.0, "{}", bob.wpm);
+    }
+}
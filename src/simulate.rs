@@ -0,0 +1,440 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ui::cli::main::{self as cli_main, TestStats};
+use crate::ui::cli::output::Report;
+use crate::utils;
+
+
+// One key press, `at` is in milliseconds from the start of the script.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Keystroke {
+    pub at: u64,
+    pub key: String,
+}
+
+// A session replayed without a terminal: the text, the settings and every key press.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Script {
+    pub reference: String,
+    pub mode: String,
+    pub time_limit: Option<u64>,
+    pub sudden_death: bool,
+    pub min_accuracy: Option<f64>,
+    pub errors: Option<String>,
+    pub delete_word_on_error: bool,
+    pub keys: Vec<Keystroke>,
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        Script {
+            reference: String::new(),
+            mode: utils::Mode::Words.name().to_string(),
+            time_limit: None,
+            sudden_death: false,
+            min_accuracy: None,
+            errors: None,
+            delete_word_on_error: false,
+            keys: Vec::new(),
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Result<Script, String> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("Invalid script {:?}: {}", path, e))
+}
+
+// The same bytes poll_input hands to the CLI, so only ASCII characters can be typed.
+fn key_byte(key: &str) -> Result<u8, String> {
+    match key {
+        "backspace" => Ok(8),
+        "ctrl+backspace" => Ok(0x17),
+        "esc" => Ok(0x1B),
+        "ctrl+c" => Ok(0x03),
+        "enter" => Ok(b'\n'),
+        "tab" => Ok(b'\t'),
+        "space" => Ok(b' '),
+        _ => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Ok(c as u8),
+                _ => Err(format!("Unknown key '{}'", key)),
+            }
+        }
+    }
+}
+
+// The name a key is logged under, the reverse of key_byte.
+pub fn key_name(key: char) -> String {
+    match key {
+        '\u{8}' | '\u{7F}' => "backspace".to_string(),
+        '\u{17}' => "ctrl+backspace".to_string(),
+        '\u{1B}' => "esc".to_string(),
+        '\u{3}' => "ctrl+c".to_string(),
+        '\n' | '\r' => "enter".to_string(),
+        '\t' => "tab".to_string(),
+        ' ' => "space".to_string(),
+        _ => key.to_string(),
+    }
+}
+
+// A script played through, what a report is made from and what a race host checks a finish with.
+pub struct Replay {
+    pub is_correct: VecDeque<i32>,
+    pub stats: TestStats,
+    pub seconds: f64,
+    pub failed: bool,
+    pub error_mode: utils::ErrorMode,
+    pub time_limit: Option<u64>,
+}
+
+pub fn run(script: &Script) -> Result<Report, String> {
+    let replay = replay(script)?;
+    Ok(Report {
+        // simulated results don't belong to a date, keeps the output reproducible
+        timestamp: 0,
+        mode: script.mode.clone(),
+        time_limit: replay.time_limit,
+        sudden_death: script.sudden_death,
+        min_accuracy: script.min_accuracy,
+        error_mode: replay.error_mode.name().to_string(),
+        delete_word_on_error: script.delete_word_on_error,
+        failed: replay.failed,
+        ..Report::new(&script.reference, &replay.is_correct, &replay.stats, replay.seconds)
+    })
+}
+
+// Feeds the keys through the CLI input handler with a simulated clock. Time tests run until the time limit
+// unless the text is finished first, other modes end with the text or the last key.
+pub fn replay(script: &Script) -> Result<Replay, String> {
+    let error_mode = match script.errors.as_deref() {
+        Some(name) => utils::ErrorMode::from_name(name)
+            .ok_or_else(|| format!("Unknown error mode '{}', use one of: {}.", name, utils::ErrorMode::NAMES.join(", ")))?,
+        None => utils::ErrorMode::Off,
+    };
+    let strictness = utils::Strictness {
+        sudden_death: script.sudden_death,
+        min_accuracy: script.min_accuracy,
+        error_mode,
+        delete_word_on_error: script.delete_word_on_error,
+    };
+    let practice = script.mode == "practice";
+    let time_limit = (script.mode == "time").then(|| script.time_limit.unwrap_or(30));
+
+    let reference = &script.reference;
+    let ref_chars: Vec<char> = reference.chars().collect();
+    if ref_chars.is_empty() {
+        return Err("The script has no reference text".to_string());
+    }
+    let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; ref_chars.len()]);
+    let mut stats = TestStats::default();
+    let mut user_input = String::new();
+    let mut position = 0;
+    let mut error_positions = vec![false; ref_chars.len()];
+    let mut extra_chars: HashMap<usize, String> = HashMap::new();
+    let mut words_done = 0;
+    let mut failed = None;
+    let mut finished = false;
+    let mut started_at: Option<u64> = None;
+    let mut last_at = 0;
+
+    for keystroke in &script.keys {
+        if keystroke.at < last_at {
+            return Err(format!("Key '{}' at {}ms comes before the previous key", keystroke.key, keystroke.at));
+        }
+        if let (Some(limit), Some(start)) = (time_limit, started_at)
+            && keystroke.at >= start + limit * 1000
+        {
+            break;
+        }
+        let byte = key_byte(&keystroke.key)?;
+        if matches!(byte, 0x03 | 0x04 | 0x1B) {
+            return Err("Test interrupted".to_string());
+        }
+        last_at = keystroke.at;
+
+        let typed = cli_main::handle_typing(
+            byte,
+            &mut user_input,
+            &ref_chars,
+            &mut position,
+            &mut error_positions,
+            &mut extra_chars,
+            &mut io::sink(),
+            &mut is_correct,
+            practice,
+            &mut words_done,
+            &mut stats.start_time,
+            strictness,
+        );
+        if stats.start_time.is_some() && started_at.is_none() {
+            started_at = Some(keystroke.at);
+        }
+        if let (Some(correct), Some(start)) = (typed, started_at) {
+            stats.record_at(((keystroke.at - start) / 1000) as usize, correct);
+        }
+
+        failed = strictness.check(&ref_chars, is_correct.iter(), position, utils::calc_accuracy(&is_correct));
+        if failed.is_some() || position >= ref_chars.len() {
+            finished = true;
+            break;
+        }
+    }
+
+    let Some(start) = started_at else {
+        return Err("No key in the script started the test".to_string());
+    };
+    let seconds = match time_limit {
+        Some(limit) if !finished => limit as f64,
+        _ => (last_at - start) as f64 / 1000.0,
+    };
+    stats.extra_chars += extra_chars.values().map(|extra| extra.chars().count()).sum::<usize>();
+
+    Ok(Replay { is_correct, stats, seconds, failed: failed.is_some(), error_mode, time_limit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use crate::ui::gui::main::{self as gui, TypingMode, TypingState};
+    use crate::ui::tui::app::App;
+    use crate::utils::ErrorMode;
+
+    const BACKSPACE: char = '\u{8}';
+    const CTRL_BACKSPACE: char = '\u{17}';
+    const DEL: char = '\u{7f}';
+
+    // Where a frontend's test is after the keys: the scored letters, the cursor and the extra letters.
+    #[derive(Debug)]
+    struct Outcome {
+        is_correct: Vec<i32>,
+        position: usize,
+        extra: usize,
+    }
+
+    impl Outcome {
+        // Marks past the cursor are left out, the TUI and GUI keep them to tell a corrected letter from a correct one.
+        fn typed(&self) -> (&[i32], usize, usize) {
+            (&self.is_correct[..self.position], self.position, self.extra)
+        }
+    }
+
+    fn strictness(error_mode: ErrorMode) -> utils::Strictness {
+        utils::Strictness { error_mode, ..Default::default() }
+    }
+
+    fn cli(reference: &str, keys: &str, error_mode: ErrorMode) -> Outcome {
+        let ref_chars: Vec<char> = reference.chars().collect();
+        let mut is_correct = VecDeque::from(vec![0; ref_chars.len()]);
+        let mut user_input = String::new();
+        let mut position = 0;
+        let mut error_positions = vec![false; ref_chars.len()];
+        let mut extra_chars = HashMap::new();
+        let mut words_done = 0;
+        let mut start_time = None;
+        for key in keys.chars() {
+            cli_main::handle_typing(
+                key as u8,
+                &mut user_input,
+                &ref_chars,
+                &mut position,
+                &mut error_positions,
+                &mut extra_chars,
+                &mut io::sink(),
+                &mut is_correct,
+                false,
+                &mut words_done,
+                &mut start_time,
+                strictness(error_mode),
+            );
+        }
+        let extra = extra_chars.values().map(|extra| extra.chars().count()).sum();
+        Outcome { is_correct: is_correct.into(), position, extra }
+    }
+
+    fn tui_app(reference: &str, keys: &str, error_mode: ErrorMode) -> App {
+        let mut app = App::new();
+        app.reference = reference.to_string();
+        app.is_correct = vec![0; reference.chars().count()];
+        app.strictness = strictness(error_mode);
+        for key in keys.chars() {
+            let event = match key {
+                BACKSPACE | DEL => KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE),
+                CTRL_BACKSPACE => KeyEvent::new(KeyCode::Backspace, KeyModifiers::CONTROL),
+                _ => KeyEvent::new(KeyCode::Char(key), KeyModifiers::NONE),
+            };
+            app.handle_key_event(event).unwrap();
+        }
+        app
+    }
+
+    fn tui(reference: &str, keys: &str, error_mode: ErrorMode) -> Outcome {
+        let app = tui_app(reference, keys, error_mode);
+        let extra = app.extra_chars.values().map(|extra| extra.chars().count()).sum();
+        Outcome { is_correct: app.is_correct, position: app.pos1, extra }
+    }
+
+    fn gui(reference: &str, keys: &str, error_mode: ErrorMode) -> Outcome {
+        let mut pressed_vec = Vec::new();
+        let mut is_correct = VecDeque::from(vec![0; reference.chars().count()]);
+        let mut position = 0;
+        let mut words_done = 0;
+        let mut errors_this_second = 0.0;
+        let mut error_positions = vec![false; reference.chars().count()];
+        let mut extra_chars = HashMap::new();
+        let mode = TypingMode { practice_mode: false, practice_menu: false, code_mode: false, auto_indent: false, strictness: strictness(error_mode) };
+        for key in keys.chars() {
+            let state = TypingState {
+                pressed_vec: &mut pressed_vec,
+                is_correct: &mut is_correct,
+                pos1: &mut position,
+                words_done: &mut words_done,
+                errors_this_second: &mut errors_this_second,
+                error_positions: &mut error_positions,
+                extra_chars: &mut extra_chars,
+            };
+            gui::apply_key(key, false, reference, state, mode);
+        }
+        let extra = extra_chars.values().map(|extra| extra.chars().count()).sum();
+        Outcome { is_correct: is_correct.into(), position, extra }
+    }
+
+    // Types the keys in all three frontends, they have to end up in the same place.
+    fn typed(reference: &str, keys: &str, error_mode: ErrorMode) -> Outcome {
+        let outcome = cli(reference, keys, error_mode);
+        assert_eq!(tui(reference, keys, error_mode).typed(), outcome.typed(), "TUI and CLI differ for {:?}", keys);
+        assert_eq!(gui(reference, keys, error_mode).typed(), outcome.typed(), "GUI and CLI differ for {:?}", keys);
+        outcome
+    }
+
+    #[test]
+    fn correct_letters() {
+        let outcome = typed("the cat sat", "the cat", ErrorMode::Off);
+        assert_eq!(outcome.typed().0, [2, 2, 2, 2, 2, 2, 2]);
+        assert_eq!(outcome.position, 7);
+    }
+
+    #[test]
+    fn errors_off_moves_on_and_backspace_corrects() {
+        let outcome = typed("the cat", "tha", ErrorMode::Off);
+        assert_eq!(outcome.typed().0, [2, 2, -1]);
+        assert_eq!(outcome.position, 3);
+
+        let outcome = typed("the cat", "tha\u{8}e", ErrorMode::Off);
+        assert_eq!(outcome.typed().0, [2, 2, 1]);
+        assert_eq!(outcome.position, 3);
+    }
+
+    #[test]
+    fn stop_on_letter_waits_for_the_right_letter() {
+        let outcome = typed("the cat", "tx", ErrorMode::StopOnLetter);
+        assert_eq!(outcome.typed().0, [2]);
+        assert_eq!(outcome.position, 1);
+        assert_eq!(outcome.is_correct[1], -1);
+
+        let outcome = typed("the cat", "txhe", ErrorMode::StopOnLetter);
+        assert_eq!(outcome.typed().0, [2, 1, 2]);
+        assert_eq!(outcome.position, 3);
+    }
+
+    #[test]
+    fn stop_on_word_refuses_the_space_after_an_error() {
+        let outcome = typed("the cat", "thx ", ErrorMode::StopOnWord);
+        assert_eq!(outcome.typed().0, [2, 2, -1]);
+        assert_eq!(outcome.position, 3);
+        assert_eq!(outcome.is_correct[3], -1);
+
+        let outcome = typed("the cat", "thx \u{8}e ", ErrorMode::StopOnWord);
+        assert_eq!(outcome.typed().0, [2, 2, 1, 1]);
+        assert_eq!(outcome.position, 4);
+    }
+
+    #[test]
+    fn del_without_a_modifier_is_a_backspace() {
+        let outcome = typed("the cat", "the ca\u{7f}", ErrorMode::Off);
+        assert_eq!(outcome.typed().0, [2, 2, 2, 2, 2]);
+        assert_eq!(outcome.position, 5);
+    }
+
+    #[test]
+    fn no_backspace_ignores_both_backspaces() {
+        let outcome = typed("the cat", "tx\u{8}\u{17}h", ErrorMode::NoBackspace);
+        assert_eq!(outcome.typed().0, [2, -1, -1]);
+        assert_eq!(outcome.position, 3);
+    }
+
+    #[test]
+    fn space_skips_the_rest_of_the_word() {
+        let outcome = typed("the cat sat", "th c", ErrorMode::Off);
+        assert_eq!(outcome.typed().0, [2, 2, 4, 2, 2]);
+        assert_eq!(outcome.position, 5);
+    }
+
+    #[test]
+    fn extra_letters_are_counted_on_the_space() {
+        let outcome = typed("the cat", "thexx c", ErrorMode::Off);
+        assert_eq!(outcome.typed().0, [2, 2, 2, 5, 2]);
+        assert_eq!(outcome.extra, 2);
+        assert_eq!(outcome.position, 5);
+    }
+
+    #[test]
+    fn ctrl_backspace_deletes_words() {
+        let outcome = typed("the cat sat", "the ca\u{17}", ErrorMode::Off);
+        assert_eq!(outcome.typed().0, [2, 2, 2, 2]);
+        assert_eq!(outcome.position, 4);
+
+        // at the start of a word it takes the previous one
+        let outcome = typed("the cat sat", "the \u{17}", ErrorMode::Off);
+        assert!(outcome.typed().0.is_empty());
+        assert_eq!(outcome.position, 0);
+
+        let outcome = typed("the cat", "thexx\u{17}", ErrorMode::Off);
+        assert!(outcome.typed().0.is_empty());
+        assert_eq!(outcome.extra, 0);
+        assert_eq!(outcome.position, 0);
+    }
+
+    #[test]
+    fn replay_scores_letters_not_bytes() {
+        let script = Script {
+            reference: "déjà vu".to_string(),
+            keys: "d".chars().map(|key| Keystroke { at: 0, key: key_name(key) }).collect(),
+            ..Default::default()
+        };
+        assert_eq!(replay(&script).unwrap().is_correct.len(), 7);
+    }
+
+    #[test]
+    fn scoring_matches_across_frontends() {
+        let reference = "the cat sat";
+        let keys = "thx cat sat";
+        let script = Script {
+            reference: reference.to_string(),
+            keys: keys.chars().enumerate().map(|(i, key)| Keystroke { at: i as u64 * 500, key: key_name(key) }).collect(),
+            ..Default::default()
+        };
+        let report = run(&script).unwrap();
+        assert_eq!(report.seconds, 5.0);
+        // two of three words right in 5 seconds
+        assert_eq!(report.wpm, 24.0);
+        assert_eq!(report.raw_wpm, 36.0);
+        assert_eq!(report.accuracy, 90.91);
+        let accuracy = 10.0 / 11.0 * 100.0;
+
+        let (wpm, raw_wpm, tui_accuracy) = tui_app(reference, keys, ErrorMode::Off).stats(report.seconds);
+        assert_eq!((wpm, raw_wpm, tui_accuracy), (report.wpm, report.raw_wpm, accuracy));
+
+        let outcome = gui(reference, keys, ErrorMode::Off);
+        let is_correct = VecDeque::from(outcome.is_correct);
+        assert_eq!(utils::calc_wpm(reference, &is_correct, report.seconds), (report.wpm, report.raw_wpm));
+        assert_eq!(utils::calc_accuracy(&is_correct), accuracy);
+    }
+}
@@ -0,0 +1,344 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::Mutex;
+
+
+pub const THEMES_DIR: &str = "themes";
+pub const DEFAULT_NAME: &str = "default";
+
+// The theme every frontend draws with, changed by --theme or the theme button.
+static CURRENT: Mutex<Option<Theme>> = Mutex::new(None);
+// Changed by --markers or the markers button.
+static MARKERS: Mutex<Markers> = Mutex::new(Markers::Off);
+
+// Written as "#rrggbb" in theme files.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Self, Self::Error> {
+        let digits = hex.trim_start_matches('#');
+        let channel = |i: usize| digits.get(i..i + 2).and_then(|channel| u8::from_str_radix(channel, 16).ok());
+        match (digits.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Rgb(r, g, b)),
+            _ => Err(format!("'{}' is not a #rrggbb colour", hex)),
+        }
+    }
+}
+
+impl From<Rgb> for String {
+    fn from(rgb: Rgb) -> Self {
+        format!("#{:02x}{:02x}{:02x}", rgb.0, rgb.1, rgb.2)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    pub background: Rgb,
+    pub border: Rgb,
+    // the cursor, selected buttons and results
+    pub main: Rgb,
+    pub dim_main: Rgb,
+    // text still to type and inactive labels
+    pub reference: Rgb,
+    // typed text and everything else written in the foreground
+    pub text: Rgb,
+    pub error: Rgb,
+    pub extra: Rgb,
+    pub missed: Rgb,
+    pub success: Rgb,
+    pub pace: Rgb,
+    // code highlighting, drawn instead of the reference colour on text still to type
+    pub keyword: Rgb,
+    pub string: Rgb,
+    pub comment: Rgb,
+    pub number: Rgb,
+    pub bracket: Rgb,
+}
+
+const DEFAULT: Colors = Colors {
+    background: Rgb(10, 10, 10),
+    border: Rgb(100, 60, 0),
+    main: Rgb(255, 155, 0),
+    dim_main: Rgb(150, 90, 0),
+    reference: Rgb(100, 100, 100),
+    text: Rgb(255, 255, 255),
+    error: Rgb(255, 0, 0),
+    extra: Rgb(160, 0, 0),
+    missed: Rgb(140, 70, 70),
+    success: Rgb(0, 255, 0),
+    pace: Rgb(70, 70, 160),
+    keyword: Rgb(150, 110, 60),
+    string: Rgb(100, 130, 90),
+    comment: Rgb(70, 70, 70),
+    number: Rgb(140, 100, 130),
+    bracket: Rgb(130, 130, 130),
+};
+
+const LIGHT: Colors = Colors {
+    background: Rgb(245, 242, 235),
+    border: Rgb(200, 160, 110),
+    main: Rgb(200, 110, 0),
+    dim_main: Rgb(235, 195, 145),
+    reference: Rgb(160, 160, 160),
+    text: Rgb(30, 30, 30),
+    error: Rgb(210, 0, 0),
+    extra: Rgb(150, 40, 40),
+    missed: Rgb(190, 120, 120),
+    success: Rgb(0, 150, 0),
+    pace: Rgb(150, 150, 230),
+    keyword: Rgb(190, 120, 40),
+    string: Rgb(80, 140, 70),
+    comment: Rgb(195, 195, 190),
+    number: Rgb(160, 90, 150),
+    bracket: Rgb(110, 110, 110),
+};
+
+const HIGH_CONTRAST: Colors = Colors {
+    background: Rgb(0, 0, 0),
+    border: Rgb(255, 255, 255),
+    main: Rgb(255, 255, 0),
+    dim_main: Rgb(0, 90, 255),
+    reference: Rgb(170, 170, 170),
+    text: Rgb(255, 255, 255),
+    error: Rgb(255, 60, 60),
+    extra: Rgb(255, 0, 255),
+    missed: Rgb(255, 140, 0),
+    success: Rgb(0, 255, 0),
+    pace: Rgb(0, 200, 255),
+    keyword: Rgb(230, 200, 0),
+    string: Rgb(0, 220, 120),
+    comment: Rgb(140, 140, 140),
+    number: Rgb(255, 120, 255),
+    bracket: Rgb(230, 230, 230),
+};
+
+const OCEAN: Colors = Colors {
+    background: Rgb(15, 25, 35),
+    border: Rgb(40, 90, 120),
+    main: Rgb(90, 200, 230),
+    dim_main: Rgb(40, 110, 140),
+    reference: Rgb(90, 110, 125),
+    text: Rgb(230, 240, 245),
+    error: Rgb(240, 90, 90),
+    extra: Rgb(180, 60, 60),
+    missed: Rgb(150, 100, 110),
+    success: Rgb(120, 220, 140),
+    pace: Rgb(200, 160, 255),
+    keyword: Rgb(90, 160, 190),
+    string: Rgb(120, 180, 130),
+    comment: Rgb(60, 80, 95),
+    number: Rgb(170, 140, 210),
+    bracket: Rgb(130, 150, 165),
+};
+
+// Okabe-Ito colours: blue and orange stay apart for red-green colour blindness (deuteranopia and protanopia).
+const DEUTERANOPIA: Colors = Colors {
+    background: Rgb(10, 10, 10),
+    border: Rgb(0, 80, 130),
+    main: Rgb(86, 180, 233),
+    dim_main: Rgb(0, 114, 178),
+    reference: Rgb(100, 100, 100),
+    text: Rgb(255, 255, 255),
+    error: Rgb(230, 159, 0),
+    extra: Rgb(213, 94, 0),
+    missed: Rgb(150, 110, 50),
+    success: Rgb(86, 180, 233),
+    pace: Rgb(120, 90, 110),
+    keyword: Rgb(0, 114, 178),
+    string: Rgb(0, 130, 100),
+    comment: Rgb(70, 70, 70),
+    number: Rgb(170, 100, 140),
+    bracket: Rgb(130, 130, 130),
+};
+
+// Red and teal stay apart for blue-yellow colour blindness (tritanopia).
+const TRITANOPIA: Colors = Colors {
+    background: Rgb(10, 10, 10),
+    border: Rgb(0, 100, 100),
+    main: Rgb(0, 200, 200),
+    dim_main: Rgb(0, 120, 120),
+    reference: Rgb(100, 100, 100),
+    text: Rgb(255, 255, 255),
+    error: Rgb(255, 60, 90),
+    extra: Rgb(180, 30, 60),
+    missed: Rgb(150, 80, 90),
+    success: Rgb(0, 200, 200),
+    pace: Rgb(90, 90, 90),
+    keyword: Rgb(0, 150, 150),
+    string: Rgb(150, 120, 120),
+    comment: Rgb(70, 70, 70),
+    number: Rgb(200, 200, 200),
+    bracket: Rgb(130, 130, 130),
+};
+
+impl Default for Colors {
+    fn default() -> Self {
+        DEFAULT
+    }
+}
+
+// A theme file only needs the colours it changes, the rest come from the default theme.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Theme {
+    #[serde(default)]
+    pub name: String,
+    #[serde(flatten)]
+    pub colors: Colors,
+}
+
+fn builtin() -> Vec<Theme> {
+    [(DEFAULT_NAME, DEFAULT), ("light", LIGHT), ("high-contrast", HIGH_CONTRAST), ("ocean", OCEAN), ("deuteranopia", DEUTERANOPIA), ("tritanopia", TRITANOPIA)]
+        .into_iter()
+        .map(|(name, colors)| Theme { name: name.to_string(), colors })
+        .collect()
+}
+
+// themes/*.json, named after the file unless they set a name, and why the files that were skipped couldn't be used.
+fn user_themes() -> (Vec<Theme>, Vec<String>) {
+    let Ok(entries) = fs::read_dir(THEMES_DIR) else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut paths: Vec<_> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    let mut themes = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        let parsed = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| serde_json::from_str::<Theme>(&contents).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(mut theme) => {
+                if theme.name.is_empty() {
+                    theme.name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                }
+                themes.push(theme);
+            }
+            Err(e) => errors.push(format!("Invalid theme {:?}: {}", path, e)),
+        }
+    }
+    (themes, errors)
+}
+
+// Built-in themes first, a user theme with the same name replaces the built-in one. The errors are the theme
+// files that were skipped, for the frontend to show.
+pub fn all() -> (Vec<Theme>, Vec<String>) {
+    let mut themes = builtin();
+    let (user, errors) = user_themes();
+    for theme in user {
+        match themes.iter_mut().find(|existing| existing.name == theme.name) {
+            Some(existing) => *existing = theme,
+            None => themes.push(theme),
+        }
+    }
+    (themes, errors)
+}
+
+// The theme and the theme files that were skipped, a broken file is named in the error when the theme isn't found.
+pub fn find(name: &str) -> Result<(Theme, Vec<String>), String> {
+    let (themes, errors) = all();
+    match themes.iter().find(|theme| theme.name == name) {
+        Some(theme) => Ok((theme.clone(), errors)),
+        None => {
+            let names: Vec<&str> = themes.iter().map(|theme| theme.name.as_str()).collect();
+            let message = format!("Unknown theme '{}', use one of: {}.", name, names.join(", "));
+            Err([vec![message], errors].concat().join("\n"))
+        }
+    }
+}
+
+pub fn set(theme: Theme) {
+    *CURRENT.lock().unwrap() = Some(theme);
+}
+
+pub fn colors() -> Colors {
+    CURRENT.lock().unwrap().as_ref().map_or(DEFAULT, |theme| theme.colors)
+}
+
+pub fn name() -> String {
+    CURRENT.lock().unwrap().as_ref().map_or(DEFAULT_NAME.to_string(), |theme| theme.name.clone())
+}
+
+// Switches to the theme after the current one, for the theme button. Returns the theme files that were skipped.
+pub fn next() -> Vec<String> {
+    let (themes, errors) = all();
+    let current = name();
+    let index = themes.iter().position(|theme| theme.name == current).map_or(0, |i| (i + 1) % themes.len());
+    set(themes[index].clone());
+    errors
+}
+
+// What a typed letter is marked with besides its colour.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mark {
+    Bold,
+    Underline,
+    Strikethrough,
+}
+
+// Marks that tell wrong and corrected letters apart without their colours: errors underlined or struck through, corrections bold.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Markers {
+    #[default]
+    Off,
+    Underline,
+    Strikethrough,
+}
+
+impl Markers {
+    pub const NAMES: [&'static str; 3] = ["off", "underline", "strike"];
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "off" => Ok(Markers::Off),
+            "underline" => Ok(Markers::Underline),
+            "strike" => Ok(Markers::Strikethrough),
+            _ => Err(format!("Unknown markers '{}', use one of: {}.", name, Markers::NAMES.join(", "))),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Markers::Off => "markers off",
+            Markers::Underline => "underline",
+            Markers::Strikethrough => "strike",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Markers::Off => Markers::Underline,
+            Markers::Underline => Markers::Strikethrough,
+            Markers::Strikethrough => Markers::Off,
+        }
+    }
+
+    // For wrong, extra and missed letters.
+    pub fn error(self) -> Option<Mark> {
+        match self {
+            Markers::Off => None,
+            Markers::Underline => Some(Mark::Underline),
+            Markers::Strikethrough => Some(Mark::Strikethrough),
+        }
+    }
+
+    pub fn corrected(self) -> Option<Mark> {
+        (self != Markers::Off).then_some(Mark::Bold)
+    }
+}
+
+pub fn markers() -> Markers {
+    *MARKERS.lock().unwrap()
+}
+
+pub fn set_markers(markers: Markers) {
+    *MARKERS.lock().unwrap() = markers;
+}
@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::Path;
+
+use crate::history::{self, Record};
+use crate::ui::cli::output;
+
+
+pub const PRACTICE_DIR: &str = "practice_results";
+pub const FORMATS: [&str; 2] = ["csv", "json"];
+const CSV_SKIPPED: &str = "keys";
+
+// YYYY-MM-DD as a unix timestamp at midnight UTC.
+pub fn parse_date(date: &str) -> Result<u64, String> {
+    let error = || format!("Invalid date '{}', expected YYYY-MM-DD", date);
+    let parts: Vec<&str> = date.trim().split('-').collect();
+    let [year, month, day] = parts.as_slice() else {
+        return Err(error());
+    };
+    let year: i64 = year.parse().map_err(|_| error())?;
+    let month: i64 = month.parse().map_err(|_| error())?;
+    let day: i64 = day.parse().map_err(|_| error())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return Err(error());
+    }
+    Ok((history::days_from_civil(year, month, day) * 86400) as u64)
+}
+
+pub fn export(format: &str, since: Option<u64>) -> Result<String, String> {
+    let records: Vec<Record> = history::load()
+        .into_iter()
+        .filter(|record| since.is_none_or(|since| record.timestamp >= since))
+        .collect();
+    match format {
+        "json" => serde_json::to_string_pretty(&records).map_err(|e| e.to_string()),
+        "csv" => {
+            // the keystroke log doesn't fit in a cell, it's only in the JSON export
+            let header = serde_json::to_value(Record::default())
+                .ok()
+                .and_then(|value| value.as_object().map(|fields| fields.keys().filter(|name| *name != CSV_SKIPPED).cloned().collect::<Vec<_>>()))
+                .unwrap_or_default();
+            let mut lines = vec![header.join(",")];
+            for record in &records {
+                if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(record) {
+                    lines.push(fields.iter().filter(|(name, _)| *name != CSV_SKIPPED).map(|(_, value)| output::csv_value(value)).collect::<Vec<_>>().join(","));
+                }
+            }
+            Ok(lines.join("\n"))
+        }
+        _ => Err(format!("Unknown export format '{}', use one of: {}.", format, FORMATS.join(", "))),
+    }
+}
+
+// Splits one CSV line, quoted fields may contain commas and doubled quotes.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+// monkeytype's "Export CSV" from the account page, one test per line.
+fn import_monkeytype(contents: &str) -> Result<Vec<Record>, String> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = split_csv_line(lines.next().unwrap_or_default());
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+    let (Some(wpm), Some(acc), Some(timestamp)) = (column("wpm"), column("acc"), column("timestamp")) else {
+        return Err("Not a monkeytype CSV export (missing wpm, acc or timestamp column)".to_string());
+    };
+    let raw_wpm = column("rawWpm");
+    let mode = column("mode");
+    let duration = column("testDuration");
+    let punctuation = column("punctuation");
+    let numbers = column("numbers");
+
+    let mut records = Vec::new();
+    for line in lines {
+        let fields = split_csv_line(line);
+        let get = |index: Option<usize>| index.and_then(|i| fields.get(i)).map(|f| f.trim()).unwrap_or_default();
+        let number = |index: Option<usize>| get(index).parse::<f64>().unwrap_or(0.0);
+        records.push(Record {
+            // monkeytype stores milliseconds
+            timestamp: (number(Some(timestamp)) / 1000.0) as u64,
+            frontend: "monkeytype".to_string(),
+            // monkeytype's mode names are the same as ours
+            mode: get(mode).to_string(),
+            punctuation: get(punctuation) == "true",
+            numbers: get(numbers) == "true",
+            seconds: number(duration),
+            wpm: number(Some(wpm)),
+            raw_wpm: number(raw_wpm),
+            accuracy: number(Some(acc)),
+            ..Default::default()
+        });
+    }
+    Ok(records)
+}
+
+// practice_results/level_N.txt, blocks of "Time/Accuracy/WPM" separated by "---".
+// The files have no dates, every result gets the time the file was last written.
+fn import_practice(path: &Path, contents: &str) -> Vec<Record> {
+    let timestamp = fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_else(history::now);
+    let value = |block: &str, key: &str| {
+        block.lines()
+            .find_map(|line| line.strip_prefix(key))
+            .map(|v| v.trim().trim_end_matches(['s', '%']).parse::<f64>().unwrap_or(0.0))
+            .unwrap_or(0.0)
+    };
+    contents
+        .split("---")
+        .filter(|block| block.contains("WPM:"))
+        .map(|block| {
+            let wpm = value(block, "WPM:");
+            Record {
+                timestamp,
+                frontend: "practice".to_string(),
+                mode: "practice".to_string(),
+                seconds: value(block, "Time:"),
+                wpm,
+                raw_wpm: wpm,
+                accuracy: value(block, "Accuracy:"),
+                error_mode: "letter".to_string(),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+fn read_records(path: &Path) -> Result<Vec<Record>, String> {
+    if path.is_dir() {
+        let mut records = Vec::new();
+        let mut entries: Vec<_> = fs::read_dir(path).map_err(|e| e.to_string())?.flatten().map(|e| e.path()).collect();
+        entries.sort();
+        for entry in entries.iter().filter(|entry| entry.is_file()) {
+            records.extend(read_records(entry)?);
+        }
+        return Ok(records);
+    }
+    let contents = fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    if contents.trim_start().starts_with("Time:") {
+        Ok(import_practice(path, &contents))
+    } else {
+        import_monkeytype(&contents).map_err(|e| format!("{:?}: {}", path, e))
+    }
+}
+
+// Results already in the history are skipped, so importing the same file twice is harmless.
+pub fn import(paths: &[&Path]) -> Result<(usize, usize), String> {
+    let mut records = history::read()?;
+    let mut added = 0;
+    let mut skipped = 0;
+    for path in paths {
+        for record in read_records(path)? {
+            let duplicate = records.iter().any(|r| {
+                r.timestamp == record.timestamp
+                    && r.frontend == record.frontend
+                    && r.mode == record.mode
+                    && r.wpm == record.wpm
+                    && r.accuracy == record.accuracy
+                    && r.seconds == record.seconds
+            });
+            if duplicate {
+                skipped += 1;
+            } else {
+                records.push(record);
+                added += 1;
+            }
+        }
+    }
+    records.sort_by_key(|record| record.timestamp);
+    history::save(&records)?;
+    Ok((added, skipped))
+}
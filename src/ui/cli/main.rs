@@ -6,11 +6,37 @@ use crossterm::{
 use std::io::{stdout, Write};
 use std::time::Instant;
 use crossterm::event::{self, Event, KeyCode, KeyEvent};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use crate::utils;
 use crate::practice;
+use crate::simulate::{self, Keystroke};
+use crate::theme::{self, Mark, Rgb};
+use crate::color_support::{self, TermColor};
 
 
+// The terminal keeps its own background, only the text takes the theme's colours.
+fn theme_color(rgb: Rgb) -> Color {
+    match color_support::reduce(rgb) {
+        TermColor::Rgb(rgb) => Color::Rgb { r: rgb.0, g: rgb.1, b: rgb.2 },
+        TermColor::Indexed(index) => Color::AnsiValue(index),
+        TermColor::Default => Color::Reset,
+    }
+}
+
+// Prints a typed letter in its colour, with the marker around it when markers are on.
+fn print_typed(stdout: &mut impl Write, color: Rgb, text: impl std::fmt::Display, mark: Option<Mark>) {
+    let (on, off) = match mark {
+        Some(Mark::Bold) => (Attribute::Bold, Attribute::NormalIntensity),
+        Some(Mark::Underline) => (Attribute::Underlined, Attribute::NoUnderline),
+        Some(Mark::Strikethrough) => (Attribute::CrossedOut, Attribute::NotCrossedOut),
+        None => {
+            queue!(stdout, SetForegroundColor(theme_color(color)), Print(text), SetForegroundColor(Color::Reset)).unwrap();
+            return;
+        }
+    };
+    queue!(stdout, SetAttribute(on), SetForegroundColor(theme_color(color)), Print(text), SetForegroundColor(Color::Reset), SetAttribute(off)).unwrap();
+}
+
 struct RawModeGuard;
 
 impl RawModeGuard {
@@ -26,6 +52,47 @@ impl Drop for RawModeGuard {
     }
 }
 
+// Kept across the batches of a time test.
+#[derive(Debug, Default)]
+pub struct TestStats {
+    pub start_time: Option<Instant>,
+    pub chars_per_second: Vec<usize>,
+    pub errors_per_second: Vec<usize>,
+    pub extra_chars: usize,
+    pub keys: Vec<Keystroke>,
+    // words finished in the earlier batches of a time test, for the live wpm
+    pub words_done: usize,
+}
+
+impl TestStats {
+    // Every key after the test started, kept with the result so it can be checked later.
+    fn log_key(&mut self, byte: u8) {
+        let Some(start_time) = self.start_time else {
+            return;
+        };
+        self.keys.push(Keystroke { at: start_time.elapsed().as_millis() as u64, key: simulate::key_name(byte as char) });
+    }
+
+    fn record(&mut self, correct: bool) {
+        let Some(start_time) = self.start_time else {
+            return;
+        };
+        self.record_at(start_time.elapsed().as_secs() as usize, correct);
+    }
+
+    // The simulator keeps its own clock and passes the second in directly.
+    pub fn record_at(&mut self, second: usize, correct: bool) {
+        if self.chars_per_second.len() <= second {
+            self.chars_per_second.resize(second + 1, 0);
+            self.errors_per_second.resize(second + 1, 0);
+        }
+        self.chars_per_second[second] += 1;
+        if !correct {
+            self.errors_per_second[second] += 1;
+        }
+    }
+}
+
 fn display_results(elapsed: f64, accuracy: f64, wpm: f64, raw: f64) {
     println!("\n\nTime: {:.0}s | Accuracy: {:.0}% | WPM: {:.0} | Raw WPM: {:.0}",
         elapsed,
@@ -52,7 +119,7 @@ fn initial_display(reference: &str, timer_pos: (u16, u16)) {
     stdout.flush().unwrap();
 }
 
-pub fn type_loop(reference: &str, time_limit: Option<u64>, start_time: &mut Option<Instant>, practice: Option<usize>, is_correct: &mut VecDeque<i32>, mode: &str) -> i32 {
+pub fn type_loop(reference: &str, time_limit: Option<u64>, stats: &mut TestStats, practice: Option<usize>, is_correct: &mut VecDeque<i32>, strictness: utils::Strictness, indicators: utils::Indicators) -> i32 {
     let ref_chars: Vec<char> = reference.chars().collect();
     let mut stdout = stdout();
     let _raw_guard = RawModeGuard::new();
@@ -65,22 +132,33 @@ pub fn type_loop(reference: &str, time_limit: Option<u64>, start_time: &mut Opti
     let mut user_input = String::new();
     let mut position = 0;
     let mut error_positions = vec![false; ref_chars.len()];
+    let mut extra_chars: HashMap<usize, String> = HashMap::new();
     let mut last_update = Instant::now();
 
     let all_words = reference.split_whitespace().count();
     let mut words_done = 0;
+    let mut failed = None;
 
     loop {
-        if mode == "time" {
-            update_timer(&mut stdout, timer_pos,*start_time, &mut last_update, width, position, time_limit);
+        if time_limit.is_some() {
+            update_timer(&mut stdout, timer_pos,stats.start_time, &mut last_update, width, position, time_limit);
         } else {
             update_word_count(&mut stdout, timer_pos, words_done, width, position, all_words);
         }
+        if indicators != utils::Indicators::default() {
+            let seconds = stats.start_time.map_or(0.0, |start_time| start_time.elapsed().as_secs_f64());
+            let mut live = indicators.text(utils::live_wpm(stats.words_done + words_done, seconds), utils::calc_accuracy(is_correct));
+            if indicators.progress {
+                let progress = utils::live_progress(time_limit.map(|limit| limit as f64), seconds, position, ref_chars.len());
+                live = format!("{}  {}", live, utils::progress_bar(progress, 20)).trim_start().to_string();
+            }
+            update_live(&mut stdout, &live, width, position);
+        }
 
         let byte_opt = poll_input();
         if byte_opt.is_none() {
             if let Some(limit) = time_limit {
-                if start_time.is_some() && start_time.unwrap().elapsed().as_secs() >= limit {
+                if stats.start_time.is_some() && stats.start_time.unwrap().elapsed().as_secs() >= limit {
                     break;
                 }
             }
@@ -94,27 +172,35 @@ pub fn type_loop(reference: &str, time_limit: Option<u64>, start_time: &mut Opti
             return 1;
         }
 
-        handle_typing(
+        let typed = handle_typing(
             byte,
             &mut user_input,
             &ref_chars,
             &mut position,
             &mut error_positions,
+            &mut extra_chars,
             &mut stdout,
             is_correct,
             practice.is_some(),
             &mut words_done,
-            start_time
+            &mut stats.start_time,
+            strictness,
         );
+        stats.log_key(byte);
+        if let Some(correct) = typed {
+            stats.record(correct);
+        }
 
         stdout.flush().unwrap();
 
-        if position >= ref_chars.len() {
+        failed = strictness.check(&ref_chars, is_correct.iter(), position, utils::calc_accuracy(is_correct));
+        if failed.is_some() || position >= ref_chars.len() {
             break;
         }
     }
-    if practice.is_some() && start_time.is_some() {
-        let elapsed = start_time.unwrap().elapsed().as_secs_f64();
+    stats.words_done += words_done;
+    if practice.is_some() && let Some(start_time) = stats.start_time {
+        let elapsed = start_time.elapsed().as_secs_f64();
         let error_count = error_positions.iter().filter(|&&e| e).count();
         let accuracy = 100.0 - (error_count as f64 / reference.len() as f64 * 100.0);
         let wpm = (user_input.len() as f64 / 5.0) / (elapsed / 60.0);
@@ -146,8 +232,13 @@ pub fn type_loop(reference: &str, time_limit: Option<u64>, start_time: &mut Opti
             println!("\nNew highscore for this level!");
         }
     }
-    show_final_results(reference, start_time.expect("No start time"), is_correct);
+    show_final_results(reference, stats.start_time.expect("No start time"), is_correct);
+    stats.extra_chars += extra_chars.values().map(|extra| extra.chars().count()).sum::<usize>();
 
+    if let Some(reason) = failed {
+        println!("Test failed: {}\r", reason);
+        return 2;
+    }
     0
 }
 
@@ -209,6 +300,19 @@ fn update_word_count(
     stdout.flush().unwrap();
 }
 
+// Live stats on the first line, left of the timer.
+fn update_live(stdout: &mut std::io::Stdout, live: &str, width: u16, position: usize) {
+    queue!(
+        stdout,
+        cursor::MoveTo(0, 0),
+        Print(format!("{:<1$}", live, width.saturating_sub(16) as usize)),
+        cursor::MoveTo(position as u16 % width, position as u16 / width + 2)
+    )
+    .unwrap();
+
+    stdout.flush().unwrap();
+}
+
 fn poll_input() -> Option<u8> {
     if event::poll(std::time::Duration::from_millis(10)).unwrap() {
         if let Event::Key(KeyEvent { code, modifiers, kind: _kind, .. }) = event::read().unwrap() {
@@ -219,6 +323,8 @@ fn poll_input() -> Option<u8> {
             match (code, modifiers) {
                 (KeyCode::Char('c'), event::KeyModifiers::CONTROL) => Some(0x03), // Ctrl+C
                 (KeyCode::Char('d'), event::KeyModifiers::CONTROL) => Some(0x04), // Ctrl+D
+                (KeyCode::Char('w') | KeyCode::Char('h'), event::KeyModifiers::CONTROL) => Some(0x17), // Ctrl+W
+                (KeyCode::Backspace, m) if m.intersects(event::KeyModifiers::CONTROL | event::KeyModifiers::ALT) => Some(0x17), // Ctrl+Backspace
                 (KeyCode::Char(c), _) => Some(c as u8),
                 (KeyCode::Backspace, _) => Some(8),
                 (KeyCode::Esc, _) => Some(0x1B),
@@ -233,6 +339,17 @@ fn poll_input() -> Option<u8> {
     }
 }
 
+pub fn wait_for_continue() -> bool {
+    let _raw_guard = RawModeGuard::new();
+    loop {
+        match poll_input() {
+            Some(b'\n') => return true,
+            Some(0x03) | Some(0x04) | Some(0x1B) => return false,
+            _ => {}
+        }
+    }
+}
+
 fn handle_control_keys(byte: u8, stdout: &mut std::io::Stdout) -> bool {
     match byte {
         0x03 | 0x04 => {
@@ -249,47 +366,73 @@ fn handle_control_keys(byte: u8, stdout: &mut std::io::Stdout) -> bool {
     }
 }
 
-fn handle_typing(
+pub fn handle_typing(
     byte: u8,
     user_input: &mut String,
     ref_chars: &[char],
     position: &mut usize,
     error_positions: &mut Vec<bool>,
-    stdout: &mut std::io::Stdout,
+    extra_chars: &mut HashMap<usize, String>,
+    stdout: &mut impl Write,
     is_correct: &mut VecDeque<i32>,
     practice_mode: bool,
     words_done: &mut usize,
-    start_time: &mut Option<Instant>
-) {
+    start_time: &mut Option<Instant>,
+    strictness: utils::Strictness,
+) -> Option<bool> {
+    let error_mode = if practice_mode { utils::ErrorMode::StopOnLetter } else { strictness.error_mode };
+    let delete_on_error = strictness.delete_word_on_error && !practice_mode;
     match byte {
         // backspace
-        8 | 127 if *position > 0 => {
-            is_correct[*position] = 0;
-            *position -= 1;
-            user_input.pop();
-            if ref_chars.len() > *position + 1 && ref_chars[*position + 1] == ' ' {
-                *words_done -= 1;
+        8 | 127 if error_mode.allows_backspace() => {
+            backspace(user_input, ref_chars, position, extra_chars, stdout, is_correct, words_done);
+            None
+        }
+        // Ctrl+Backspace, at the start of a word it takes the previous one
+        0x17 if error_mode.allows_backspace() => {
+            if *position > 0 && ref_chars[*position - 1].is_whitespace() && extra_chars.get(position).is_none_or(|extra| extra.is_empty()) {
+                backspace(user_input, ref_chars, position, extra_chars, stdout, is_correct, words_done);
             }
-
-            queue!(
-                stdout,
-                cursor::MoveLeft(1),
-                SetAttribute(Attribute::Dim),
-                Print(ref_chars[*position]),
-                SetAttribute(Attribute::Reset),
-                cursor::MoveLeft(1),
-                SetForegroundColor(Color::Reset),
-            )
-            .unwrap();
+            delete_word(user_input, ref_chars, position, extra_chars, stdout, is_correct, words_done);
+            None
         }
+        // no-backspace mode ignores the key instead of typing it
+        8 | 127 | 0x17 => None,
         _ if *position < ref_chars.len() => {
             let c = byte as char;
+            let mut skipped = false;
+            match utils::word_key(error_mode, ref_chars, *position, c) {
+                utils::WordKey::Ignore => return None,
+                utils::WordKey::Extra => {
+                    // the reference is drawn over in place, so extra letters are counted but not shown
+                    let extra = extra_chars.entry(*position).or_default();
+                    if extra.chars().count() < utils::MAX_EXTRA_CHARS {
+                        extra.push(c);
+                        user_input.push(c);
+                    }
+                    if delete_on_error {
+                        delete_word(user_input, ref_chars, position, extra_chars, stdout, is_correct, words_done);
+                    }
+                    return Some(false);
+                }
+                utils::WordKey::Skip(word_end) => {
+                    is_correct.range_mut(*position..word_end).for_each(|v| *v = 4); // Missed
+                    let missed: String = ref_chars[*position..word_end].iter().collect();
+                    print_typed(stdout, theme::colors().missed, missed, theme::markers().error());
+                    *position = word_end;
+                    *words_done += 1;
+                    skipped = true;
+                }
+                utils::WordKey::Normal => {}
+            }
             let ref_char = ref_chars[*position];
+            let accepted = error_mode.accepts(ref_chars, is_correct.iter(), *position, c);
+            let advances = error_mode.advances(ref_chars, *position, accepted);
             
-            if !(ref_chars.len() <= *position + 1 || ref_chars[*position + 1] != ' ' || practice_mode && c != ref_char) {
+            if advances && ref_chars.get(*position + 1) == Some(&' ') {
                 *words_done += 1;
             }
-            if c == ref_char {
+            if accepted {
                 if start_time.is_none() {
                     *start_time = Some(Instant::now());
                 }
@@ -301,57 +444,111 @@ fn handle_typing(
                     } else {
                         c
                     };
-                    queue!(
-                        stdout,
-                        SetForegroundColor(Color::Yellow),
-                        Print(char_display),
-                        SetForegroundColor(Color::Reset)
-                    )
-                    .unwrap();
+                    print_typed(stdout, theme::colors().main, char_display, theme::markers().corrected());
                 } else {
                     // Correct on first try: green
                     is_correct[*position] = 2;
-                    queue!(
-                        stdout,
-                        SetForegroundColor(Color::White),
-                        Print(c),
-                        SetForegroundColor(Color::Reset)
-                    )
-                    .unwrap();
+                    print_typed(stdout, theme::colors().text, c, None);
+                }
+                if extra_chars.get(position).is_some_and(|extra| !extra.is_empty()) {
+                    is_correct[*position] = 5; // Space after extra letters
                 }
                 user_input.push(c);
                 *position += 1;
+                Some(!skipped)
             } else {
                 if start_time.is_none() {
                     *start_time = Some(Instant::now());
                 }
                 is_correct[*position] = -1;
                 error_positions[*position] = true;
-                if practice_mode {
-                    return;
-                }
-                if ref_char == ' ' {
-                    queue!(
-                        stdout,
-                        SetForegroundColor(Color::Red),
-                        Print('_'),
-                        SetForegroundColor(Color::Reset)
-                    )
-                .unwrap();
-                } else {
-                    queue!(
-                        stdout,
-                        SetForegroundColor(Color::Red),
-                        Print(ref_char),
-                        SetForegroundColor(Color::Reset)
-                    )
-                    .unwrap();
+                if !advances {
+                    if delete_on_error {
+                        delete_word(user_input, ref_chars, position, extra_chars, stdout, is_correct, words_done);
+                    }
+                    return Some(false);
                 }
+                let shown = if ref_char == ' ' { '_' } else { ref_char };
+                print_typed(stdout, theme::colors().error, shown, theme::markers().error());
                 user_input.push(c);
                 *position += 1;
+                if delete_on_error {
+                    delete_word(user_input, ref_chars, position, extra_chars, stdout, is_correct, words_done);
+                }
+                Some(false)
             }
         }
-        _ => {}
+        _ => None,
+    }
+}
+
+fn backspace(
+    user_input: &mut String,
+    ref_chars: &[char],
+    position: &mut usize,
+    extra_chars: &mut HashMap<usize, String>,
+    stdout: &mut impl Write,
+    is_correct: &mut VecDeque<i32>,
+    words_done: &mut usize,
+) {
+    if let Some(extra) = extra_chars.get_mut(position)
+        && extra.pop().is_some()
+    {
+        user_input.pop();
+        return;
+    }
+    if *position == 0 {
+        return;
+    }
+    is_correct[*position] = 0;
+    *position -= 1;
+    user_input.pop();
+    if ref_chars.len() > *position + 1 && ref_chars[*position + 1] == ' ' {
+        *words_done = words_done.saturating_sub(1);
+    }
+
+    queue!(
+        stdout,
+        cursor::MoveLeft(1),
+        SetAttribute(Attribute::Dim),
+        Print(ref_chars[*position]),
+        SetAttribute(Attribute::Reset),
+        cursor::MoveLeft(1),
+        SetForegroundColor(Color::Reset),
+    )
+    .unwrap();
+
+    // back to where a skipped word was left
+    if *position > 0 && is_correct[*position - 1] == 4 {
+        *words_done = words_done.saturating_sub(1);
+        while *position > 0 && is_correct[*position - 1] == 4 {
+            *position -= 1;
+            is_correct[*position] = 0;
+            queue!(
+                stdout,
+                cursor::MoveLeft(1),
+                SetAttribute(Attribute::Dim),
+                Print(ref_chars[*position]),
+                SetAttribute(Attribute::Reset),
+                cursor::MoveLeft(1),
+            )
+            .unwrap();
+        }
+    }
+}
+
+// Removes extra letters and the rest of the current word.
+fn delete_word(
+    user_input: &mut String,
+    ref_chars: &[char],
+    position: &mut usize,
+    extra_chars: &mut HashMap<usize, String>,
+    stdout: &mut impl Write,
+    is_correct: &mut VecDeque<i32>,
+    words_done: &mut usize,
+) {
+    while extra_chars.get(position).is_some_and(|extra| !extra.is_empty()) || (*position > 0 && !ref_chars[*position - 1].is_whitespace()) {
+        backspace(user_input, ref_chars, position, extra_chars, stdout, is_correct, words_done);
     }
 }
 
@@ -360,10 +557,8 @@ fn show_final_results(
     start_time: Instant,
     is_correct: &VecDeque<i32>,
 ) {
-    let (_corrected_words, correct_words, all_words) = utils::count_correct_words(reference, is_correct);
     let elapsed = start_time.elapsed().as_secs_f64();
-    let wpm = correct_words as f64 / (elapsed / 60.0);
-    let raw = all_words as f64 / (elapsed / 60.0);
+    let (wpm, raw) = utils::calc_wpm(reference, is_correct, elapsed);
 
     let term_width = crossterm::terminal::size().map(|(w, _)| w as usize).unwrap_or(80);
     let lines = reference.len().div_ceil(term_width);
@@ -376,13 +571,7 @@ fn show_final_results(
     .unwrap();
     stdout.flush().unwrap();
     
-    let correct_count = is_correct.iter().filter(|&&v| v == 1 || v == 2).count();
-    let all_pressed_count = is_correct.iter().filter(|&&v| v != 0).count();
-    let accuracy = if correct_count > 0 {
-        (correct_count as f64 / all_pressed_count as f64) * 100.0
-    } else {
-        0.0
-    };
+    let accuracy = utils::calc_accuracy(is_correct);
     display_results(elapsed, accuracy, wpm, raw);
     
     queue!(
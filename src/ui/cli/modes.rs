@@ -1,6 +1,4 @@
 use std::{path::PathBuf};
-use std::fs;
-use std::time::Instant;
 use std::fs::File;
 use std::io::BufReader;
 use rand::prelude::IndexedRandom;
@@ -8,11 +6,104 @@ use std::collections::VecDeque;
 
 use crate::ui::cli;
 use crate::Cli;
-use crate::Quote;
+use crate::utils::{Mode, Quote};
 use crate::utils;
 use crate ::practice;
+use crate::custom;
+use crate::book;
+use crate::history;
+use crate::card;
+use crate::daily;
+use crate::leaderboard;
 
 
+fn strictness(args: &Cli) -> utils::Strictness {
+    utils::Strictness {
+        sudden_death: args.sudden_death,
+        min_accuracy: args.min_accuracy,
+        error_mode: args.errors.as_deref().and_then(utils::ErrorMode::from_name).unwrap_or_default(),
+        delete_word_on_error: args.delete_word_on_error,
+    }
+}
+
+fn indicators(args: &Cli) -> utils::Indicators {
+    args.live.as_deref().and_then(|names| utils::Indicators::from_names(names).ok()).unwrap_or_default()
+}
+
+// Interrupted tests (Esc, Ctrl+C) are not kept in the history or printed, failed ones are.
+// Seed is None for texts that weren't generated (custom files, books).
+fn finish_test(args: &Cli, mode: &str, seed: Option<u64>, reference: &str, is_correct: &VecDeque<i32>, stats: &cli::main::TestStats, res: i32) {
+    if stats.start_time.is_none() || res == 1 {
+        return;
+    }
+    let seconds = stats.start_time.map(|s| s.elapsed().as_secs_f64()).unwrap_or(0.0);
+    let report = cli::output::Report {
+        mode: mode.to_string(),
+        punctuation: args.punctuation,
+        numbers: args.digits,
+        top_words: args.top_words,
+        time_limit: (mode == Mode::Time.name()).then(|| args.time_limit.unwrap_or(Some(30)).unwrap_or(30)),
+        seed,
+        sudden_death: args.sudden_death,
+        min_accuracy: args.min_accuracy,
+        error_mode: strictness(args).error_mode.name().to_string(),
+        delete_word_on_error: args.delete_word_on_error,
+        failed: res == 2,
+        ..cli::output::Report::new(reference, is_correct, stats, seconds)
+    };
+    let saved = history::add(history::Record {
+        timestamp: report.timestamp,
+        frontend: "cli".to_string(),
+        mode: report.mode.clone(),
+        punctuation: report.punctuation,
+        numbers: report.numbers,
+        seed: report.seed,
+        seconds,
+        wpm: report.wpm,
+        raw_wpm: report.raw_wpm,
+        accuracy: report.accuracy,
+        sudden_death: report.sudden_death,
+        min_accuracy: report.min_accuracy,
+        error_mode: report.error_mode.clone(),
+        delete_word_on_error: report.delete_word_on_error,
+        failed: report.failed,
+        reference: reference.to_string(),
+        keys: stats.keys.clone(),
+    });
+    // the CLI saves one result per run, so the config is read here
+    let mut submitter = leaderboard::Submitter::new(leaderboard::load_config());
+    match saved {
+        Ok(record) => submitter.submit(&record),
+        Err(err) => eprintln!("{}", err),
+    }
+    if let Some(format) = args.output.as_deref().and_then(cli::output::Format::from_name) {
+        println!("{}", cli::output::render(format, &report));
+    }
+    if let Some(path) = &args.card {
+        let result_card = card::Card {
+            timestamp: report.timestamp,
+            mode: report.mode.clone(),
+            punctuation: report.punctuation,
+            numbers: report.numbers,
+            seconds: report.seconds,
+            wpm: report.wpm,
+            raw_wpm: report.raw_wpm,
+            accuracy: report.accuracy,
+            wpm_per_second: report.wpm_per_second.clone(),
+            errors_per_second: report.errors_per_second.iter().map(|&errors| errors as f64).collect(),
+        };
+        match card::save(&result_card, path) {
+            Ok(()) => println!("Result card saved to {}", path.display()),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    match submitter.wait() {
+        Some(Ok(message)) => println!("{}", message),
+        Some(Err(err)) => eprintln!("{}", err),
+        None => {}
+    }
+}
+
 pub fn word_mode(args: &Cli) {
     println!("Starting common words test with specified word number");
 
@@ -36,11 +127,13 @@ pub fn word_mode(args: &Cli) {
 
     let word_list = utils::read_first_n_words(top_words);
 
-    let reference = utils::get_reference(punctuation, digits, &word_list, word_number);
-    let mut start_time: Option<Instant> = None;
+    let mut rng = utils::TestRng::new(args.seed);
+    let reference = utils::get_reference(punctuation, digits, &word_list, word_number, rng.rng());
+    let mut stats = cli::main::TestStats::default();
     let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; reference.len()]);
 
-    cli::main::type_loop(&reference, None, &mut start_time, None, &mut is_correct, "word");
+    let res = cli::main::type_loop(&reference, None, &mut stats, None, &mut is_correct, strictness(args), indicators(args));
+    finish_test(args, Mode::Words.name(), Some(rng.seed), &reference, &is_correct, &stats, res);
 }
 
 pub fn time_mode(args: &Cli) {
@@ -51,26 +144,26 @@ pub fn time_mode(args: &Cli) {
         eprintln!("Time limit must be at least 1 second.");
         return;
     }
-    if time_limit > 300 {
-        eprintln!("The maximum time limit is 300 seconds.");
-        return;
-    }
 
     let top_words = args.top_words.unwrap_or(500);
     println!("Starting common words test with {} second time limit", time_limit);
     let word_list = utils::read_first_n_words(top_words);
     let batch_size = 20;
-    let mut start_time: Option<Instant> = None;
+    let mut stats = cli::main::TestStats::default();
 
     let punctuation = args.punctuation;
     let digits = args.digits;
+    let mut full_reference = String::new();
+    let mut full_is_correct: VecDeque<i32> = VecDeque::new();
+    let mut res = 0;
+    let mut rng = utils::TestRng::new(args.seed);
 
-    'outer: while start_time.is_none() || start_time.unwrap().elapsed().as_secs() < time_limit {
-        let reference = utils::get_reference(punctuation, digits, &word_list, batch_size) + " ";
+    'outer: while stats.start_time.is_none() || stats.start_time.unwrap().elapsed().as_secs() < time_limit {
+        let reference = utils::get_reference(punctuation, digits, &word_list, batch_size, rng.rng()) + " ";
         let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; reference.len()]);
         
-        if start_time.is_some() {
-            let elapsed = start_time.unwrap().elapsed().as_secs();
+        if stats.start_time.is_some() {
+            let elapsed = stats.start_time.unwrap().elapsed().as_secs();
             let remaining_time = if time_limit > elapsed {
                 Some(time_limit - elapsed)
             } else {
@@ -82,48 +175,122 @@ pub fn time_mode(args: &Cli) {
             }
         }
 
-        let res = cli::main::type_loop(&reference, Some(time_limit), &mut start_time, None, &mut is_correct, "time");
-        if res != 0 {
+        res = cli::main::type_loop(&reference, Some(time_limit), &mut stats, None, &mut is_correct, strictness(args), indicators(args));
+        full_reference += &reference;
+        full_is_correct.extend(is_correct);
+        if res == 1 {
             println!("Test interrupted by user.");
             break;
         }
+        if res == 2 {
+            break;
+        }
 
-        if start_time.unwrap().elapsed().as_secs() >= time_limit {
+        if stats.start_time.unwrap().elapsed().as_secs() >= time_limit {
             break 'outer;
         }
     }
+    finish_test(args, Mode::Time.name(), Some(rng.seed), &full_reference, &full_is_correct, &stats, res);
 }
 
-pub fn custom_text(path: &PathBuf) {
+pub fn custom_text(args: &Cli, path: &PathBuf) {
     println!("Starting custom text test with file: {:?}", path);
-    utils::validate_custom_file(path).unwrap_or_else(|err| {
+    let custom = custom::load_file(path).unwrap_or_else(|err| {
         eprintln!("{}", err);
         std::process::exit(1);
     });
-    let reference = match fs::read_to_string(path) {
-        Ok(content) => content.replace('\n', " "),
-        Err(_) => {
-            eprintln!("Error reading file");
-            return;
-        }
-    };
+    let mut custom = custom::remember(custom);
+    if custom.chunk_count() > 1 {
+        println!("Part {} of {}", custom.chunk + 1, custom.chunk_count());
+    }
+    let reference = custom.current_chunk();
     let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; reference.len()]);
-    let mut start_time: Option<Instant> = None;
-    cli::main::type_loop(reference.as_str(), None, &mut start_time, None, &mut is_correct, "custom");
+    let mut stats = cli::main::TestStats::default();
+    let res = cli::main::type_loop(reference.as_str(), None, &mut stats, None, &mut is_correct, strictness(args), indicators(args));
+    if res == 0 {
+        custom::advance(&mut custom);
+    }
+    finish_test(args, Mode::Custom.name(), None, &reference, &is_correct, &stats, res);
+}
+
+pub fn book(args: &Cli, path: &PathBuf) {
+    println!("Starting document: {:?}", path);
+    let text = book::import(path).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    let pages = book::pages(&text);
+    if pages.is_empty() {
+        eprintln!("Document is empty");
+        return;
+    }
+    let mut document = book::open(path, pages.len());
+
+    while document.page < pages.len() {
+        let reference = &pages[document.page];
+        let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; reference.len()]);
+        let mut stats = cli::main::TestStats::default();
+        let res = cli::main::type_loop(reference, None, &mut stats, None, &mut is_correct, strictness(args), indicators(args));
+        finish_test(args, Mode::Book.name(), None, reference, &is_correct, &stats, res);
+        if res != 0 {
+            break;
+        }
+        let elapsed = stats.start_time.map(|s| s.elapsed().as_secs_f64()).unwrap_or(0.0);
+        let (_corrected_words, correct_words, _all_words) = utils::count_correct_words(reference, &is_correct);
+        let correct_chars = is_correct.iter().filter(|&&v| v == 1 || v == 2).count();
+        let chars_typed = is_correct.iter().filter(|&&v| v != 0).count();
+        document.record_page(elapsed, correct_words, correct_chars, chars_typed);
+        book::save(&document);
+
+        println!("Page {} of {} done | {}: {:.0} WPM, {:.0}% accuracy over {} pages",
+            document.page,
+            document.pages,
+            document.name,
+            document.wpm(),
+            document.accuracy(),
+            document.pages_done,
+        );
+        if document.page >= pages.len() {
+            println!("You reached the end of the document!");
+            document.page = 0;
+            book::save(&document);
+            break;
+        }
+        println!("Press Enter for the next page, Esc to stop");
+        if !cli::main::wait_for_continue() {
+            break;
+        }
+    }
 }
 
-pub fn quotes() {
+pub fn quotes(args: &Cli) {
     println!("Starting random quote test");
     let file = File::open("assets/quotes.json").expect("Failed to open quotes file");
     let reader = BufReader::new(file);
     let quotes: Vec<Quote> = serde_json::from_reader(reader).expect("Failed to parse quotes");
-    let mut rng = rand::rng();
-    let random_quote = quotes.choose(&mut rng).expect("No quotes available");
+    let mut rng = utils::TestRng::new(args.seed);
+    let random_quote = quotes.choose(rng.rng()).expect("No quotes available");
     let reference = format!("\"{}\" - {}", random_quote.text, random_quote.author);
-    let mut start_time: Option<Instant> = None;
+    let mut stats = cli::main::TestStats::default();
+    let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; reference.len()]);
+
+    let res = cli::main::type_loop(&reference, None, &mut stats, None, &mut is_correct, strictness(args), indicators(args));
+    finish_test(args, Mode::Quote.name(), Some(rng.seed), &reference, &is_correct, &stats, res);
+}
+
+pub fn daily(args: &Cli) {
+    let day = daily::today();
+    println!("Starting the daily challenge for {}", history::date(day * 86400));
+    let reference = daily::reference(day);
+    let mut stats = cli::main::TestStats::default();
     let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; reference.len()]);
 
-    cli::main::type_loop(&reference, None, &mut start_time, None, &mut is_correct, "quote");
+    let res = cli::main::type_loop(&reference, None, &mut stats, None, &mut is_correct, strictness(args), indicators(args));
+    let mode = daily::attempt_mode(&history::load(), day);
+    finish_test(args, mode, Some(daily::seed(day)), &reference, &is_correct, &stats, res);
+    if stats.start_time.is_some() && res != 1 {
+        println!("Daily challenge: {}", daily::status(mode));
+    }
 }
 
 pub fn practice(args: &Cli) {
@@ -144,10 +311,12 @@ pub fn practice(args: &Cli) {
     let curr_level= level.unwrap() - 1;
     let chars = practice::TYPING_LEVELS[curr_level].1;
     
-    let reference = practice::create_words(chars, args.word_number.unwrap_or(Some(50)).unwrap_or(50));
+    let mut rng = utils::TestRng::new(args.seed);
+    let reference = practice::create_words(chars, args.word_number.unwrap_or(Some(50)).unwrap_or(50), rng.rng());
     let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; reference.len()]);
-    let mut start_time: Option<Instant> = None;
-    let res = cli::main::type_loop(&reference, None, &mut start_time, Some(curr_level), &mut is_correct, "practice");
+    let mut stats = cli::main::TestStats::default();
+    let res = cli::main::type_loop(&reference, None, &mut stats, Some(curr_level), &mut is_correct, strictness(args), indicators(args));
+    finish_test(args, Mode::Practice.name(), Some(rng.seed), &reference, &is_correct, &stats, res);
     if res == 1 {
         println!("Exiting practice mode.");
     }
@@ -0,0 +1,131 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+
+use crate::ui::cli::main::TestStats;
+use crate::utils;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+    Toml,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 3] = ["json", "csv", "toml"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub timestamp: u64,
+    pub mode: String,
+    pub punctuation: bool,
+    pub numbers: bool,
+    pub top_words: Option<usize>,
+    pub time_limit: Option<u64>,
+    pub seed: Option<u64>,
+    pub sudden_death: bool,
+    pub min_accuracy: Option<f64>,
+    pub error_mode: String,
+    pub delete_word_on_error: bool,
+    pub failed: bool,
+    pub seconds: f64,
+    pub wpm: f64,
+    pub raw_wpm: f64,
+    pub accuracy: f64,
+    pub consistency: f64,
+    pub correct_chars: usize,
+    pub corrected_chars: usize,
+    pub incorrect_chars: usize,
+    pub missed_chars: usize,
+    pub extra_chars: usize,
+    pub wpm_per_second: Vec<f64>,
+    pub errors_per_second: Vec<usize>,
+}
+
+fn round2(value: f64) -> f64 {
+    if value.is_finite() { (value * 100.0).round() / 100.0 } else { 0.0 }
+}
+
+impl Report {
+    // Results part of the record, settings are filled in by the caller.
+    pub fn new(reference: &str, is_correct: &VecDeque<i32>, stats: &TestStats, seconds: f64) -> Self {
+        let (wpm, raw_wpm) = utils::calc_wpm(reference, is_correct, seconds);
+        let count = |value: i32| is_correct.iter().filter(|&&v| v == value).count();
+
+        let full_seconds = (seconds.ceil() as usize).max(stats.chars_per_second.len());
+        let mut chars_per_second = stats.chars_per_second.clone();
+        let mut errors_per_second = stats.errors_per_second.clone();
+        chars_per_second.resize(full_seconds, 0);
+        errors_per_second.resize(full_seconds, 0);
+        let wpm_per_second: Vec<f64> = chars_per_second.iter().map(|&chars| chars as f64 * 60.0 / 5.0).collect();
+
+        Report {
+            timestamp: crate::history::now(),
+            seconds: round2(seconds),
+            wpm: round2(wpm),
+            raw_wpm: round2(raw_wpm),
+            accuracy: round2(utils::calc_accuracy(is_correct)),
+            consistency: round2(utils::calc_consistency(&wpm_per_second, wpm)),
+            correct_chars: count(2),
+            corrected_chars: count(1),
+            incorrect_chars: count(-1) + count(5),
+            missed_chars: count(4),
+            extra_chars: stats.extra_chars,
+            wpm_per_second,
+            errors_per_second,
+            ..Default::default()
+        }
+    }
+}
+
+pub fn render(format: Format, report: &Report) -> String {
+    let value = serde_json::to_value(report).unwrap_or_default();
+    let Value::Object(fields) = &value else {
+        return String::new();
+    };
+    match format {
+        Format::Json => serde_json::to_string_pretty(&value).unwrap_or_default(),
+        Format::Csv => {
+            let header: Vec<&str> = fields.keys().map(|key| key.as_str()).collect();
+            let row: Vec<String> = fields.values().map(csv_value).collect();
+            format!("{}\n{}", header.join(","), row.join(","))
+        }
+        Format::Toml => fields
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| format!("{} = {}", key, toml_value(value)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+// Lists are joined with ';' so every record stays on one line.
+pub fn csv_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) if s.contains([',', '"', '\n']) => format!("\"{}\"", s.replace('"', "\"\"")),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(csv_value).collect::<Vec<_>>().join(";"),
+        other => other.to_string(),
+    }
+}
+
+// JSON strings, numbers and booleans are valid TOML as they are.
+fn toml_value(value: &Value) -> String {
+    match value {
+        Value::Array(values) => format!("[{}]", values.iter().map(toml_value).collect::<Vec<_>>().join(", ")),
+        other => other.to_string(),
+    }
+}
@@ -1,10 +1,12 @@
 use core::time;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use macroquad::prelude::*;
 use std::time::{Instant, Duration};
 
 use crate::ui::gui::main;
-use crate::{practice, utils};
+use crate::{code, daily, practice, theme, utils};
+use crate::pace::{Pace, PaceMode};
+use crate::simulate::Keystroke;
 
 
 pub fn draw_rounded_rect(x: f32, y: f32, w: f32, h: f32, radius: f32, color: Color) {
@@ -43,14 +45,14 @@ fn draw_toggle_button(
     let hovered = rect.contains(vec2(mx, my));
     let clicked = hovered && is_mouse_button_pressed(MouseButton::Left);
 
-    let mut text_color = if is_active { main::MAIN_COLOR } else { inactive_color };
+    let mut text_color = if is_active { main::theme_color(theme::colors().main, 255) } else { inactive_color };
     let mut bg_color = Color::from_rgba(255, 0, 0, 0);
     if selected && is_active {
-        text_color = macroquad::color::BLACK;
-        bg_color = Color::from_rgba(150, 90, 0, 255);
+        text_color = main::theme_color(theme::colors().background, 255);
+        bg_color = main::theme_color(theme::colors().dim_main, 255);
     } else if selected {
-        text_color = macroquad::color::BLACK;
-        bg_color = Color::from_rgba(100, 60, 0, 255);
+        text_color = main::theme_color(theme::colors().background, 255);
+        bg_color = main::theme_color(theme::colors().border, 255);
     }
 
     let font_size: u16 = if label == "|" {
@@ -78,8 +80,77 @@ fn draw_toggle_button(
     (clicked, hovered, btn_width + btn_padding * 2.0)
 }
 
+// What the settings line sets up: the mode and its length, and the options every test runs with.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub punctuation: bool,
+    pub numbers: bool,
+    pub quote: bool,
+    pub time_mode: bool,
+    pub word_mode: bool,
+    pub practice_mode: bool,
+    pub custom_mode: bool,
+    pub code_mode: bool,
+    pub zen_mode: bool,
+    pub daily_mode: bool,
+    pub test_time: f32,
+    pub batch_size: usize,
+    pub code_language: String,
+    pub code_auto_indent: bool,
+    pub strictness: utils::Strictness,
+    pub indicators: utils::Indicators,
+    pub pace: Pace,
+    pub blind_mode: bool,
+    pub memory_mode: bool,
+    // theme files the theme button skipped, shown under the buttons
+    pub theme_error: Option<String>,
+}
+
+impl Settings {
+    // A 30 second time test, with the options given on the command line.
+    pub fn new(strictness: utils::Strictness, indicators: utils::Indicators, pace: Pace) -> Self {
+        Settings {
+            punctuation: false,
+            numbers: false,
+            quote: false,
+            time_mode: true,
+            word_mode: false,
+            practice_mode: false,
+            custom_mode: false,
+            code_mode: false,
+            zen_mode: false,
+            daily_mode: false,
+            test_time: 30.0,
+            batch_size: 50,
+            code_language: "rust".to_string(),
+            code_auto_indent: true,
+            strictness,
+            indicators,
+            pace,
+            blind_mode: false,
+            memory_mode: false,
+            theme_error: None,
+        }
+    }
+
+    pub fn mode(&self) -> utils::Mode {
+        utils::Mode::from_flags([self.time_mode, self.word_mode, self.quote, self.custom_mode, self.code_mode, self.zen_mode, self.daily_mode])
+    }
+
+    pub fn typing_mode(&self, practice_menu: bool) -> main::TypingMode {
+        main::TypingMode {
+            practice_mode: self.practice_mode,
+            practice_menu,
+            code_mode: self.code_mode,
+            auto_indent: self.code_auto_indent,
+            strictness: self.strictness,
+        }
+    }
+}
+
+// Starts the test on its first key and ends it on time, at the end of the text or, in zen mode, on Ctrl+D.
 pub fn update_game_state(
-    reference: &str,
+    reference: &mut String,
     pressed_vec: &mut Vec<char>,
     is_correct: &mut VecDeque<i32>,
     pos1: &mut usize,
@@ -87,21 +158,34 @@ pub fn update_game_state(
     start_time: &mut Instant,
     game_started: &mut bool,
     game_over: &mut bool,
-    test_time: f32,
-    time_mode: bool,
     words_done: &mut usize,
     errors_this_second: &mut f64,
-    practice_mode: &mut bool,
     practice_menu: bool,
+    settings: &Settings,
+    keys: &mut Vec<Keystroke>,
 ) {
-    if !*game_started && main::handle_input(reference, pressed_vec, is_correct, pos1, words_done, errors_this_second, &mut false, &mut vec![false; reference.chars().count()], *practice_mode, practice_menu) {
+    // zen text is typed here every frame, other tests only start here and are typed in the main loop
+    let typed = if settings.zen_mode {
+        main::handle_zen_input(reference, pressed_vec, is_correct, pos1, words_done, keys, game_started.then_some(*start_time))
+    } else {
+        !*game_started && main::handle_input(reference, pressed_vec, is_correct, pos1, words_done, errors_this_second, &mut false, &mut vec![false; reference.chars().count()], &mut HashMap::new(), settings.typing_mode(practice_menu), keys, None)
+    };
+    if typed && !*game_started {
         *game_started = true;
         *start_time = Instant::now();
     }
-    
+
     if *game_started && !*game_over {
         *timer = start_time.elapsed();
-        if (timer.as_secs_f32() >= test_time && time_mode) || *pos1 >= reference.chars().count() {
+        if settings.zen_mode {
+            if (is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl)) && is_key_pressed(KeyCode::D) {
+                // the word being typed counts too
+                if !reference.ends_with(' ') {
+                    *words_done += 1;
+                }
+                *game_over = true;
+            }
+        } else if (timer.as_secs_f32() >= settings.test_time && settings.time_mode) || *pos1 >= reference.chars().count() {
             *game_over = true;
         }
     }
@@ -140,11 +224,7 @@ pub fn reset_game_state(
 pub fn handle_settings_buttons(
     font: &Option<Font>,
     word_list: &[String],
-    punctuation: &mut bool,
-    numbers: &mut bool,
-    quote: &mut bool,
-    time_mode: &mut bool,
-    word_mode: &mut bool,
+    settings: &mut Settings,
     pressed_vec: &mut Vec<char>,
     is_correct: &mut VecDeque<i32>,
     pos1: &mut usize,
@@ -153,8 +233,6 @@ pub fn handle_settings_buttons(
     game_started: &mut bool,
     game_over: &mut bool,
     reference: &mut String,
-    test_time: &mut f32,
-    batch_size: &mut usize,
     start_x: f32,
     speed_per_second: &mut Vec<f64>,
     last_recorded_time: &mut Instant,
@@ -165,11 +243,13 @@ pub fn handle_settings_buttons(
     selected_config: &mut String,
     practice_menu: &mut bool,
     selected_practice_level: &mut Option<usize>,
-    practice_mode: &mut bool,
     saved_results: &mut bool,
     error_positions: &mut Vec<bool>,
+    custom_menu: &mut bool,
+    length_input: &mut Option<String>,
+    test_rng: &mut utils::TestRng,
 ) -> bool {
-    let inactive_color = Color::from_rgba(255, 255, 255, 80);
+    let inactive_color = main::theme_color(theme::colors().reference, 255);
     let btn_y = screen_height() / 5.0;
     let btn_padding = if screen_width() > 800.0 {
         font_size as f32 * 0.5
@@ -179,25 +259,77 @@ pub fn handle_settings_buttons(
     let divider = true;
     let mut total_width = 0.0;
 
+    let mode = settings.mode();
     let mut button_states = vec![
-        ("! punctuation", *punctuation, !*quote && !*practice_mode),
-        ("# numbers", *numbers, !*quote && !*practice_mode),
+        ("! punctuation", settings.punctuation, mode.allows_punctuation()),
+        ("# numbers", settings.numbers, mode.allows_punctuation()),
+        ("sudden death", settings.strictness.sudden_death, mode.allows_strictness()),
+        ("word reset", settings.strictness.delete_word_on_error, mode.allows_error_settings()),
+        ("min acc", settings.strictness.min_accuracy.is_some(), mode.allows_strictness()),
+        ("errors", settings.strictness.error_mode != utils::ErrorMode::Off, mode.allows_error_settings()),
+        ("blind", settings.blind_mode, mode.allows_error_settings()),
+        ("memory", settings.memory_mode, mode.allows_error_settings()),
+        ("live wpm", settings.indicators.wpm, true),
+        ("live acc", settings.indicators.accuracy, true),
+        ("progress", settings.indicators.progress, true),
+        ("pace", settings.pace.mode != PaceMode::Off, mode.allows_strictness()),
+        ("theme", true, true),
+        ("markers", theme::markers() != theme::Markers::Off, mode.allows_strictness()),
         ("|", divider, true),
-        ("time", *time_mode, true),
-        ("words", *word_mode, true),
-        ("quote", *quote, true),
-        ("practice", *practice_mode, true),
+        ("time", settings.time_mode, true),
+        ("words", settings.word_mode, true),
+        ("quote", settings.quote, true),
+        ("custom", settings.custom_mode, true),
+        ("code", settings.code_mode, true),
+        ("zen", settings.zen_mode, true),
+        ("daily", settings.daily_mode, true),
+        ("practice", settings.practice_mode, true),
         ("|", divider, true),
-        ("15", test_time == &15.0, *time_mode),
-        ("30", test_time == &30.0, *time_mode),
-        ("60", test_time == &60.0, *time_mode),
-        ("120", test_time == &120.0, *time_mode),
-        ("25", *batch_size == 25, *word_mode),
-        ("50", *batch_size == 50, *word_mode),
-        ("100", *batch_size == 100, *word_mode),
+        ("15", settings.test_time == 15.0, settings.time_mode),
+        ("30", settings.test_time == 30.0, settings.time_mode),
+        ("60", settings.test_time == 60.0, settings.time_mode),
+        ("120", settings.test_time == 120.0, settings.time_mode),
+        ("25", settings.batch_size == 25, settings.word_mode),
+        ("50", settings.batch_size == 50, settings.word_mode),
+        ("100", settings.batch_size == 100, settings.word_mode),
+        ("edit", !is_preset_length(settings.time_mode, settings.test_time, settings.batch_size), settings.time_mode || settings.word_mode),
+        ("rust", settings.code_language == "rust", settings.code_mode),
+        ("python", settings.code_language == "python", settings.code_mode),
+        ("javascript", settings.code_language == "javascript", settings.code_mode),
+        ("go", settings.code_language == "go", settings.code_mode),
+        ("c", settings.code_language == "c", settings.code_mode),
+        ("auto indent", settings.code_auto_indent, settings.code_mode),
+        ("80%", settings.strictness.min_accuracy == Some(80.0), settings.strictness.min_accuracy.is_some() && !settings.zen_mode),
+        ("90%", settings.strictness.min_accuracy == Some(90.0), settings.strictness.min_accuracy.is_some() && !settings.zen_mode),
+        ("95%", settings.strictness.min_accuracy == Some(95.0), settings.strictness.min_accuracy.is_some() && !settings.zen_mode),
     ];
 
-    if is_key_down(KeyCode::Up) {
+    if let Some(input) = length_input.as_mut() {
+        while let Some(ch) = get_char_pressed() {
+            if ch.is_ascii_digit() && input.len() < 5 {
+                input.push(ch);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            input.pop();
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            *length_input = None;
+        } else if is_key_pressed(KeyCode::Enter) {
+            if let Some(n) = utils::parse_length(input) {
+                if settings.time_mode {
+                    settings.test_time = n as f32;
+                } else {
+                    settings.batch_size = n;
+                }
+                *reference = utils::get_reference(settings.punctuation, settings.numbers, word_list, settings.batch_size, test_rng.restart());
+                *is_correct = VecDeque::from(vec![0; reference.chars().count()]);
+                *error_positions = vec![false; is_correct.len()];
+                reset_game_state(pressed_vec, is_correct, pos1, timer, start_time, game_started, game_over, speed_per_second, last_recorded_time, words_done, errors_per_second, saved_results, error_positions);
+            }
+            *length_input = None;
+        }
+    } else if is_key_down(KeyCode::Up) {
         *config_opened = true;
     } else if is_key_down(KeyCode::Down) {
         *config_opened = false;
@@ -254,16 +386,26 @@ pub fn handle_settings_buttons(
             }
         }
     } else if is_key_pressed(KeyCode::Enter) && *config_opened {
-        update_config(selected_config, punctuation, numbers, time_mode, word_mode, quote, test_time, batch_size, practice_menu, selected_practice_level, practice_mode);
-        if *quote {
-            *reference = utils::get_random_quote();
-        } else if *practice_mode {
+        update_config(selected_config, settings, practice_menu, selected_practice_level, custom_menu, length_input);
+        if *custom_menu {
+            return false;
+        }
+        if settings.quote {
+            *reference = utils::get_random_quote(test_rng.restart());
+        } else if settings.practice_mode {
             *reference = practice::create_words(
                 practice::TYPING_LEVELS[selected_practice_level.unwrap_or(0)].1,
-                *batch_size,
+                settings.batch_size,
+                test_rng.restart(),
             );
+        } else if settings.code_mode {
+            *reference = code::get_snippet(&settings.code_language, test_rng.restart());
+        } else if settings.zen_mode {
+            reference.clear();
+        } else if settings.daily_mode {
+            *reference = daily::reference(daily::today());
         } else {
-            *reference = utils::get_reference(*punctuation, *numbers, word_list, *batch_size);
+            *reference = utils::get_reference(settings.punctuation, settings.numbers, word_list, settings.batch_size, test_rng.restart());
         }
         *is_correct = VecDeque::from(vec![0; reference.len()]);
         *error_positions = vec![false; is_correct.len()];
@@ -275,12 +417,26 @@ pub fn handle_settings_buttons(
     for (label, state_val, visible) in button_states.iter_mut() {
         let x = start_x + total_width;
         let is_active = *state_val;
+        let shown_label = if *label == "edit" {
+            let value = if settings.time_mode { settings.test_time as usize } else { settings.batch_size };
+            utils::length_label(length_input.as_deref(), value, !is_active)
+        } else if *label == "errors" {
+            settings.strictness.error_mode.label().to_string()
+        } else if *label == "pace" {
+            settings.pace.label()
+        } else if *label == "theme" {
+            format!("theme {}", theme::name())
+        } else if *label == "markers" {
+            theme::markers().label().to_string()
+        } else {
+            label.to_string()
+        };
         
         let (clicked, hovered, btni_width) = draw_toggle_button(
             x, 
             btn_y,
             btn_padding,
-            label,
+            &shown_label,
             font, 
             is_active, 
             inactive_color,
@@ -296,18 +452,30 @@ pub fn handle_settings_buttons(
         
         if clicked && *label != "|" {
             
-            update_config(label, punctuation, numbers, time_mode, word_mode, quote, test_time, batch_size, practice_menu, selected_practice_level, practice_mode);
-            if *quote {
-                *reference = utils::get_random_quote();
+            update_config(label, settings, practice_menu, selected_practice_level, custom_menu, length_input);
+            if settings.quote {
+                *reference = utils::get_random_quote(test_rng.restart());
                 *is_correct = VecDeque::from(vec![0; reference.chars().count()]);
                 *error_positions = vec![false; is_correct.len()];
-                *punctuation = false;
-                *numbers = false;
+                settings.punctuation = false;
+                settings.numbers = false;
                 reset_game_state(pressed_vec, is_correct, pos1, timer, start_time, game_started, game_over, speed_per_second, last_recorded_time, words_done, errors_per_second, saved_results, error_positions);
             } else if *practice_menu {
                 *practice_menu = true;
+            } else if *custom_menu {
+                return any_button_hovered;
+            } else if settings.daily_mode {
+                *reference = daily::reference(daily::today());
+                *is_correct = VecDeque::from(vec![0; reference.chars().count()]);
+                *error_positions = vec![false; is_correct.len()];
+                reset_game_state(pressed_vec, is_correct, pos1, timer, start_time, game_started, game_over, speed_per_second, last_recorded_time, words_done, errors_per_second, saved_results, error_positions);
+            } else if settings.code_mode || settings.zen_mode {
+                *reference = if settings.zen_mode { String::new() } else { code::get_snippet(&settings.code_language, test_rng.restart()) };
+                *is_correct = VecDeque::from(vec![0; reference.chars().count()]);
+                *error_positions = vec![false; is_correct.len()];
+                reset_game_state(pressed_vec, is_correct, pos1, timer, start_time, game_started, game_over, speed_per_second, last_recorded_time, words_done, errors_per_second, saved_results, error_positions);
             } else {
-                *reference = utils::get_reference(*punctuation, *numbers, word_list, *batch_size);
+                *reference = utils::get_reference(settings.punctuation, settings.numbers, word_list, settings.batch_size, test_rng.restart());
                 *is_correct = VecDeque::from(vec![0; reference.chars().count()]);
                 *error_positions = vec![false; is_correct.len()];
                 reset_game_state(pressed_vec, is_correct, pos1, timer, start_time, game_started, game_over, speed_per_second, last_recorded_time, words_done, errors_per_second, saved_results, error_positions);
@@ -315,68 +483,187 @@ pub fn handle_settings_buttons(
         }
     }
 
+    if let Some(error) = &settings.theme_error {
+        draw_text_ex(error, start_x, btn_y + font_size as f32 * 2.0, TextParams { font: font.as_ref(), font_size, color: main::theme_color(theme::colors().error, 255), ..Default::default() });
+    }
+
     any_button_hovered
 }
 
-fn update_config(label: &str, punctuation: &mut bool, numbers: &mut bool, time_mode: &mut bool, word_mode: &mut bool, quote: &mut bool, test_time: &mut f32, batch_size: &mut usize, practice_menu: &mut bool, selected_practice_level: &mut Option<usize>, practice_mode: &mut bool) {
+fn is_preset_length(time_mode: bool, test_time: f32, batch_size: usize) -> bool {
+    if time_mode {
+        [15.0, 30.0, 60.0, 120.0].contains(&test_time)
+    } else {
+        [25, 50, 100].contains(&batch_size)
+    }
+}
+
+fn update_config(label: &str, settings: &mut Settings, practice_menu: &mut bool, selected_practice_level: &mut Option<usize>, custom_menu: &mut bool, length_input: &mut Option<String>) {
     match label {
         "! punctuation" => {
-            *punctuation = !*punctuation;
-            *quote = false;
+            settings.punctuation = !settings.punctuation;
+            settings.quote = false;
         },
         "# numbers" => {
-            *numbers = !*numbers;
-            *quote = false;
+            settings.numbers = !settings.numbers;
+            settings.quote = false;
         },
         "time" => {
-            *time_mode = true;
-            *word_mode = false;
-            *quote = false;
-            *practice_mode = false;
+            settings.time_mode = true;
+            settings.word_mode = false;
+            settings.quote = false;
+            settings.practice_mode = false;
+            settings.custom_mode = false;
+            settings.zen_mode = false;
+            settings.daily_mode = false;
+            settings.code_mode = false;
         },
         "words" => {
-            *word_mode = true;
-            *time_mode = false;
-            *quote = false;
-            *practice_mode = false;
+            settings.word_mode = true;
+            settings.time_mode = false;
+            settings.quote = false;
+            settings.practice_mode = false;
+            settings.custom_mode = false;
+            settings.zen_mode = false;
+            settings.daily_mode = false;
+            settings.code_mode = false;
         },
         "quote" => {
-            *quote = true;
-            *punctuation = false;
-            *numbers = false;
-            *time_mode = false;
-            *word_mode = false;
-            *practice_mode = false;
+            settings.quote = true;
+            settings.punctuation = false;
+            settings.numbers = false;
+            settings.time_mode = false;
+            settings.word_mode = false;
+            settings.practice_mode = false;
+            settings.custom_mode = false;
+            settings.zen_mode = false;
+            settings.daily_mode = false;
+            settings.code_mode = false;
+        },
+        "custom" => {
+            *custom_menu = true;
+        },
+        "code" => {
+            settings.code_mode = true;
+            settings.punctuation = false;
+            settings.numbers = false;
+            settings.time_mode = false;
+            settings.word_mode = false;
+            settings.quote = false;
+            settings.practice_mode = false;
+            settings.custom_mode = false;
+            settings.zen_mode = false;
+            settings.daily_mode = false;
+        },
+        "zen" => {
+            settings.zen_mode = true;
+            settings.daily_mode = false;
+            settings.punctuation = false;
+            settings.numbers = false;
+            settings.time_mode = false;
+            settings.word_mode = false;
+            settings.quote = false;
+            settings.practice_mode = false;
+            settings.custom_mode = false;
+            settings.code_mode = false;
+        },
+        "daily" => {
+            settings.daily_mode = true;
+            settings.punctuation = false;
+            settings.numbers = false;
+            settings.time_mode = false;
+            settings.word_mode = false;
+            settings.quote = false;
+            settings.practice_mode = false;
+            settings.custom_mode = false;
+            settings.zen_mode = false;
+            settings.code_mode = false;
+        },
+        "rust" | "python" | "javascript" | "go" | "c" => {
+            settings.code_language = label.to_string();
+        },
+        "auto indent" => {
+            settings.code_auto_indent = !settings.code_auto_indent;
+        },
+        "edit" => {
+            *length_input = Some(String::new());
+        },
+        "sudden death" => {
+            settings.strictness.sudden_death = !settings.strictness.sudden_death;
+        },
+        "errors" => {
+            settings.strictness.error_mode = settings.strictness.error_mode.next();
+        },
+        "word reset" => {
+            settings.strictness.delete_word_on_error = !settings.strictness.delete_word_on_error;
+        },
+        "blind" => {
+            settings.blind_mode = !settings.blind_mode;
+        },
+        "memory" => {
+            settings.memory_mode = !settings.memory_mode;
+        },
+        "live wpm" => {
+            settings.indicators.wpm = !settings.indicators.wpm;
+        },
+        "live acc" => {
+            settings.indicators.accuracy = !settings.indicators.accuracy;
+        },
+        "progress" => {
+            settings.indicators.progress = !settings.indicators.progress;
+        },
+        "pace" => {
+            settings.pace = settings.pace.next();
+        },
+        "theme" => {
+            let errors = theme::next();
+            settings.theme_error = (!errors.is_empty()).then(|| errors.join(", "));
+        },
+        "markers" => {
+            theme::set_markers(theme::markers().next());
+        },
+        "min acc" => {
+            settings.strictness.min_accuracy = match settings.strictness.min_accuracy {
+                Some(_) => None,
+                None => Some(90.0),
+            };
+        },
+        "80%" | "90%" | "95%" => {
+            settings.strictness.min_accuracy = label.trim_end_matches('%').parse().ok();
         },
         "practice" => {
-            *quote = false;
-            *punctuation = false;
-            *numbers = false;
-            *time_mode = false;
-            *word_mode = false;
+            settings.quote = false;
+            settings.punctuation = false;
+            settings.numbers = false;
+            settings.time_mode = false;
+            settings.word_mode = false;
+            settings.custom_mode = false;
+            settings.zen_mode = false;
+            settings.daily_mode = false;
+            settings.code_mode = false;
             *practice_menu = true;
             *selected_practice_level = Some(practice::get_first_not_done());
         },
         "15" => {
-            *test_time = 15.0;
+            settings.test_time = 15.0;
         },
         "30" => {
-            *test_time = 30.0;
+            settings.test_time = 30.0;
         },
         "60" => {
-            *test_time = 60.0;
+            settings.test_time = 60.0;
         },
         "120" => {
-            *test_time = 120.0;
+            settings.test_time = 120.0;
         },
         "25" => {
-            *batch_size = 25;
+            settings.batch_size = 25;
         },
         "50" => {
-            *batch_size = 50;
+            settings.batch_size = 50;
         },
         "100" => {
-            *batch_size = 100;
+            settings.batch_size = 100;
         },
         _ => {}
     }
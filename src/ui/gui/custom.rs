@@ -0,0 +1,172 @@
+use macroquad::prelude::*;
+use miniquad::window::{clipboard_get, set_mouse_cursor};
+use miniquad::CursorIcon;
+use std::path::PathBuf;
+
+use crate::custom::{self, CustomText};
+use crate::theme;
+use crate::ui::gui::main;
+
+
+pub fn display_custom_menu(
+    font: Option<Font>,
+    input: &mut String,
+    message: &str,
+    recent: &[CustomText],
+    selected: &mut usize,
+    custom_menu: &mut bool,
+) -> Option<Result<CustomText, String>> {
+    let font_size = if screen_width() > 3000.0 {
+        20
+    } else if screen_width() > 1900.0 {
+        18
+    } else {
+        15
+    };
+    let title_size = if screen_height() > 2000.0 && screen_width() > 1900.0 {
+        40
+    } else if screen_height() > 1000.0 && screen_width() > 800.0 {
+        30
+    } else {
+        25
+    };
+    let x = screen_width() / 6.0;
+    let mut y = screen_height() / 10.0;
+
+    let mut typed: Vec<char> = vec![];
+    while let Some(ch) = get_char_pressed() {
+        typed.push(ch);
+    }
+
+    let ctrl_down = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl)
+        || is_key_down(KeyCode::LeftSuper) || is_key_down(KeyCode::RightSuper);
+    if ctrl_down && is_key_pressed(KeyCode::V) {
+        return match clipboard_get() {
+            Some(text) => Some(custom::from_pasted(&text)),
+            None => Some(Err("Clipboard is empty".to_string())),
+        };
+    }
+    if is_key_pressed(KeyCode::Escape) {
+        *custom_menu = false;
+        return None;
+    }
+    if is_key_pressed(KeyCode::Backspace) {
+        input.pop();
+    }
+    for ch in typed {
+        if !ch.is_control() && !ctrl_down {
+            input.push(ch);
+        }
+    }
+    if is_key_pressed(KeyCode::Up) {
+        *selected = selected.saturating_sub(1);
+    }
+    if is_key_pressed(KeyCode::Down) && *selected + 1 < recent.len() {
+        *selected += 1;
+    }
+    if is_key_pressed(KeyCode::Enter) && !is_key_down(KeyCode::Tab) {
+        if !input.is_empty() {
+            return Some(custom::load_file(&PathBuf::from(input.trim())));
+        } else if let Some(text) = recent.get(*selected) {
+            return Some(Ok(text.clone()));
+        }
+    }
+
+    draw_text_ex(
+        "Custom Text",
+        x,
+        y,
+        TextParams {
+            font: font.as_ref(),
+            font_size: title_size,
+            color: main::theme_color(theme::colors().main, 255),
+            ..Default::default()
+        },
+    );
+    y += 2.5 * font_size as f32;
+
+    draw_text_ex(
+        &format!("file: {}_", input),
+        x,
+        y,
+        TextParams {
+            font: font.as_ref(),
+            font_size,
+            color: main::theme_color(theme::colors().text, 230),
+            ..Default::default()
+        },
+    );
+    y += 2.0 * font_size as f32;
+
+    draw_text_ex(
+        message,
+        x,
+        y,
+        TextParams {
+            font: font.as_ref(),
+            font_size,
+            color: main::theme_color(theme::colors().error, 180),
+            ..Default::default()
+        },
+    );
+    y += 2.5 * font_size as f32;
+
+    draw_text_ex(
+        if recent.is_empty() { "recent: nothing here yet" } else { "recent:" },
+        x,
+        y,
+        TextParams {
+            font: font.as_ref(),
+            font_size,
+            color: main::theme_color(theme::colors().reference, 255),
+            ..Default::default()
+        },
+    );
+
+    let mouse_pos = mouse_position();
+    let mut any_hovered = false;
+    for (i, text) in recent.iter().enumerate() {
+        y += 20.0 + font_size as f32;
+        let label = format!("{} (part {}/{})", text.name, text.chunk + 1, text.chunk_count());
+        let text_size = measure_text(&label, font.as_ref(), font_size, 1.0);
+        let button_rect = Rect::new(
+            x + 40.0,
+            y - font_size as f32 - 10.0,
+            text_size.width + 2.0 * font_size as f32,
+            20.0 + font_size as f32,
+        );
+        let hovered = button_rect.contains(vec2(mouse_pos.0, mouse_pos.1));
+        if hovered {
+            any_hovered = true;
+            *selected = i;
+            if is_mouse_button_pressed(MouseButton::Left) {
+                return Some(Ok(text.clone()));
+            }
+        }
+
+        let text_color = if *selected == i && input.is_empty() {
+            main::theme_color(theme::colors().main, 255)
+        } else {
+            main::theme_color(theme::colors().text, 230)
+        };
+        draw_text_ex(
+            &label,
+            x + 40.0,
+            y,
+            TextParams {
+                font: font.as_ref(),
+                font_size,
+                color: text_color,
+                ..Default::default()
+            },
+        );
+    }
+
+    set_mouse_cursor(if any_hovered {
+        CursorIcon::Pointer
+    } else {
+        CursorIcon::Default
+    });
+
+    None
+}
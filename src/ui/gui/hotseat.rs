@@ -0,0 +1,234 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use macroquad::prelude::*;
+
+use crate::ui::gui::main::{self, ROBOTO_MONO};
+use crate::ui::gui::{config, results};
+use crate::{leaderboard, theme, utils};
+use crate::pace::Pace;
+use crate::simulate::Keystroke;
+
+
+const WORDS: usize = 30;
+const MODE: &str = "hot seat";
+
+// One player's test, the same state the single player screen keeps in locals.
+struct Seat {
+    name: String,
+    pressed_vec: Vec<char>,
+    is_correct: VecDeque<i32>,
+    pos1: usize,
+    words_done: usize,
+    error_positions: Vec<bool>,
+    extra_chars: HashMap<usize, String>,
+    keys: Vec<Keystroke>,
+    errors_this_second: f64,
+    speed_per_second: Vec<f64>,
+    errors_per_second: Vec<f64>,
+    char_number: usize,
+    start_time: Instant,
+    last_recorded_time: Instant,
+    timer: Duration,
+    game_started: bool,
+    game_over: bool,
+    failed: Option<&'static str>,
+    saved_results: bool,
+    saved_card: Option<String>,
+    leaderboard: leaderboard::Submitter,
+}
+
+impl Seat {
+    fn new(name: &str, reference: &str, leaderboard: Option<&leaderboard::Config>) -> Self {
+        Seat {
+            name: name.to_string(),
+            pressed_vec: Vec::new(),
+            is_correct: VecDeque::from(vec![0; reference.len()]),
+            pos1: 0,
+            words_done: 0,
+            error_positions: vec![false; reference.len()],
+            extra_chars: HashMap::new(),
+            keys: Vec::new(),
+            errors_this_second: 0.0,
+            speed_per_second: Vec::new(),
+            errors_per_second: Vec::new(),
+            char_number: 0,
+            start_time: Instant::now(),
+            last_recorded_time: Instant::now(),
+            timer: Duration::from_secs(0),
+            game_started: false,
+            game_over: false,
+            failed: None,
+            saved_results: false,
+            saved_card: None,
+            leaderboard: leaderboard::Submitter::new(leaderboard.cloned()),
+        }
+    }
+
+    // Handles this frame's key press for the player whose turn it is.
+    fn update(&mut self, reference: &str, strictness: utils::Strictness) {
+        let typed = main::handle_input(
+            reference,
+            &mut self.pressed_vec,
+            &mut self.is_correct,
+            &mut self.pos1,
+            &mut self.words_done,
+            &mut self.errors_this_second,
+            &mut false,
+            &mut self.error_positions,
+            &mut self.extra_chars,
+            main::TypingMode { practice_mode: false, practice_menu: false, code_mode: false, auto_indent: false, strictness },
+            &mut self.keys,
+            self.game_started.then_some(self.start_time),
+        );
+        if typed && !self.game_started {
+            self.game_started = true;
+            self.start_time = Instant::now();
+            self.last_recorded_time = Instant::now();
+        }
+        if !self.game_started {
+            return;
+        }
+        self.timer = self.start_time.elapsed();
+        let reference_chars: Vec<char> = reference.chars().collect();
+        self.failed = strictness.check(&reference_chars, &self.is_correct, self.pos1, utils::calc_accuracy(&self.is_correct));
+        if self.failed.is_some() || self.pos1 >= reference_chars.len() {
+            self.game_over = true;
+        }
+
+        if self.last_recorded_time.elapsed() >= Duration::from_secs(1) || self.game_over {
+            let total_typed = self.pressed_vec.len();
+            self.speed_per_second.push(total_typed.saturating_sub(self.char_number) as f64 * 60.0);
+            self.char_number = total_typed;
+            self.errors_per_second.push(self.errors_this_second);
+            self.errors_this_second = 0.0;
+            self.last_recorded_time += Duration::from_secs(1);
+        }
+    }
+
+    fn wpm(&self, reference: &str) -> f64 {
+        let (_, correct_words, _) = utils::count_correct_words(reference, &self.is_correct);
+        correct_words as f64 / (self.timer.as_secs_f64() / 60.0)
+    }
+}
+
+fn draw_seat(seat: &Seat, reference: &str, active: bool, font: &Font, font_size: f32, x: f32, width: f32) {
+    let lines = main::create_lines(&mut reference.to_string(), Some(font.clone()), font_size, width, false, true);
+    let line_h = measure_text("Gy", Some(font), font_size as u16, 1.0).height * 1.6;
+    let char_w = measure_text("G", Some(font), font_size as u16, 1.0).width.floor();
+    let start_y = screen_height() / 2.0 - lines.len() as f32 * font_size * 0.6 + font_size;
+
+    let status = if seat.game_over {
+        format!("{} - done", seat.name)
+    } else if active {
+        format!("{} - your turn", seat.name)
+    } else {
+        format!("{} - waiting", seat.name)
+    };
+    let color = if active { main::theme_color(theme::colors().main, 255) } else { main::theme_color(theme::colors().text, 120) };
+    draw_text_ex(&status, x, start_y - screen_height() / 8.0, TextParams { font: Some(font), font_size: font_size as u16, color, ..Default::default() });
+    main::draw_word_count(Some(font), font_size, x, start_y, &mut seat.words_done.clone(), reference.split_whitespace().count());
+
+    let hidden = vec![false; reference.chars().count()];
+    let view = main::ReferenceView { pos1: seat.pos1, is_correct: &seat.is_correct, extra_chars: &seat.extra_chars, syntax: &[], blind: false, hidden: &hidden };
+    main::draw_reference_text(&lines, &view, Some(font), font_size, x, start_y);
+    if active {
+        let chars_in_line: Vec<i32> = lines.iter().map(|line| line.chars().count() as i32).collect();
+        let (mut cursor_x, cursor_y) = main::calc_pos(&chars_in_line, seat.pos1);
+        cursor_x += (seat.pos1.saturating_sub(cursor_x)..=seat.pos1)
+            .filter_map(|i| seat.extra_chars.get(&i))
+            .map(|extra| extra.chars().count())
+            .sum::<usize>();
+        let show_cursor = seat.game_started || ((get_time() / 0.5) as i32) % 2 == 0;
+        if show_cursor {
+            main::draw_cursor(cursor_x, cursor_y, x, start_y, line_h, char_w);
+        }
+    }
+}
+
+// Two players take turns on the same text in one window, each round the other one starts.
+pub async fn hot_seat_async(strictness: utils::Strictness, seed: Option<u64>) {
+    let font = load_ttf_font_from_bytes(ROBOTO_MONO).unwrap();
+    let word_list = utils::read_first_n_words(500);
+    let mut test_rng = utils::TestRng::new(seed);
+    // the results only show what the players type, no punctuation or numbers
+    let settings = config::Settings::new(strictness, utils::Indicators::default(), Pace::default());
+    let leaderboard = leaderboard::load_config();
+    let mut reference = utils::get_reference(false, false, &word_list, WORDS, test_rng.rng());
+    let names = ["Player 1", "Player 2"];
+    let mut seats = names.map(|name| Seat::new(name, &reference, leaderboard.as_ref()));
+    let mut round = 1;
+    let mut turn = 0;
+    let mut wins = [0, 0];
+    let mut scored = false;
+
+    loop {
+        clear_background(main::theme_color(theme::colors().background, 255));
+        if is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+        let half = screen_width() / 2.0;
+        let font_size = if screen_width() > 1600.0 { 28.0 } else { 20.0 };
+
+        if seats.iter().all(|seat| seat.game_over) {
+            if !scored {
+                let wpm = seats.each_ref().map(|seat| if seat.failed.is_some() { 0.0 } else { seat.wpm(&reference) });
+                if wpm[0] != wpm[1] {
+                    wins[if wpm[0] > wpm[1] { 0 } else { 1 }] += 1;
+                }
+                scored = true;
+            }
+            let average_word_length = reference.split_whitespace().map(|word| word.len()).sum::<usize>() as f64 / WORDS as f64 + 1.0;
+            for (i, seat) in seats.iter_mut().enumerate() {
+                results::write_results(
+                    &seat.is_correct,
+                    half * i as f32,
+                    half,
+                    screen_height(),
+                    Some(&font),
+                    seat.timer.as_secs_f32(),
+                    &seat.speed_per_second,
+                    average_word_length,
+                    MODE,
+                    &settings,
+                    &seat.errors_per_second,
+                    &reference,
+                    None,
+                    &mut seat.saved_results,
+                    seat.failed,
+                    None,
+                    Some(test_rng.seed),
+                    &mut seat.saved_card,
+                    &mut seat.leaderboard,
+                    &reference,
+                    &seat.keys,
+                );
+                let name_width = measure_text(&seat.name, Some(&font), font_size as u16, 1.0).width;
+                draw_text_ex(&seat.name, half * i as f32 + (half - name_width) / 2.0, screen_height() / 10.0, TextParams { font: Some(&font), font_size: font_size as u16, color: main::theme_color(theme::colors().main, 255), ..Default::default() });
+            }
+            let score = format!("round {}  {} {} - {} {}  Enter - next round, Esc - quit", round, names[0], wins[0], wins[1], names[1]);
+            let score_width = measure_text(&score, Some(&font), font_size as u16, 1.0).width;
+            draw_text_ex(&score, (screen_width() - score_width) / 2.0, screen_height() - font_size * 2.0, TextParams { font: Some(&font), font_size: font_size as u16, color: main::theme_color(theme::colors().text, 120), ..Default::default() });
+
+            if is_key_pressed(KeyCode::Enter) {
+                reference = utils::get_reference(false, false, &word_list, WORDS, test_rng.restart());
+                seats = names.map(|name| Seat::new(name, &reference, leaderboard.as_ref()));
+                turn = round % 2;
+                round += 1;
+                scored = false;
+            }
+            // don't type the keys pressed on the results into the next round
+            while get_char_pressed().is_some() {}
+        } else {
+            seats[turn].update(&reference, strictness);
+            if seats[turn].game_over {
+                turn = 1 - turn;
+            }
+            for (i, seat) in seats.iter().enumerate() {
+                draw_seat(seat, &reference, i == turn && !seat.game_over, &font, font_size, half * i as f32 + half * 0.075, half * 0.85);
+            }
+            draw_line(half, screen_height() * 0.15, half, screen_height() * 0.85, 1.0, main::theme_color(theme::colors().main, 60));
+        }
+
+        next_frame().await;
+    }
+}
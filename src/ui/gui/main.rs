@@ -1,5 +1,5 @@
 use core::time;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use macroquad::prelude::*;
 use miniquad::window::set_mouse_cursor;
 use miniquad::CursorIcon;
@@ -10,24 +10,30 @@ use crate::utils;
 use crate::ui::gui::results;
 use crate::ui::gui::config::{self, reset_game_state};
 use crate::ui::gui::practice as gui_practice;
+use crate::ui::gui::custom as gui_custom;
 use crate::practice::{self, TYPING_LEVELS};
+use crate::custom::{self, CustomText};
+use crate::code::{self, Syntax};
+use crate::{daily, history, leaderboard, pace, theme};
+use crate::theme::{Mark, Rgb};
+use crate::pace::Pace;
+use crate::simulate::{self, Keystroke};
 
 
-pub const MAIN_COLOR: macroquad::color::Color = macroquad::color::Color::from_rgba(255, 155, 0, 255);
+// A colour of the current theme, with the alpha the spot it's drawn in uses.
+pub fn theme_color(rgb: Rgb, alpha: u8) -> macroquad::color::Color {
+    macroquad::color::Color::from_rgba(rgb.0, rgb.1, rgb.2, alpha)
+}
 
-const ROBOTO_MONO: &[u8] =
+pub const ROBOTO_MONO: &[u8] =
     include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/fonts/RobotoMono-VariableFont_wght.ttf"));
 
 const DEJAVU: &[u8] =
     include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/fonts/DejaVuSansCondensed.ttf"));
 
 
-pub async fn gui_main_async() {
-    let mut punctuation = false;
-    let mut numbers = false;
-    let mut quote = false;
-    let mut time_mode = true;
-    let mut word_mode = false;
+pub async fn gui_main_async(strictness: utils::Strictness, indicators: utils::Indicators, pace: Pace, seed: Option<u64>) {
+    let mut settings = config::Settings::new(strictness, indicators, pace);
 
     let font = load_ttf_font_from_bytes(ROBOTO_MONO).unwrap();
     let title_font = load_ttf_font_from_bytes(ROBOTO_MONO).unwrap();
@@ -35,16 +41,15 @@ pub async fn gui_main_async() {
 
     let top_words = 500;
     let word_list = utils::read_first_n_words(top_words as usize);
-    let mut batch_size = 50;
 
-    let mut reference = utils::get_reference(punctuation, false, &word_list, batch_size);
+    let mut test_rng = utils::TestRng::new(seed);
+    let mut reference = utils::get_reference(settings.punctuation, false, &word_list, settings.batch_size, test_rng.rng());
 
     let mut pressed_vec: Vec<char> = vec![];
     let mut is_correct: VecDeque<i32> = VecDeque::from(vec![0; reference.len()]);
     let mut pos1: usize = 0;
     let mut timer = time::Duration::from_secs(0);
     let mut start_time: Instant = Instant::now();
-    let mut test_time = 30.0;
     let mut game_started = false;
     let mut game_over = false;
 
@@ -63,10 +68,32 @@ pub async fn gui_main_async() {
     let mut selected_config: String = "time".to_string();
 
     let mut practice_menu = false;
-    let mut practice_mode = false;
     let mut scroll_offset: f32 = 0.0;
     let mut selected_practice_level: Option<usize> = None;
     let mut saved_results = false;
+    let mut saved_card: Option<String> = None;
+    let mut leaderboard = leaderboard::Submitter::new(leaderboard::load_config());
+
+    let mut custom_menu = false;
+    let mut custom_input = String::new();
+    let mut custom_message = String::new();
+    let mut custom_texts: Vec<CustomText> = vec![];
+    let mut selected_custom: usize = 0;
+    let mut custom_text: Option<CustomText> = None;
+
+    let mut daily_attempt = daily::MODE;
+    let mut daily_status: Option<String> = None;
+
+    // the pace's target, looked up once a test starts
+    let mut pace_wpm: Option<Option<f64>> = None;
+    let mut failed: Option<&'static str> = None;
+
+    let mut memory = utils::Memory::new();
+    let mut length_input: Option<String> = None;
+    let mut extra_chars: HashMap<usize, String> = HashMap::new();
+    // what a time test typed before the current batch, and every key, for the saved result
+    let mut done_batches = String::new();
+    let mut keys: Vec<Keystroke> = Vec::new();
 
     let words: Vec<&str> = reference.split_whitespace().collect();
     let average_word_length: f64 = if !words.is_empty() {
@@ -76,7 +103,7 @@ pub async fn gui_main_async() {
     };
     
     loop {
-        clear_background(macroquad::color::Color::from_rgba(15, 12, 10, 255));
+        clear_background(theme_color(theme::colors().background, 255));
         let mut max_width = f32::min(if screen_height() > screen_width() {screen_width() * 0.9} else {screen_width() * 0.7}, 1600.0);
         if screen_width() < 1000.0 || screen_height() < 600.0 {
             max_width = 0.85 * screen_width();
@@ -90,29 +117,39 @@ pub async fn gui_main_async() {
         };
         let line_h = measure_text("Gy", Some(&font.clone()), font_size as u16, 1.0).height * 1.6;
         let char_w = measure_text("G", Some(&font.clone()), font_size as u16, 1.0).width.floor();
-        lines = create_lines(&mut reference, Some(font.clone()), font_size, max_width, quote, word_mode);
+        lines = if settings.zen_mode {
+            create_lines(&mut reference, Some(font.clone()), font_size, max_width, true, settings.word_mode)
+        } else if settings.code_mode {
+            create_code_lines(&reference, Some(font.clone()), font_size, max_width)
+        } else {
+            create_lines(&mut reference, Some(font.clone()), font_size, max_width, settings.quote || settings.custom_mode, settings.word_mode)
+        };
 
         let mut chars_in_line: Vec<i32> = vec![];
         for line in &lines {
             chars_in_line.push(line.chars().count() as i32);
         }
 
+        let mut custom_menu_closed = false;
+        // Esc only cancels typing a custom length
+        let editing_length = length_input.is_some();
+
         if !game_started {
             last_recorded_time = Instant::now();
             timer = time::Duration::from_secs(0);
             start_time = Instant::now();
             pos1 = 0;
+            failed = None;
+            extra_chars.clear();
+            done_batches.clear();
+            keys.clear();
         }
         
-        if !game_over && !practice_menu {
+        if !game_over && !practice_menu && !custom_menu {
             let any_button_hovered = config::handle_settings_buttons(
                 &Option::Some(font.clone()),
                 &word_list,
-                &mut punctuation,
-                &mut numbers,
-                &mut quote,
-                &mut time_mode,
-                &mut word_mode,
+                &mut settings,
                 &mut pressed_vec,
                 &mut is_correct,
                 &mut pos1,
@@ -121,8 +158,6 @@ pub async fn gui_main_async() {
                 &mut game_started,
                 &mut game_over,
                 &mut reference,
-                &mut test_time,
-                &mut batch_size,
                 screen_width() / 2.0 - max_width / 2.0,
                 &mut speed_per_second,
                 &mut last_recorded_time,
@@ -133,11 +168,19 @@ pub async fn gui_main_async() {
                 &mut selected_config,
                 &mut practice_menu,
                 &mut selected_practice_level,
-                &mut practice_mode,
                 &mut saved_results,
                 &mut error_positions,
+                &mut custom_menu,
+                &mut length_input,
+                &mut test_rng,
             );
 
+            if custom_menu {
+                custom_input.clear();
+                custom_message.clear();
+                custom_texts = custom::recent_texts();
+                selected_custom = 0;
+            }
             
             set_mouse_cursor(if any_button_hovered {
                 CursorIcon::Pointer
@@ -146,7 +189,7 @@ pub async fn gui_main_async() {
             });
             
             config::update_game_state(
-                &reference,
+                &mut reference,
                 &mut pressed_vec,
                 &mut is_correct,
                 &mut pos1,
@@ -154,26 +197,28 @@ pub async fn gui_main_async() {
                 &mut start_time,
                 &mut game_started,
                 &mut game_over,
-                test_time,
-                time_mode,
                 &mut words_done,
                 &mut errors_this_second,
-                &mut practice_mode,
-                practice_menu
+                practice_menu,
+                &settings,
+                &mut keys,
             );
-
-            if !game_started && handle_input(&reference, &mut pressed_vec, &mut is_correct, &mut pos1, &mut words_done, &mut errors_this_second, &mut config_opened, &mut error_positions, practice_mode, practice_menu) {
-                game_started = true;
-            }
-            
-            if (game_started || words_done == batch_size) && !game_over {
-                timer = start_time.elapsed();
-                if (timer.as_secs_f32() >= test_time - 0.2 && time_mode) || pos1 >= reference.chars().count() {
-                    game_over = true;
+            if !settings.zen_mode {
+                if !game_started && handle_input(&reference, &mut pressed_vec, &mut is_correct, &mut pos1, &mut words_done, &mut errors_this_second, &mut config_opened, &mut error_positions, &mut extra_chars, settings.typing_mode(practice_menu), &mut keys, None) {
+                    game_started = true;
                 }
-            }            
             
-            let total_height = lines.len() as f32 * font_size * 1.2;
+                if (game_started || words_done == settings.batch_size) && !game_over {
+                    timer = start_time.elapsed();
+                    if (timer.as_secs_f32() >= settings.test_time - 0.2 && settings.time_mode) || pos1 >= reference.chars().count() {
+                        game_over = true;
+                    }
+                }
+            }
+
+            // Zen text only grows, keep the latest lines on screen
+            let hidden_lines = if settings.zen_mode { lines.len().saturating_sub(5) } else { 0 };
+            let total_height = (lines.len() - hidden_lines) as f32 * font_size * 1.2;
             let start_y = screen_height() / 2.0 - total_height / 2.0 + font_size;
             let start_x = screen_width() / 2.0 - max_width / 2.0;
             let title_y = screen_height() / 7.5;
@@ -189,28 +234,73 @@ pub async fn gui_main_async() {
                 title_y,
             );
             
-            handle_input(&reference, &mut pressed_vec, &mut is_correct, &mut pos1, &mut words_done, &mut errors_this_second, &mut config_opened, &mut error_positions, practice_mode, practice_menu);
+            if !settings.zen_mode {
+                handle_input(&reference, &mut pressed_vec, &mut is_correct, &mut pos1, &mut words_done, &mut errors_this_second, &mut config_opened, &mut error_positions, &mut extra_chars, settings.typing_mode(practice_menu), &mut keys, game_started.then_some(start_time));
+            }
+            if game_started && !game_over && !settings.zen_mode {
+                let reference_chars: Vec<char> = reference.chars().collect();
+                failed = settings.strictness.check(&reference_chars, &is_correct, pos1, utils::calc_accuracy(&is_correct));
+                if failed.is_some() {
+                    timer = start_time.elapsed();
+                    game_over = true;
+                }
+            }
             
-            if time_mode {
-                draw_timer(Some(&font.clone()), font_size, start_x, start_y, timer, test_time);
-            } else if word_mode {
-                draw_word_count(Some(&font.clone()), font_size, start_x, start_y, &mut words_done, batch_size);
-            } else if practice_mode {
+            if settings.time_mode {
+                draw_timer(Some(&font.clone()), font_size, start_x, start_y, timer, settings.test_time);
+            } else if settings.word_mode {
+                draw_word_count(Some(&font.clone()), font_size, start_x, start_y, &mut words_done, settings.batch_size);
+            } else if settings.practice_mode {
                 draw_word_count(Some(&font.clone()), font_size, start_x, start_y, &mut words_done, 50);
-            } else if quote {
+            } else if settings.quote || settings.custom_mode {
                 draw_word_count(Some(&font.clone()), font_size, start_x, start_y, &mut words_done, reference.split_whitespace().count());
+            } else if settings.code_mode {
+                draw_word_count(Some(&font.clone()), font_size, start_x, start_y, &mut code::lines_done(&reference, pos1), code::count_lines(&reference));
+            } else if settings.zen_mode {
+                draw_live_wpm(Some(&font.clone()), font_size, start_x, start_y, timer, words_done);
             }
+            let live = settings.indicators.text(utils::live_wpm(words_done, timer.as_secs_f64()), utils::calc_accuracy(&is_correct));
+            let progress = (settings.indicators.progress && !settings.zen_mode)
+                .then(|| utils::live_progress(settings.time_mode.then_some(settings.test_time as f64), timer.as_secs_f64(), pos1, reference.chars().count()));
+            draw_live_indicators(Some(&font.clone()), font_size, start_x, start_y, max_width, &live, progress);
 
+            let syntax = if settings.code_mode {
+                code::highlight(&reference, &settings.code_language)
+            } else {
+                Vec::new()
+            };
+            let reference_chars: Vec<char> = reference.chars().collect();
+            memory.update(&reference_chars, pos1, game_started);
+            let hidden: Vec<bool> = (0..reference_chars.len())
+                .map(|i| settings.memory_mode && !settings.zen_mode && !settings.practice_mode && memory.hidden(&reference_chars, pos1, i))
+                .collect();
+            let view = ReferenceView {
+                pos1,
+                is_correct: &is_correct,
+                extra_chars: &extra_chars,
+                syntax: &syntax,
+                blind: settings.blind_mode && !settings.zen_mode && !settings.practice_mode,
+                hidden: &hidden,
+            };
             draw_reference_text(
-                &lines,
-                &pressed_vec,
-                &is_correct,
+                &lines[hidden_lines..],
+                &view,
                 Some(&font.clone()),
                 font_size,
                 start_x,
                 start_y,
             );
-            let (calc_pos_x, calc_pos_y) = calc_pos(&chars_in_line, pos1);
+            let (mut calc_pos_x, mut calc_pos_y) = calc_pos(&chars_in_line, pos1);
+            calc_pos_x += (pos1.saturating_sub(calc_pos_x)..=pos1)
+                .filter_map(|i| extra_chars.get(&i))
+                .map(|extra| extra.chars().count())
+                .sum::<usize>();
+            if settings.zen_mode {
+                calc_pos_y -= hidden_lines;
+                if reference.ends_with(' ') {
+                    calc_pos_x += 1;
+                }
+            }
             if !game_started {
                 let blink_interval = 0.5;
                 let show_cursor = ((get_time() / blink_interval) as i32) % 2 == 0;
@@ -221,6 +311,17 @@ pub async fn gui_main_async() {
                 draw_cursor(calc_pos_x, calc_pos_y, start_x, start_y, line_h, char_w);
             }
 
+            if !game_started {
+                pace_wpm = None;
+            } else if !game_over && !settings.zen_mode {
+                let target = *pace_wpm.get_or_insert_with(|| settings.pace.target(&history::load(), settings.mode().name()));
+                let position = target.map(|wpm| pace::position(wpm, timer.as_secs_f64(), &reference));
+                if let Some(position) = position.filter(|&position| position != pos1 && position < reference.chars().count()) {
+                    let (pace_x, pace_y) = calc_pos(&chars_in_line, position);
+                    draw_pace_cursor(pace_x, pace_y, start_x, start_y, line_h, char_w);
+                }
+            }
+
             let now = Instant::now();
             let time_since_last = now.duration_since(last_recorded_time);
 
@@ -241,17 +342,29 @@ pub async fn gui_main_async() {
 
         }  
         else if game_over {
-            let mode = if time_mode {
-                "time".to_string()
-            } else if word_mode {
-                "word".to_string()
-            } else if quote {
-                "quote".to_string()
+            let mode = if settings.daily_mode {
+                // decided before the attempt is saved, so it doesn't count itself
+                if !saved_results {
+                    daily_attempt = daily::attempt_mode(&history::load(), daily::today());
+                    daily_status = None;
+                }
+                daily_attempt
+            } else {
+                settings.mode().name()
+            };
+
+            let first_results_frame = !saved_results;
+
+            // the batch that was just generated after finishing one isn't part of the test
+            let typed_reference = if settings.code_mode || settings.zen_mode {
+                String::new()
+            } else if pos1 > 0 {
+                format!("{}{}", done_batches, reference)
             } else {
-                "practice".to_string()
+                done_batches.clone()
             };
 
-            let practice_level = if !practice_mode {
+            let practice_level = if !settings.practice_mode {
                 None
             } else {
                 selected_practice_level
@@ -259,27 +372,44 @@ pub async fn gui_main_async() {
             
             results::write_results(
                 &is_correct,
+                0.0,
                 screen_width(),
                 screen_height(),
                 Some(&title_font.clone()),
                 timer.as_secs_f32(),
                 &speed_per_second,
                 average_word_length,
-                &mode,
-                punctuation,
-                numbers,
+                mode,
+                &settings,
                 &errors_per_second,
                 &reference,
                 practice_level,
                 &mut saved_results,
+                failed,
+                daily_status.as_deref().filter(|_| settings.daily_mode),
+                if settings.daily_mode { Some(daily::seed(daily::today())) } else { (!settings.custom_mode && !settings.zen_mode).then_some(test_rng.seed) },
+                &mut saved_card,
+                &mut leaderboard,
+                &typed_reference,
+                &keys,
             );
+
+            if settings.daily_mode && first_results_frame {
+                daily_status = Some(daily::status(mode));
+            }
+
+            if settings.custom_mode && first_results_frame
+                && let Some(custom_text) = custom_text.as_mut()
+            {
+                custom::advance(custom_text);
+            }
             
             if is_key_pressed(KeyCode::Q) {
                 if practice_menu {
                     practice_menu = false;
-                    practice_mode = false;
+                    settings.practice_mode = false;
                     game_over = false;
-                    time_mode = true;
+                    settings.time_mode = true;
                     reset_game_state(&mut pressed_vec, &mut is_correct, &mut pos1, &mut timer, &mut start_time, &mut game_started, &mut game_over, &mut speed_per_second, &mut last_recorded_time, &mut words_done, &mut errors_per_second, &mut saved_results, &mut error_positions);
                     reset_game_state(&mut pressed_vec, &mut is_correct, &mut pos1, &mut timer, &mut start_time, &mut game_started, &mut game_over, &mut speed_per_second, &mut last_recorded_time, &mut words_done, &mut errors_per_second, &mut saved_results, &mut error_positions);
                 }
@@ -293,7 +423,7 @@ pub async fn gui_main_async() {
                 emoji_font.clone(),
                 &mut selected_practice_level,
                 &mut practice_menu,
-                &mut time_mode,
+                &mut settings.time_mode,
                 &mut pressed_vec,
                 &mut is_correct,
                 &mut pos1,
@@ -324,15 +454,61 @@ pub async fn gui_main_async() {
                     &mut saved_results,
                     &mut error_positions,
                 );
-                reference = practice::create_words(TYPING_LEVELS[level.unwrap()].1, 50);
+                reference = practice::create_words(TYPING_LEVELS[level.unwrap()].1, 50, test_rng.restart());
                 is_correct = VecDeque::from(vec![0; reference.len()]);
                 error_positions = vec![false; is_correct.len()];
-                practice_mode = true;
+                settings.practice_mode = true;
                 practice_menu = false;
                 config_opened = false;
             }
+        } else if custom_menu {
+            let loaded = gui_custom::display_custom_menu(
+                Some(font.clone()),
+                &mut custom_input,
+                &custom_message,
+                &custom_texts,
+                &mut selected_custom,
+                &mut custom_menu,
+            );
+            match loaded {
+                Some(Ok(text)) => {
+                    let text = custom::remember(text);
+                    reference = text.current_chunk();
+                    custom_text = Some(text);
+                    settings.custom_mode = true;
+                    custom_menu = false;
+                    settings.code_mode = false;
+                    settings.zen_mode = false;
+                    settings.daily_mode = false;
+                    settings.time_mode = false;
+                    settings.word_mode = false;
+                    settings.quote = false;
+                    settings.practice_mode = false;
+                    config_opened = false;
+                    is_correct = VecDeque::from(vec![0; reference.len()]);
+                    config::reset_game_state(
+                        &mut pressed_vec,
+                        &mut is_correct,
+                        &mut pos1,
+                        &mut timer,
+                        &mut start_time,
+                        &mut game_started,
+                        &mut game_over,
+                        &mut speed_per_second,
+                        &mut last_recorded_time,
+                        &mut words_done,
+                        &mut errors_per_second,
+                        &mut saved_results,
+                        &mut error_positions,
+                    );
+                }
+                Some(Err(err)) => custom_message = err,
+                None => {}
+            }
+            // Esc closes the menu, don't quit with the same key press
+            custom_menu_closed = !custom_menu;
         }
-        if is_key_down(KeyCode::Escape) {
+        if is_key_pressed(KeyCode::Escape) && !custom_menu_closed && !editing_length {
             break;
         }
 
@@ -352,27 +528,37 @@ pub async fn gui_main_async() {
                 &mut saved_results,
                 &mut error_positions,
             );
-            if practice_mode {
-                reference = practice::create_words(TYPING_LEVELS[selected_practice_level.unwrap_or(0)].1, 50);
-            } else if quote {
-                reference = utils::get_random_quote();
+            if settings.practice_mode {
+                reference = practice::create_words(TYPING_LEVELS[selected_practice_level.unwrap_or(0)].1, 50, test_rng.restart());
+            } else if settings.quote {
+                reference = utils::get_random_quote(test_rng.restart());
+            } else if let Some(custom_text) = custom_text.as_ref().filter(|_| settings.custom_mode) {
+                reference = custom_text.current_chunk();
+            } else if settings.code_mode {
+                reference = code::get_snippet(&settings.code_language, test_rng.restart());
+            } else if settings.zen_mode {
+                reference.clear();
+            } else if settings.daily_mode {
+                reference = daily::reference(daily::today());
             } else {
-                reference = utils::get_reference(punctuation, false, &word_list, batch_size);
+                reference = utils::get_reference(settings.punctuation, false, &word_list, settings.batch_size, test_rng.restart());
             }
             is_correct = VecDeque::from(vec![0; reference.len()]);
             error_positions = vec![false; is_correct.len()];
             thread::sleep(time::Duration::from_millis(80));
         }
 
-        if pos1 >= reference.chars().count() && time_mode && !game_over{
+        if pos1 >= reference.chars().count() && settings.time_mode && !game_over{
             words_done += 1;
-            reference = utils::get_reference(punctuation, numbers, &utils::read_first_n_words(500), batch_size);
+            done_batches.push_str(&reference);
+            reference = utils::get_reference(settings.punctuation, settings.numbers, &utils::read_first_n_words(500), settings.batch_size, test_rng.rng());
             is_correct = VecDeque::from(vec![0; reference.len()]);
             error_positions = vec![false; is_correct.len()];
             pos1 = 0;
+            extra_chars.clear();
         }
 
-        draw_shortcut_info(Some(&font.clone()), f32::max(font_size / 1.7, 11.0), screen_width() / 2.0 - max_width / 2.0, screen_height() - screen_height() / 7.5, emoji_font.clone(), practice_menu, game_over, practice_mode);
+        draw_shortcut_info(Some(&font.clone()), f32::max(font_size / 1.7, 11.0), screen_width() / 2.0 - max_width / 2.0, screen_height() - screen_height() / 7.5, emoji_font.clone(), practice_menu, game_over, settings.practice_mode);
 
         next_frame().await;
     }
@@ -383,8 +569,8 @@ fn write_title(font: Option<Font>, font_size: f32, x: f32, y: f32) {
     let type_width = measure_text(type_text, font.as_ref(), font_size as u16, 1.0).width;
 
     for (text, color, dx) in [
-        (type_text, MAIN_COLOR, 0.0),
-        (man_text, macroquad::color::Color::from_rgba(255, 255, 255, 220), type_width),
+        (type_text, theme_color(theme::colors().main, 255), 0.0),
+        (man_text, theme_color(theme::colors().text, 220), type_width),
         ] {
             draw_text_ex(
                 text,
@@ -427,7 +613,7 @@ fn draw_shortcut_info(
         ]
     } else if game_over {
         x /= 2.0;
-        vec!["Tab + Enter - reset"]
+        vec!["Tab + Enter - reset", "Ctrl + S - save result card (+ Shift for SVG)"]
     } else {
         vec![
             "↑ to navigate to config, ← → to change settings (or click)",
@@ -461,7 +647,7 @@ fn draw_shortcut_info(
                     TextParams {
                         font: Some(&emoji_font),
                         font_size: font_size as u16,
-                        color: Color::from_rgba(255, 255, 255, 80),
+                        color: theme_color(theme::colors().reference, 255),
                         ..Default::default()
                     },
                 );
@@ -484,7 +670,7 @@ fn draw_shortcut_info(
                     TextParams {
                         font,
                         font_size: font_size as u16,
-                        color: Color::from_rgba(255, 255, 255, 80),
+                        color: theme_color(theme::colors().reference, 255),
                         ..Default::default()
                     },
                 );
@@ -500,7 +686,7 @@ fn draw_shortcut_info(
         TextParams {
             font,
             font_size: font_size as u16,
-            color: Color::from_rgba(255, 255, 255, 80),
+            color: theme_color(theme::colors().reference, 255),
             ..Default::default()
         },
     );
@@ -560,6 +746,33 @@ pub fn create_lines(reference: &mut String, font: Option<Font>, font_size: f32,
     lines
 }
     
+// Wraps code as many characters wide as fit in `max_width`.
+pub fn create_code_lines(reference: &str, font: Option<Font>, font_size: f32, max_width: f32) -> Vec<String> {
+    let char_w = measure_text("G", font.as_ref(), font_size as u16, 1.0).width;
+    code::split_lines(reference, (max_width / char_w) as usize)
+}
+
+// What a key press in a test changes.
+pub struct TypingState<'a> {
+    pub pressed_vec: &'a mut Vec<char>,
+    pub is_correct: &'a mut VecDeque<i32>,
+    pub pos1: &'a mut usize,
+    pub words_done: &'a mut usize,
+    pub errors_this_second: &'a mut f64,
+    pub error_positions: &'a mut Vec<bool>,
+    pub extra_chars: &'a mut HashMap<usize, String>,
+}
+
+// The settings that decide how a key press in a test is scored.
+#[derive(Debug, Clone, Copy)]
+pub struct TypingMode {
+    pub practice_mode: bool,
+    pub practice_menu: bool,
+    pub code_mode: bool,
+    pub auto_indent: bool,
+    pub strictness: utils::Strictness,
+}
+
 pub fn handle_input(
     reference: &str,
     pressed_vec: &mut Vec<char>,
@@ -569,55 +782,256 @@ pub fn handle_input(
     errors_this_second: &mut f64,
     config_opened: &mut bool,
     error_positions: &mut Vec<bool>,
-    practice_mode: bool,
-    practice_menu: bool,
+    extra_chars: &mut HashMap<usize, String>,
+    mode: TypingMode,
+    keys: &mut Vec<Keystroke>,
+    start: Option<Instant>,
 ) -> bool {
-    let pressed = get_char_pressed();
-    if let Some(ch) = pressed {
-        *config_opened = false;
-        if ch == '\t' || ch == '\n' || ch == '\r' {
-            return false;
+    let Some(ch) = get_char_pressed() else {
+        return false;
+    };
+    *config_opened = false;
+    let modifier_down = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl)
+        || is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt);
+    let state = TypingState { pressed_vec, is_correct, pos1, words_done, errors_this_second, error_positions, extra_chars };
+    let typed = apply_key(ch, modifier_down, reference, state, mode);
+    if typed {
+        log_key(keys, start, if is_word_delete(ch, modifier_down) { '\u{17}' } else { ch });
+    }
+    typed
+}
+
+// Keys are logged in ms from the start of the test, the key that starts it is at 0.
+fn log_key(keys: &mut Vec<Keystroke>, start: Option<Instant>, key: char) {
+    let at = start.map_or(0, |start| start.elapsed().as_millis() as u64);
+    keys.push(Keystroke { at, key: simulate::key_name(key) });
+}
+
+// Ctrl+Backspace arrives as DEL or Ctrl+W on some platforms, a DEL without Ctrl or Alt is a plain Backspace
+fn is_word_delete(ch: char, modifier_down: bool) -> bool {
+    ch == '\u{17}' || (matches!(ch, '\u{8}' | '\u{7f}') && modifier_down)
+}
+
+// Scores one typed character against the reference, returns whether the test changed.
+pub fn apply_key(ch: char, modifier_down: bool, reference: &str, state: TypingState, mode: TypingMode) -> bool {
+    let TypingState { pressed_vec, is_correct, pos1, words_done, errors_this_second, error_positions, extra_chars } = state;
+    let TypingMode { practice_mode, practice_menu, code_mode, auto_indent, strictness } = mode;
+    let reference_chars: Vec<char> = reference.chars().collect();
+    let ch = if code_mode && ch == '\r' { '\n' } else { ch };
+    if ch == '\t' && code_mode && !auto_indent {
+        let indent = code::tab_spaces(&reference_chars, *pos1);
+        for _ in 0..indent {
+            is_correct[*pos1] = if is_correct[*pos1] == -1 || is_correct[*pos1] == 1 { 1 } else { 2 };
+            pressed_vec.push(' ');
+            *pos1 += 1;
         }
-        if ch == '\u{8}' {
-            // Backspace
-            if !pressed_vec.is_empty() && reference.chars().nth(*pos1) == Some(' ') {
-            *words_done -= 1;
-            }
-            pressed_vec.pop();
-            if *pos1 > 0 {
-            *pos1 -= 1;
-            }
-        } else if ch == '\u{7f}' {
-            // Delete
+        return indent > 0;
+    }
+    if ch == '\t' || ch == '\r' || (ch == '\n' && !code_mode) {
+        return false;
+    }
+    let word_delete = is_word_delete(ch, modifier_down);
+    let backspace_key = ch == '\u{8}' || ch == '\u{7f}';
+    if (backspace_key || word_delete) && !strictness.error_mode.allows_backspace() {
+        return false;
+    }
+    if word_delete {
+        delete_word(&reference_chars, pressed_vec, is_correct, pos1, words_done, extra_chars, true);
+    } else if backspace_key {
+        // Backspace
+        backspace(&reference_chars, pressed_vec, is_correct, pos1, words_done, extra_chars);
+    } else {
+        if ch == 'q' && practice_menu {
             return false;
-        } else {
-            if ch == 'q' && practice_menu {
-                return false;
-            }
-            let ref_char: Option<char> = reference.chars().nth(*pos1);
-            if is_correct.len() > *pos1 && ref_char == Some(ch) && is_correct[*pos1] != -1 && is_correct[*pos1] != 1 {
-                is_correct[*pos1] = 2; // Correct
-            } else if is_correct.len() > *pos1 && ref_char == Some(ch) && (is_correct[*pos1] == -1 || is_correct[*pos1] == 1) {
-                is_correct[*pos1] = 1; // Corrected
-            } else if is_correct.len() > *pos1 {
-                is_correct[*pos1] = -1; // Incorrect
-                error_positions[*pos1] = true;
-                *errors_this_second += 1.0;
-            }
-            if practice_mode && is_correct.len() > *pos1 && is_correct[*pos1] == -1 {
+        }
+        if *pos1 >= is_correct.len() {
+            return true;
+        }
+        let error_mode = if practice_mode { utils::ErrorMode::StopOnLetter } else { strictness.error_mode };
+        match utils::word_key(error_mode, &reference_chars, *pos1, ch) {
+            utils::WordKey::Ignore => return false,
+            utils::WordKey::Extra => {
+                let extra = extra_chars.entry(*pos1).or_default();
+                if extra.chars().count() < utils::MAX_EXTRA_CHARS {
+                    extra.push(ch);
+                    pressed_vec.push(ch);
+                    *errors_this_second += 1.0;
+                }
+                if strictness.delete_word_on_error {
+                    delete_word(&reference_chars, pressed_vec, is_correct, pos1, words_done, extra_chars, false);
+                }
                 return true;
             }
-            *pos1 += 1;
-            pressed_vec.push(ch);
-            if reference.chars().nth(*pos1) == Some(' ') {
+            utils::WordKey::Skip(word_end) => {
+                is_correct.range_mut(*pos1..word_end).for_each(|c| *c = 4); // Missed
+                *pos1 = word_end;
                 *words_done += 1;
             }
+            utils::WordKey::Normal => {}
         }
-        return true;
+        let accepted = error_mode.accepts(&reference_chars, &*is_correct, *pos1, ch);
+        if accepted && is_correct[*pos1] != -1 && is_correct[*pos1] != 1 {
+            is_correct[*pos1] = 2; // Correct
+        } else if accepted {
+            is_correct[*pos1] = 1; // Corrected
+        } else {
+            is_correct[*pos1] = -1; // Incorrect
+            error_positions[*pos1] = true;
+            *errors_this_second += 1.0;
+        }
+        if accepted && extra_chars.get(pos1).is_some_and(|extra| !extra.is_empty()) {
+            is_correct[*pos1] = 5; // Space after extra letters
+        }
+        if !error_mode.advances(&reference_chars, *pos1, accepted) {
+            if strictness.delete_word_on_error && !practice_mode {
+                delete_word(&reference_chars, pressed_vec, is_correct, pos1, words_done, extra_chars, false);
+            }
+            return true;
+        }
+        *pos1 += 1;
+        pressed_vec.push(ch);
+        if utils::is_word_end(&reference_chars, *pos1) {
+            *words_done += 1;
+        }
+        if !accepted && strictness.delete_word_on_error && !practice_mode {
+            delete_word(&reference_chars, pressed_vec, is_correct, pos1, words_done, extra_chars, false);
+            return true;
+        }
+        if code_mode && auto_indent {
+            let indent_end = code::indentation_end(&reference_chars, *pos1);
+            // Skipped indentation
+            is_correct.range_mut(*pos1..indent_end).for_each(|c| *c = 3);
+            *pos1 = indent_end;
+        }
+    }
+    true
+}
+fn backspace(
+    reference_chars: &[char],
+    pressed_vec: &mut Vec<char>,
+    is_correct: &mut VecDeque<i32>,
+    pos1: &mut usize,
+    words_done: &mut usize,
+    extra_chars: &mut HashMap<usize, String>,
+) {
+    if let Some(extra) = extra_chars.get_mut(pos1)
+        && extra.pop().is_some()
+    {
+        pressed_vec.pop();
+        return;
+    }
+    while *pos1 > 0 && is_correct[*pos1 - 1] == 3 {
+        *pos1 -= 1;
+        is_correct[*pos1] = 0;
+    }
+    if !pressed_vec.is_empty() && utils::is_word_end(reference_chars, *pos1) {
+        *words_done = words_done.saturating_sub(1);
+    }
+    pressed_vec.pop();
+    if *pos1 > 0 {
+        *pos1 -= 1;
+    }
+    // back to where a skipped word was left
+    if *pos1 > 0 && is_correct[*pos1 - 1] == 4 {
+        *words_done = words_done.saturating_sub(1);
+        while *pos1 > 0 && is_correct[*pos1 - 1] == 4 {
+            *pos1 -= 1;
+            is_correct[*pos1] = 0;
+        }
+    }
+}
+
+// Removes extra letters and the current word, or the previous word when the cursor is at the start of one.
+fn delete_word(
+    reference_chars: &[char],
+    pressed_vec: &mut Vec<char>,
+    is_correct: &mut VecDeque<i32>,
+    pos1: &mut usize,
+    words_done: &mut usize,
+    extra_chars: &mut HashMap<usize, String>,
+    into_previous: bool,
+) {
+    let has_extra = |extra_chars: &HashMap<usize, String>, pos1: usize| extra_chars.get(&pos1).is_some_and(|extra| !extra.is_empty());
+    if into_previous && !has_extra(extra_chars, *pos1) && *pos1 > 0 && reference_chars[*pos1 - 1].is_whitespace() {
+        backspace(reference_chars, pressed_vec, is_correct, pos1, words_done, extra_chars);
+    }
+    while has_extra(extra_chars, *pos1) || (*pos1 > 0 && !reference_chars[*pos1 - 1].is_whitespace()) {
+        backspace(reference_chars, pressed_vec, is_correct, pos1, words_done, extra_chars);
     }
-    false
 }
     
+// Zen mode has no reference, every typed character is appended to it and counted as correct.
+pub fn handle_zen_input(
+    reference: &mut String,
+    pressed_vec: &mut Vec<char>,
+    is_correct: &mut VecDeque<i32>,
+    pos1: &mut usize,
+    words_done: &mut usize,
+    keys: &mut Vec<Keystroke>,
+    start: Option<Instant>,
+) -> bool {
+    let Some(ch) = get_char_pressed() else {
+        return false;
+    };
+    if ch == '\u{8}' {
+        // Backspace
+        if let Some(removed) = reference.pop() {
+            log_key(keys, start, ch);
+            if removed == ' ' && !reference.ends_with(' ') {
+                *words_done = words_done.saturating_sub(1);
+            }
+            is_correct.pop_back();
+            pressed_vec.pop();
+            *pos1 -= 1;
+        }
+        return false;
+    }
+    let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+    if ch.is_control() || ctrl || (ch == ' ' && reference.is_empty()) {
+        return false;
+    }
+    if ch == ' ' && !reference.ends_with(' ') {
+        *words_done += 1;
+    }
+    log_key(keys, start, ch);
+    reference.push(ch);
+    is_correct.push_back(2);
+    pressed_vec.push(ch);
+    *pos1 += 1;
+    true
+}
+
+fn draw_live_wpm(font: Option<&Font>, font_size: f32, start_x: f32, start_y: f32, timer: time::Duration, words_done: usize) {
+    let wpm = utils::live_wpm(words_done, timer.as_secs_f64());
+    draw_text_ex(
+        &format!("{:.0} wpm  {}s", wpm, timer.as_secs()),
+        start_x,
+        start_y - 2.0 * font_size,
+        TextParams {
+            font,
+            font_size: font_size as u16,
+            color: theme_color(theme::colors().main, 255),
+            ..Default::default()
+        },
+    );
+}
+
+// Right aligned above the text, the progress bar left of the numbers.
+fn draw_live_indicators(font: Option<&Font>, font_size: f32, start_x: f32, start_y: f32, max_width: f32, live: &str, progress: Option<f64>) {
+    let color = theme_color(theme::colors().main, 255);
+    let text_width = measure_text(live, font, font_size as u16, 1.0).width;
+    let y = start_y - 2.0 * font_size;
+    draw_text_ex(live, start_x + max_width - text_width, y, TextParams { font, font_size: font_size as u16, color, ..Default::default() });
+    if let Some(progress) = progress {
+        let bar_width = max_width / 4.0;
+        let gap = if live.is_empty() { 0.0 } else { font_size };
+        let bar_x = start_x + max_width - text_width - gap - bar_width;
+        let bar_y = y - font_size * 0.35;
+        draw_rectangle(bar_x, bar_y, bar_width, font_size * 0.2, theme_color(theme::colors().text, 40));
+        draw_rectangle(bar_x, bar_y, bar_width * progress as f32, font_size * 0.2, color);
+    }
+}
+
 fn draw_timer(font: Option<&Font>, font_size: f32, start_x: f32, start_y: f32, timer: time::Duration, test_time: f32) {
     let timer_str = format!("{:.0}", test_time - timer.as_secs_f32());
     draw_text_ex(
@@ -627,13 +1041,13 @@ fn draw_timer(font: Option<&Font>, font_size: f32, start_x: f32, start_y: f32, t
         TextParams {
             font,
             font_size: font_size as u16,
-            color: macroquad::color::Color::from_rgba(255, 155, 0, 255),
+            color: theme_color(theme::colors().main, 255),
             ..Default::default()
         },
     );
 }
 
-fn draw_word_count(font: Option<&Font>, font_size: f32, start_x: f32, start_y: f32, words_done: &mut usize, total_words: usize) {
+pub fn draw_word_count(font: Option<&Font>, font_size: f32, start_x: f32, start_y: f32, words_done: &mut usize, total_words: usize) {
     let timer_str = format!("{}/{}", words_done, total_words);
     draw_text_ex(
         &timer_str,
@@ -642,55 +1056,73 @@ fn draw_word_count(font: Option<&Font>, font_size: f32, start_x: f32, start_y: f
         TextParams {
             font,
             font_size: font_size as u16,
-            color: macroquad::color::Color::from_rgba(255, 155, 0, 255),
+            color: theme_color(theme::colors().main, 255),
             ..Default::default()
         },
     );
 }
     
-fn draw_reference_text(
+// What the reference is drawn with: how far the test got, the scored and extra letters, and how letters are shown.
+pub struct ReferenceView<'a> {
+    pub pos1: usize,
+    pub is_correct: &'a VecDeque<i32>,
+    pub extra_chars: &'a HashMap<usize, String>,
+    pub syntax: &'a [Syntax],
+    pub blind: bool,
+    pub hidden: &'a [bool],
+}
+
+pub fn draw_reference_text(
     lines: &[String],
-    pressed_vec: &[char],
-    is_correct: &VecDeque<i32>,
+    view: &ReferenceView,
     font: Option<&Font>,
     font_size: f32,
     start_x: f32,
     start_y: f32,
 ) {
+    let ReferenceView { pos1, is_correct, extra_chars, syntax, blind, hidden } = *view;
     let mut pos = 0;
     let mut pos_y = 0.0;
+    let markers = theme::markers();
 
     for line in lines.iter() {
         let mut pos_x = 0;
         for char in line.chars() {
-            let mut curr_char = char;
-            let color = if pos + 1 > pressed_vec.len() || is_correct[pos] == 0 {
-                macroquad::color::Color::from_rgba(255, 255, 255, 80)
+            // letters typed past the end of the word overflow inline before its space
+            if let Some(extra) = extra_chars.get(&pos) {
+                let (color, mark) = if blind {
+                    (theme_color(theme::colors().text, 200), None)
+                } else {
+                    (theme_color(theme::colors().extra, 220), markers.error())
+                };
+                for extra_char in extra.chars() {
+                    pos_x += draw_marked(&extra_char.to_string(), pos_x as f32 + start_x, pos_y + start_y, font, font_size, color, mark) as usize;
+                }
+            }
+            let mut curr_char = if char == '\n' { '¶' } else if hidden.get(pos).is_some_and(|&h| h) { '_' } else { char };
+            let (color, mark) = if pos >= pos1 || is_correct[pos] == 0 {
+                (syntax_color(syntax.get(pos)), None)
+            } else if blind {
+                // no right or wrong until the results
+                (theme_color(theme::colors().text, 200), None)
             } else if is_correct.get(pos).is_some() && is_correct[pos] == 2 {
-                macroquad::color::Color::from_rgba(255, 255, 255, 200)
+                (theme_color(theme::colors().text, 200), None)
             } else if is_correct.get(pos).is_some() && is_correct[pos] == 1 {
                 if char == ' ' {
                     curr_char = '_';
                 }
-                macroquad::color::Color::from_rgba(255, 165, 0, 255)
+                (theme_color(theme::colors().main, 255), markers.corrected())
+            } else if is_correct[pos] == 4 {
+                (theme_color(theme::colors().missed, 255), markers.error())
+            } else if is_correct[pos] == 5 {
+                (theme_color(theme::colors().text, 200), None)
             } else {
                 if char == ' ' {
                     curr_char = '_';
                 }
-                macroquad::color::Color::from_rgba(255, 50, 50, 180)
+                (theme_color(theme::colors().error, 180), markers.error())
             };
-            draw_text_ex(
-                &curr_char.to_string(),
-                pos_x as f32 + start_x,
-                pos_y + start_y,
-                TextParams {
-                    font,
-                    font_size: font_size as u16,
-                    color,
-                    ..Default::default()
-                },
-            );
-            let type_width = measure_text(&char.to_string(), font, font_size as u16, 1.0).width;
+            let type_width = draw_marked(&curr_char.to_string(), pos_x as f32 + start_x, pos_y + start_y, font, font_size, color, mark);
             pos_x += type_width as usize;
             pos += 1;
         }
@@ -699,13 +1131,46 @@ fn draw_reference_text(
     }
 }
 
-fn draw_cursor(cursor_x: usize, cursor_y: usize, start_x: f32, start_y: f32, line_h: f32, char_w: f32) {
+// Draws a letter with the marker on it, bold is the letter drawn again a pixel to the right. Returns its width.
+fn draw_marked(text: &str, x: f32, y: f32, font: Option<&Font>, font_size: f32, color: macroquad::color::Color, mark: Option<Mark>) -> f32 {
+    let params = TextParams { font, font_size: font_size as u16, color, ..Default::default() };
+    draw_text_ex(text, x, y, params.clone());
+    let width = measure_text(text, font, font_size as u16, 1.0).width;
+    match mark {
+        Some(Mark::Bold) => {
+            draw_text_ex(text, x + 1.0, y, params);
+        }
+        Some(Mark::Underline) => draw_line(x, y + font_size * 0.15, x + width, y + font_size * 0.15, 2.0, color),
+        Some(Mark::Strikethrough) => draw_line(x, y - font_size * 0.3, x + width, y - font_size * 0.3, 2.0, color),
+        None => {}
+    }
+    width
+}
+
+fn syntax_color(syntax: Option<&Syntax>) -> macroquad::color::Color {
+    match syntax {
+        Some(Syntax::Keyword) => theme_color(theme::colors().keyword, 255),
+        Some(Syntax::String) => theme_color(theme::colors().string, 255),
+        Some(Syntax::Comment) => theme_color(theme::colors().comment, 255),
+        Some(Syntax::Number) => theme_color(theme::colors().number, 255),
+        Some(Syntax::Bracket) => theme_color(theme::colors().bracket, 255),
+        _ => theme_color(theme::colors().reference, 255),
+    }
+}
+
+pub fn draw_cursor(cursor_x: usize, cursor_y: usize, start_x: f32, start_y: f32, line_h: f32, char_w: f32) {
+    let cursor_x = start_x + cursor_x as f32 * char_w;
+    let cursor_y = start_y + cursor_y as f32 * line_h;
+    draw_line(cursor_x, cursor_y - line_h * 0.7, cursor_x, cursor_y + line_h * 0.3, 2.0, theme_color(theme::colors().main, 255));
+}
+
+fn draw_pace_cursor(cursor_x: usize, cursor_y: usize, start_x: f32, start_y: f32, line_h: f32, char_w: f32) {
     let cursor_x = start_x + cursor_x as f32 * char_w;
     let cursor_y = start_y + cursor_y as f32 * line_h;
-    draw_line(cursor_x, cursor_y - line_h * 0.7, cursor_x, cursor_y + line_h * 0.3, 2.0, macroquad::color::Color::from_rgba(255, 155, 0, 255));
+    draw_line(cursor_x, cursor_y - line_h * 0.7, cursor_x, cursor_y + line_h * 0.3, 2.0, theme_color(theme::colors().pace, 180));
 }
 
-fn calc_pos(chars_in_line: &[i32], pos1: usize) -> (usize, usize) {
+pub fn calc_pos(chars_in_line: &[i32], pos1: usize) -> (usize, usize) {
     let mut total = 0;
     for (i, &count) in chars_in_line.iter().enumerate() {
         if pos1 < total + count as usize {
@@ -5,7 +5,8 @@ use std::collections::VecDeque;
 use std::thread;
 use std::time::{Instant, Duration};
 
-use crate::ui::gui::config;
+use crate::ui::gui::{config, main};
+use crate::theme;
 use crate::practice::{check_if_completed, TYPING_LEVELS};
 
 
@@ -68,7 +69,7 @@ pub fn display_practice_menu(
                     } else {
                         25
                     },
-                color: Color::from_rgba(255, 150, 0, 255),
+                color: main::theme_color(theme::colors().main, 255),
                 ..Default::default()
             },
         );
@@ -151,9 +152,9 @@ pub fn display_practice_menu(
         }
 
         let text_color = if is_hovered {
-            Color::from_rgba(255, 150, 0, 255)
+            main::theme_color(theme::colors().main, 255)
         } else {
-            Color::from_rgba(200, 200, 200, 230)
+            main::theme_color(theme::colors().text, 230)
         };
 
         if show_tick {
@@ -164,7 +165,7 @@ pub fn display_practice_menu(
                 TextParams {
                     font: Some(&emoji_font),
                     font_size: 50,
-                    color: Color::from_rgba(0, 255, 0, 255),
+                    color: main::theme_color(theme::colors().success, 255),
                     ..Default::default()
                 },
             );
@@ -329,7 +330,7 @@ pub fn display_practice_menu(
             10.0,
             thumb_height,
             5.0,
-            Color::from_rgba(180, 180, 180, 220),
+            main::theme_color(theme::colors().text, 220),
         );
     }
 
@@ -1,4 +1,5 @@
 use std::collections::VecDeque;
+use std::path::Path;
 use macroquad::prelude::*;
 use eframe::egui;
 use egui::{Color32,  Area, pos2};
@@ -6,6 +7,12 @@ use egui_plot::{Line, Plot};
 
 use crate::utils;
 use crate::practice;
+use crate::history;
+use crate::leaderboard;
+use crate::card;
+use crate::theme;
+use crate::simulate::Keystroke;
+use crate::ui::gui::{config, main};
 
 
 fn calc_standard_deviation(values: &[f64], average_word_length: f64) -> f64 {
@@ -17,6 +24,8 @@ fn calc_standard_deviation(values: &[f64], average_word_length: f64) -> f64 {
 
 pub fn write_results(
     is_correct: &VecDeque<i32>,
+    // left edge and size of the area the results are drawn in
+    x: f32,
     screen_width: f32,
     screen_height: f32,
     font: Option<&Font>,
@@ -24,22 +33,24 @@ pub fn write_results(
     speed_per_second: &[f64],
     average_word_length: f64,
     mode: &str,
-    punctuation: bool,
-    numbers: bool,
+    settings: &config::Settings,
     errors_per_second: &Vec<f64>,
     reference: &String,
     practice_level: Option<usize>,
     saved_results: &mut bool,
+    failed: Option<&str>,
+    daily_status: Option<&str>,
+    seed: Option<u64>,
+    saved_card: &mut Option<String>,
+    leaderboard: &mut leaderboard::Submitter,
+    // the whole text typed and every key, saved so the result can be checked by replay
+    typed_reference: &str,
+    keys: &[Keystroke],
 ) {
+    let first_frame = !*saved_results;
     let (no_corrected_words, correct_words, all_words) = utils::count_correct_words(reference, is_correct);
 
-    let correct_count = is_correct.iter().filter(|&&v| v == 1 || v == 2).count();
-    let all_pressed_count = is_correct.iter().filter(|&&v| v != 0).count();
-    let accuracy = if correct_count > 0 {
-        (correct_count as f64 / all_pressed_count as f64) * 100.0
-    } else {
-        0.0
-    };
+    let accuracy = utils::calc_accuracy(is_correct);
 
     let wpm = if practice_level.is_some() {
         (no_corrected_words as f32 / (test_time / 60.0)).round()
@@ -62,7 +73,7 @@ pub fn write_results(
         if a.width > b.width { a } else { b }
     };
 
-    let chart_x = (screen_width - chart_width) / 2.0 + fontsize_1 as f32;
+    let chart_x = x + (screen_width - chart_width) / 2.0 + fontsize_1 as f32;
     let chart_y = if practice_level.is_some() {
         (screen_height - chart_height) / 4.0
     } else {
@@ -125,8 +136,8 @@ pub fn write_results(
         chart_x + padding + (text2_width + padding) * 3.0,
         chart_y + chart_height + fontsize_4 as f32 * 2.0,
         mode,
-        punctuation,
-        numbers,
+        settings.punctuation,
+        settings.numbers,
         fontsize_3,
         fontsize_4,
         practice_level
@@ -161,14 +172,13 @@ pub fn write_results(
         };
         let text_size = measure_text(&practice_text, font, passed_text_font, 1.0);
 
-        draw_text_ex(practice_text.as_str(), (screen_width - text_size.width) / 2.0, chart_y + chart_height + screen_height / 4.0, TextParams { font, font_size: passed_text_font, font_scale: 1.0, color: Color::from_rgba(255, 255, 255, 100), ..Default::default() });
+        draw_text_ex(practice_text.as_str(), x + (screen_width - text_size.width) / 2.0, chart_y + chart_height + screen_height / 4.0, TextParams { font, font_size: passed_text_font, font_scale: 1.0, color: main::theme_color(theme::colors().text, 100), ..Default::default() });
         if practice::get_prev_best_wpm(practice_level.unwrap() + 1) < wpm as f64 {
             let new_highscore_text = "New highscore for this level!";
             let text_size = measure_text(new_highscore_text, font, 30, 1.0);
-            draw_text_ex(new_highscore_text, (screen_width - text_size.width) / 2.0, chart_y + chart_height + 250.0, TextParams { font, font_size: passed_text_font, font_scale: 1.0, color: Color::from_rgba(255, 255, 255, 100), ..Default::default() });
+            draw_text_ex(new_highscore_text, x + (screen_width - text_size.width) / 2.0, chart_y + chart_height + 250.0, TextParams { font, font_size: passed_text_font, font_scale: 1.0, color: main::theme_color(theme::colors().text, 100), ..Default::default() });
         }
-        if !*saved_results {
-            *saved_results = true;
+        if first_frame {
             practice::save_results(
                 test_time as f64,
                 accuracy,
@@ -177,6 +187,77 @@ pub fn write_results(
             );
         }
     }
+
+    if let Some(reason) = failed {
+        let failed_text = format!("Test failed: {} ({})", reason, settings.strictness.label().trim());
+        let text_size = measure_text(&failed_text, font, fontsize_4 * 2, 1.0);
+        draw_text_ex(failed_text.as_str(), x + (screen_width - text_size.width) / 2.0, chart_y - text_size.height * 2.0, TextParams { font, font_size: fontsize_4 * 2, font_scale: 1.0, color: main::theme_color(theme::colors().error, 180), ..Default::default() });
+    }
+
+    if let Some(status) = daily_status {
+        let daily_text = format!("Daily challenge: {}", status);
+        let text_size = measure_text(&daily_text, font, fontsize_4 * 2, 1.0);
+        draw_text_ex(daily_text.as_str(), x + (screen_width - text_size.width) / 2.0, chart_y + chart_height + screen_height / 4.0, TextParams { font, font_size: fontsize_4 * 2, font_scale: 1.0, color: main::theme_color(theme::colors().text, 100), ..Default::default() });
+    }
+
+    if first_frame {
+        let saved = history::add(history::Record {
+            frontend: "gui".to_string(),
+            mode: mode.to_string(),
+            punctuation: settings.punctuation,
+            numbers: settings.numbers,
+            seed,
+            seconds: test_time as f64,
+            wpm: wpm as f64,
+            raw_wpm: raw as f64,
+            accuracy,
+            sudden_death: settings.strictness.sudden_death,
+            min_accuracy: settings.strictness.min_accuracy,
+            error_mode: settings.strictness.error_mode.name().to_string(),
+            delete_word_on_error: settings.strictness.delete_word_on_error,
+            failed: failed.is_some(),
+            reference: typed_reference.to_string(),
+            keys: keys.to_vec(),
+            ..Default::default()
+        });
+        // the message slot under the results also tells when the result couldn't be saved, and how the leaderboard took it
+        *saved_card = None;
+        match saved {
+            Ok(record) => leaderboard.submit(&record),
+            Err(err) => *saved_card = Some(err),
+        }
+    }
+    if let Some(outcome) = leaderboard.outcome() {
+        *saved_card = Some(outcome.unwrap_or_else(|err| err));
+    }
+
+    let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+    if ctrl && is_key_pressed(KeyCode::S) {
+        let format = if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) { "svg" } else { "png" };
+        let timestamp = history::now();
+        let path = card::default_path(timestamp, format);
+        let result_card = card::Card {
+            timestamp,
+            mode: mode.to_string(),
+            punctuation: settings.punctuation,
+            numbers: settings.numbers,
+            seconds: test_time as f64,
+            wpm: wpm as f64,
+            raw_wpm: raw as f64,
+            accuracy,
+            wpm_per_second: speed_per_second.iter().map(|&speed| speed / average_word_length).collect(),
+            errors_per_second: errors_per_second.clone(),
+        };
+        *saved_card = Some(match card::save(&result_card, Path::new(&path)) {
+            Ok(()) => format!("Result card saved to {}", path),
+            Err(err) => err,
+        });
+    }
+    if let Some(message) = saved_card.as_deref() {
+        let text_size = measure_text(message, font, fontsize_4 * 2, 1.0);
+        draw_text_ex(message, x + (screen_width - text_size.width) / 2.0, chart_y + chart_height + screen_height / 6.0, TextParams { font, font_size: fontsize_4 * 2, font_scale: 1.0, color: main::theme_color(theme::colors().text, 100), ..Default::default() });
+    }
+    *saved_results = true;
 }
 
 fn write_mode(
@@ -204,7 +285,7 @@ fn write_mode(
         TextParams {
             font,
             font_size: fontsize_4,
-            color: Color::from_rgba(255, 255, 255, 80),
+            color: main::theme_color(theme::colors().reference, 255),
             ..Default::default()
         },
     );
@@ -234,7 +315,7 @@ fn write_mode(
         TextParams {
             font,
             font_size: fontsize_3,
-            color: Color::from_rgba(255, 155, 0, 255),
+            color: main::theme_color(theme::colors().main, 255),
             ..Default::default()
         },
     );
@@ -246,7 +327,7 @@ fn write_mode(
             TextParams {
                 font,
                 font_size: fontsize_4,
-                color: Color::from_rgba(255, 155, 0, 255),
+                color: main::theme_color(theme::colors().main, 255),
                 ..Default::default()
             },
         );
@@ -259,7 +340,7 @@ fn write_mode(
             TextParams {
                 font,
                 font_size: fontsize_4,
-                color: Color::from_rgba(255, 155, 0, 255),
+                color: main::theme_color(theme::colors().main, 255),
                 ..Default::default()
             },
         );
@@ -273,7 +354,7 @@ fn write_mode(
             TextParams {
                 font,
                 font_size: fontsize_4,
-                color: Color::from_rgba(255, 155, 0, 255),
+                color: main::theme_color(theme::colors().main, 255),
                 ..Default::default()
             },
         );
@@ -296,7 +377,7 @@ fn write_time(
         TextParams {
             font,
             font_size: fontsize_4,
-            color: Color::from_rgba(255, 255, 255, 80),
+            color: main::theme_color(theme::colors().reference, 255),
             ..Default::default()
         },
     );
@@ -307,7 +388,7 @@ fn write_time(
         TextParams {
             font,
             font_size: fontsize_3,
-            color: Color::from_rgba(255, 155, 0, 255),
+            color: main::theme_color(theme::colors().main, 255),
             ..Default::default()
         },
     );
@@ -338,7 +419,7 @@ fn write_consistency(
         TextParams {
             font,
             font_size: fontsize_4,
-            color: Color::from_rgba(255, 255, 255, 80),
+            color: main::theme_color(theme::colors().reference, 255),
             ..Default::default()
         },
     );
@@ -349,7 +430,7 @@ fn write_consistency(
         TextParams {
             font,
             font_size: fontsize_3,
-            color: Color::from_rgba(255, 155, 0, 255),
+            color: main::theme_color(theme::colors().main, 255),
             ..Default::default()
         },
     );
@@ -371,7 +452,7 @@ fn write_wpm(
         TextParams {
             font,
             font_size: fontsize_2,
-            color: Color::from_rgba(255, 255, 255, 80),
+            color: main::theme_color(theme::colors().reference, 255),
             ..Default::default()
         },
     );
@@ -382,7 +463,7 @@ fn write_wpm(
         TextParams {
             font,
             font_size: fontsize_1,
-            color: Color::from_rgba(255, 155, 0, 255),
+            color: main::theme_color(theme::colors().main, 255),
             ..Default::default()
         },
     );
@@ -398,7 +479,7 @@ fn write_acc(accuracy: f64, font: Option<&Font>, x: f32, y: f32, fontsize_1: u16
         TextParams {
             font,
             font_size: fontsize_2,
-            color: Color::from_rgba(255, 255, 255, 80),
+            color: main::theme_color(theme::colors().reference, 255),
             ..Default::default()
         },
     );
@@ -409,7 +490,7 @@ fn write_acc(accuracy: f64, font: Option<&Font>, x: f32, y: f32, fontsize_1: u16
         TextParams {
             font,
             font_size: fontsize_1,
-            color: Color::from_rgba(255, 155, 0, 255),
+            color: main::theme_color(theme::colors().main, 255),
             ..Default::default()
         },
     );
@@ -424,7 +505,7 @@ fn write_raw_wpm(raw_wpm: f32, font: Option<&Font>, x: f32, y: f32, fontsize_3:
         TextParams {
             font,
             font_size: fontsize_4,
-            color: Color::from_rgba(255, 255, 255, 80),
+            color: main::theme_color(theme::colors().reference, 255),
             ..Default::default()
         },
     );
@@ -435,7 +516,7 @@ fn write_raw_wpm(raw_wpm: f32, font: Option<&Font>, x: f32, y: f32, fontsize_3:
         TextParams {
             font,
             font_size: fontsize_3,
-            color: Color::from_rgba(255, 155, 0, 255),
+            color: main::theme_color(theme::colors().main, 255),
             ..Default::default()
         },
     );
@@ -471,7 +552,7 @@ fn draw_chart(points: &[[f64; 2]], chart_width: f32, chart_height: f32, chart_x:
                 let size = egui::Vec2::new(chart_width, chart_height);
                 let (rect, _response) = ui.allocate_exact_size(size, egui::Sense::hover());
 
-                let mut child_ui = ui.child_ui(rect, *ui.layout(), None);
+                let mut child_ui = ui.new_child(egui::UiBuilder::new().max_rect(rect).layout(*ui.layout()));
 
                 let grid_spacer = |input: egui_plot::GridInput| -> Vec<egui_plot::GridMark> {
                     let min = input.bounds.0;
@@ -510,8 +591,9 @@ fn draw_chart(points: &[[f64; 2]], chart_width: f32, chart_height: f32, chart_x:
                     .default_x_bounds(0.8, max_x)
                     .default_y_bounds(0.0, 1.2 * max_y)
                     .show(&mut child_ui, |plot_ui| {
+                        let colors = theme::colors();
                         let line = Line::new("Performance", points.to_vec())
-                            .color(Color32::from_rgb(255, 155, 0))
+                            .color(Color32::from_rgb(colors.main.0, colors.main.1, colors.main.2))
                             .highlight(true)
                             .name("Performance");
                         plot_ui.line(line);
@@ -543,7 +625,7 @@ fn draw_chart(points: &[[f64; 2]], chart_width: f32, chart_height: f32, chart_x:
                                             "Error",
                                             vec![[x, cross_y]]
                                         )
-                                        .color(Color32::from_rgb(255, 50, 50))
+                                        .color(Color32::from_rgb(colors.error.0, colors.error.1, colors.error.2))
                                         .radius(size)
                                         .shape(egui_plot::MarkerShape::Cross);
                                         
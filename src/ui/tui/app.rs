@@ -1,11 +1,17 @@
 use std::io;
-use crossterm::event::{self, Event as CEvent, KeyEvent};
+use std::collections::{HashMap, VecDeque};
+use crossterm::event::{self, Event as CEvent, KeyEvent, KeyModifiers};
 use ratatui::DefaultTerminal;
 use std::time::{Duration, Instant};
 
 use crate::ui::tui::ui::render_app;
-use crate::{practice, utils};
+use crate::{code, custom, daily, history, leaderboard, pace, practice, theme, utils};
+use crate::pace::{Pace, PaceMode};
+use crate::theme::Markers;
+use crate::custom::CustomText;
 use crate::practice::TYPING_LEVELS;
+use crate::utils::Mode;
+use crate::simulate::{self, Keystroke};
 
 
 #[derive(PartialEq, Eq)]
@@ -29,9 +35,7 @@ pub struct App {
     pub config: bool,
     pub punctuation: bool,
     pub numbers: bool,
-    pub time_mode: bool,
-    pub word_mode: bool,
-    pub quote: bool,
+    pub mode: Mode,
     pub batch_size: usize,
     pub selected_config: &'static str,
     pub speed_per_second: Vec<f64>,
@@ -41,9 +45,42 @@ pub struct App {
     pub correct_count: usize,
     pub error_count: usize,
     pub practice_menu: bool,
-    pub practice_mode: bool,
     pub selected_level: usize,
     pub timer: Duration,
+    pub custom_menu: bool,
+    pub custom_input: String,
+    pub custom_message: String,
+    pub custom_texts: Vec<CustomText>,
+    pub selected_custom: usize,
+    pub custom: Option<CustomText>,
+    pub code_language: &'static str,
+    pub code_auto_indent: bool,
+    pub daily_status: Option<String>,
+    // shown under the results: a result that couldn't be saved, or how the leaderboard took it
+    pub notice: Option<Result<String, String>>,
+    pub leaderboard: leaderboard::Submitter,
+    pub strictness: utils::Strictness,
+    pub indicators: utils::Indicators,
+    pub pace: Pace,
+    // the pace's target for this test, looked up when it's reset
+    pub pace_wpm: Option<f64>,
+    pub failed: Option<&'static str>,
+    pub blind_mode: bool,
+    pub memory_mode: bool,
+    pub memory: utils::Memory,
+    pub length_input: Option<String>,
+    pub extra_chars: HashMap<usize, String>,
+    pub test_rng: utils::TestRng,
+    // batches of a time test typed before the current one, and every key press since the start
+    pub done_batches: String,
+    pub done_is_correct: Vec<i32>,
+    pub keys: Vec<Keystroke>,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl App {
@@ -62,9 +99,7 @@ impl App {
             config: false,
             punctuation: false,
             numbers: false,
-            time_mode: true,
-            word_mode: false,
-            quote: false,
+            mode: Mode::Time,
             batch_size: 50,
             selected_config: "time",
             speed_per_second: Vec::new(),
@@ -74,15 +109,39 @@ impl App {
             correct_count: 0,
             error_count: 0,
             practice_menu: false,
-            practice_mode: false,
             selected_level: 0,
             timer: Duration::from_secs(0),
+            custom_menu: false,
+            custom_input: String::new(),
+            custom_message: String::new(),
+            custom_texts: Vec::new(),
+            selected_custom: 0,
+            custom: None,
+            code_language: "rust",
+            code_auto_indent: true,
+            daily_status: None,
+            notice: None,
+            leaderboard: leaderboard::Submitter::default(),
+            strictness: utils::Strictness::default(),
+            indicators: utils::Indicators::default(),
+            pace: Pace::default(),
+            pace_wpm: None,
+            failed: None,
+            blind_mode: false,
+            memory_mode: false,
+            memory: utils::Memory::new(),
+            length_input: None,
+            extra_chars: HashMap::new(),
+            test_rng: utils::TestRng::new(None),
+            done_batches: String::new(),
+            done_is_correct: Vec::new(),
+            keys: Vec::new(),
         }
     }
 
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
         let word_list = utils::read_first_n_words(500);
-        self.reference = utils::get_reference(false, false, &word_list, self.batch_size);
+        self.reference = utils::get_reference(false, false, &word_list, self.batch_size, self.test_rng.rng());
         self.is_correct = vec![0; self.reference.chars().count()];
         let mut last_recorded_time = Instant::now();
         
@@ -91,8 +150,10 @@ impl App {
                 last_recorded_time = Instant::now();
             }
             if event::poll(Duration::from_millis(16))? {
-                if let CEvent::Key(key) = event::read()? {
-                    self.handle_key_event(key, self.reference.clone())?;
+                match event::read()? {
+                    CEvent::Key(key) => self.handle_key_event(key)?,
+                    CEvent::Paste(text) if self.custom_menu => self.load_custom(custom::from_pasted(&text)),
+                    _ => {}
                 }
             }
             self.timer = if let Some(start_time) = self.start_time {
@@ -109,18 +170,17 @@ impl App {
 
             if (self.test_time - self.timer.as_secs_f32() < 0.0 
                 && self.game_state == GameState::Started 
-                && self.time_mode) 
+                && self.mode == Mode::Time) 
                 || (self.words_done >= self.batch_size 
-                    && (self.word_mode || self.quote) 
+                    && matches!(self.mode, Mode::Words | Mode::Quote | Mode::Custom | Mode::Daily) 
                     && self.game_state != GameState::Results)
-                || (self.words_done >= 5 && self.practice_mode && self.game_state != GameState::Results)
+                || (self.words_done >= 5 && self.mode == Mode::Practice && self.game_state != GameState::Results)
+                || (self.mode == Mode::Code && self.pos1 >= self.reference.chars().count() && self.game_state != GameState::Results)
             {
-                self.errors_per_second.push(self.errors_this_second);
-                let total_typed = self.pressed_vec.len();
-                let chars_in_this_second = total_typed.saturating_sub(self.char_number);
-                let cpm = chars_in_this_second as f64 * 60.0;
-                self.speed_per_second.push(cpm);
-                self.game_state = GameState::Results;
+                self.finish_test();
+            }
+            if let Some(outcome) = self.leaderboard.outcome() {
+                self.notice = Some(outcome);
             }
             let now = Instant::now();
             let time_since_last = now.duration_since(last_recorded_time);
@@ -138,54 +198,501 @@ impl App {
                 self.errors_this_second = 0.0;
                 last_recorded_time += Duration::from_secs(1);
             }
+            if self.memory_mode {
+                let reference_chars: Vec<char> = self.reference.chars().collect();
+                self.memory.update(&reference_chars, self.pos1, self.game_state == GameState::Started);
+            }
             terminal.draw(|frame| render_app(frame, self, self.timer))?;
         }
         Ok(())
     }
 
+    pub fn mode_name(&self) -> &'static str {
+        self.mode.name()
+    }
+
+    // Every batch typed so far, continuing into the current one.
+    fn all_is_correct(&self) -> VecDeque<i32> {
+        self.done_is_correct.iter().chain(&self.is_correct).copied().collect()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        if self.pressed_vec.is_empty() {
+            100.0
+        } else {
+            utils::calc_accuracy(&self.all_is_correct())
+        }
+    }
+
+    // Net wpm, raw wpm and accuracy over the whole test, counted the same way as in the CLI.
+    pub fn stats(&self, seconds: f64) -> (f64, f64, f64) {
+        let is_correct = self.all_is_correct();
+        let (wpm, raw_wpm) = if seconds > 0.0 {
+            utils::calc_wpm(&format!("{}{}", self.done_batches, self.reference), &is_correct, seconds)
+        } else {
+            (0.0, 0.0)
+        };
+        (wpm, raw_wpm, utils::calc_accuracy(&is_correct))
+    }
+
+    pub fn finish_test(&mut self) {
+        self.errors_per_second.push(self.errors_this_second);
+        let total_typed = self.pressed_vec.len();
+        let chars_in_this_second = total_typed.saturating_sub(self.char_number);
+        let cpm = chars_in_this_second as f64 * 60.0;
+        self.speed_per_second.push(cpm);
+        self.game_state = GameState::Results;
+        if let Some(custom) = self.custom.as_mut().filter(|_| self.mode == Mode::Custom) {
+            custom::advance(custom);
+        }
+
+        let seconds = self.timer.as_secs_f64();
+        let (wpm, raw_wpm, accuracy) = self.stats(seconds);
+        let day = daily::today();
+        let mode = if self.mode == Mode::Daily { daily::attempt_mode(&history::load(), day) } else { self.mode_name() };
+        // the batch that was just generated after finishing one isn't part of the test
+        let typed_reference = if matches!(self.mode, Mode::Code | Mode::Zen) {
+            String::new()
+        } else if self.pos1 > 0 {
+            format!("{}{}", self.done_batches, self.reference)
+        } else {
+            self.done_batches.clone()
+        };
+        let seed = if self.mode == Mode::Daily { Some(daily::seed(day)) } else { (!matches!(self.mode, Mode::Custom | Mode::Zen)).then_some(self.test_rng.seed) };
+        let saved = history::add(history::Record {
+            frontend: "tui".to_string(),
+            mode: mode.to_string(),
+            punctuation: self.punctuation,
+            numbers: self.numbers,
+            seed,
+            seconds,
+            wpm,
+            raw_wpm,
+            accuracy,
+            sudden_death: self.strictness.sudden_death,
+            min_accuracy: self.strictness.min_accuracy,
+            error_mode: self.strictness.error_mode.name().to_string(),
+            delete_word_on_error: self.strictness.delete_word_on_error,
+            failed: self.failed.is_some(),
+            reference: typed_reference,
+            keys: self.keys.clone(),
+            ..Default::default()
+        });
+        self.notice = None;
+        match saved {
+            Ok(record) => self.leaderboard.submit(&record),
+            Err(err) => self.notice = Some(Err(err)),
+        }
+        self.daily_status = (self.mode == Mode::Daily).then(|| daily::status(mode));
+    }
+
+    pub fn reset_state(&mut self) {
+        self.is_correct = vec![0; self.reference.chars().count()];
+        self.pressed_vec.clear();
+        self.pos1 = 0;
+        self.words_done = 0;
+        self.errors_this_second = 0.0;
+        self.start_time = None;
+        self.game_state = GameState::NotStarted;
+        self.speed_per_second.clear();
+        self.char_number = 0;
+        self.errors_per_second.clear();
+        self.tab_pressed = Instant::now() - Duration::from_secs(5);
+        self.correct_count = 0;
+        self.error_count = 0;
+        self.failed = None;
+        self.extra_chars.clear();
+        self.done_batches.clear();
+        self.done_is_correct.clear();
+        self.keys.clear();
+        self.notice = None;
+        self.refresh_pace();
+    }
+
+    pub fn refresh_pace(&mut self) {
+        self.pace_wpm = self.pace.target(&history::load(), self.mode_name());
+    }
+
+    // Where the pace caret is in the current text, None when it's off, hasn't started or ran past the end.
+    pub fn pace_position(&self) -> Option<usize> {
+        let wpm = self.pace_wpm.filter(|_| self.game_state == GameState::Started && self.mode != Mode::Zen)?;
+        pace::position(wpm, self.timer.as_secs_f64(), &self.reference)
+            .checked_sub(self.done_batches.chars().count())
+            .filter(|&position| position < self.reference.chars().count())
+    }
+
+    // Keys are logged only while a test runs, in ms from its start.
+    pub fn log_key(&mut self, key: char) {
+        if let Some(start_time) = self.start_time.filter(|_| self.game_state == GameState::Started) {
+            self.keys.push(Keystroke { at: start_time.elapsed().as_millis() as u64, key: simulate::key_name(key) });
+        }
+    }
+
+    pub fn open_custom_menu(&mut self) {
+        self.custom_menu = true;
+        self.custom_input.clear();
+        self.custom_message.clear();
+        self.custom_texts = custom::recent_texts();
+        self.selected_custom = 0;
+        self.config = false;
+    }
+
+    pub fn load_custom(&mut self, loaded: Result<CustomText, String>) {
+        match loaded {
+            Ok(text) => {
+                let text = custom::remember(text);
+                self.reference = text.current_chunk();
+                self.batch_size = self.reference.split_whitespace().count();
+                self.custom = Some(text);
+                self.custom_menu = false;
+                self.mode = Mode::Custom;
+                self.reset_state();
+            }
+            Err(err) => self.custom_message = err,
+        }
+    }
+
+    fn handle_custom_menu_key(&mut self, key_event: KeyEvent) {
+        use crossterm::event::KeyCode;
+
+        match key_event.code {
+            KeyCode::Esc => self.custom_menu = false,
+            KeyCode::Up => self.selected_custom = self.selected_custom.saturating_sub(1),
+            KeyCode::Down if self.selected_custom + 1 < self.custom_texts.len() => self.selected_custom += 1,
+            KeyCode::Backspace => {
+                self.custom_input.pop();
+            }
+            KeyCode::Char(ch) => self.custom_input.push(ch),
+            KeyCode::Enter => {
+                if !self.custom_input.is_empty() {
+                    let path = std::path::PathBuf::from(self.custom_input.trim());
+                    self.load_custom(custom::load_file(&path));
+                } else if let Some(text) = self.custom_texts.get(self.selected_custom).cloned() {
+                    self.load_custom(Ok(text));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn is_preset_length(&self) -> bool {
+        if self.mode == Mode::Time {
+            [15.0, 30.0, 60.0, 120.0].contains(&self.test_time)
+        } else {
+            [25, 50, 100].contains(&self.batch_size)
+        }
+    }
+
+    fn handle_length_input(&mut self, key_event: KeyEvent) {
+        use crossterm::event::KeyCode;
+        let Some(input) = self.length_input.as_mut() else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Char(ch) if ch.is_ascii_digit() && input.len() < 5 => input.push(ch),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Esc => self.length_input = None,
+            KeyCode::Enter => {
+                if let Some(n) = utils::parse_length(input) {
+                    if self.mode == Mode::Time {
+                        self.test_time = n as f32;
+                    } else {
+                        self.batch_size = n;
+                    }
+                    self.reference = utils::get_reference(self.punctuation, self.numbers, &utils::read_first_n_words(500), self.batch_size, self.test_rng.restart());
+                    self.reset_state();
+                    self.config = false;
+                }
+                self.length_input = None;
+            }
+            _ => {}
+        }
+    }
+
+    // Zen mode has no target, the typed text becomes the reference and is always correct.
+    fn type_zen_char(&mut self, ch: char) {
+        if self.game_state == GameState::Results || (self.reference.is_empty() && ch == ' ') {
+            return;
+        }
+        if self.game_state == GameState::NotStarted {
+            self.game_state = GameState::Started;
+            self.start_time = Some(Instant::now());
+        }
+        self.log_key(ch);
+        if ch == ' ' && !self.reference.ends_with(' ') {
+            self.words_done += 1;
+        }
+        self.reference.push(ch);
+        self.is_correct.push(2);
+        self.pressed_vec.push(ch);
+        self.correct_count += 1;
+        self.pos1 += 1;
+        self.config = false;
+    }
+
+    fn delete_zen_char(&mut self) {
+        if self.game_state == GameState::Results {
+            return;
+        }
+        if let Some(ch) = self.reference.pop() {
+            if ch == ' ' && !self.reference.ends_with(' ') {
+                self.words_done = self.words_done.saturating_sub(1);
+            }
+            self.is_correct.pop();
+            self.pressed_vec.pop();
+            self.correct_count = self.correct_count.saturating_sub(1);
+            self.pos1 -= 1;
+        }
+        self.config = false;
+    }
+
+    fn finish_zen(&mut self) {
+        if self.game_state != GameState::Started {
+            return;
+        }
+        if !self.reference.ends_with(' ') {
+            self.words_done += 1;
+        }
+        self.finish_test();
+    }
+
+    pub fn backspace(&mut self) {
+        let reference_chars: Vec<char> = self.reference.chars().collect();
+        if let Some(extra) = self.extra_chars.get_mut(&self.pos1)
+            && extra.pop().is_some()
+        {
+            self.pressed_vec.pop();
+            self.error_count = self.error_count.saturating_sub(1);
+            return;
+        }
+        while self.pos1 > 0 && self.is_correct[self.pos1 - 1] == 3 {
+            self.pos1 -= 1;
+            self.is_correct[self.pos1] = 0;
+        }
+        if !self.pressed_vec.is_empty() && utils::is_word_end(&reference_chars, self.pos1) {
+            self.words_done = self.words_done.saturating_sub(1);
+        }
+        if self.is_correct[self.pos1] == 2 || self.is_correct[self.pos1] == 1 {
+            self.correct_count = self.correct_count.saturating_sub(1);
+        } else if self.is_correct[self.pos1] == -1 {
+            self.error_count = self.error_count.saturating_sub(1);
+        }
+        self.pressed_vec.pop();
+        if self.pos1 > 0 {
+            self.pos1 -= 1;
+        }
+        // back to where a skipped word was left
+        if self.pos1 > 0 && self.is_correct[self.pos1 - 1] == 4 {
+            self.words_done = self.words_done.saturating_sub(1);
+            while self.pos1 > 0 && self.is_correct[self.pos1 - 1] == 4 {
+                self.pos1 -= 1;
+                self.is_correct[self.pos1] = 0;
+            }
+        }
+    }
+
+    // Removes extra letters and the current word, or the previous word when the cursor is at the start of one.
+    pub fn delete_word(&mut self, into_previous: bool) {
+        let reference_chars: Vec<char> = self.reference.chars().collect();
+        let has_extra = |app: &Self| app.extra_chars.get(&app.pos1).is_some_and(|extra| !extra.is_empty());
+        if into_previous && !has_extra(self) && self.pos1 > 0 && reference_chars[self.pos1 - 1].is_whitespace() {
+            self.backspace();
+        }
+        while has_extra(self) || (self.pos1 > 0 && !reference_chars[self.pos1 - 1].is_whitespace()) {
+            self.backspace();
+        }
+    }
+
+    fn delete_zen_word(&mut self) {
+        while self.reference.ends_with(' ') {
+            self.delete_zen_char();
+        }
+        while !self.reference.is_empty() && !self.reference.ends_with(' ') {
+            self.delete_zen_char();
+        }
+    }
+
+    pub fn type_char(&mut self, ch: char) {
+        if self.mode == Mode::Zen {
+            self.type_zen_char(ch);
+            return;
+        }
+        if self.is_correct[0] == 0 && ch == ' ' {
+            return;
+        }
+        let reference_chars: Vec<char> = self.reference.chars().collect();
+        if self.pos1 < reference_chars.len() {
+            if self.game_state == GameState::Results {
+                return;
+            }
+            if self.game_state == GameState::NotStarted {
+                self.game_state = GameState::Started;
+                self.start_time = Some(Instant::now());
+            }
+            self.log_key(ch);
+            let error_mode = if self.mode == Mode::Practice { utils::ErrorMode::StopOnLetter } else { self.strictness.error_mode };
+            match utils::word_key(error_mode, &reference_chars, self.pos1, ch) {
+                utils::WordKey::Ignore => return,
+                utils::WordKey::Extra => {
+                    let extra = self.extra_chars.entry(self.pos1).or_default();
+                    if extra.chars().count() < utils::MAX_EXTRA_CHARS {
+                        extra.push(ch);
+                        self.pressed_vec.push(ch);
+                        self.errors_this_second += 1.0;
+                        self.error_count += 1;
+                    }
+                    if self.strictness.delete_word_on_error {
+                        self.delete_word(false);
+                    }
+                    return;
+                }
+                utils::WordKey::Skip(word_end) => {
+                    self.is_correct[self.pos1..word_end].fill(4); // Missed
+                    self.pos1 = word_end;
+                    self.words_done += 1;
+                }
+                utils::WordKey::Normal => {}
+            }
+            let accepted = error_mode.accepts(&reference_chars, &self.is_correct, self.pos1, ch);
+            let advances = error_mode.advances(&reference_chars, self.pos1, accepted);
+            if accepted && self.is_correct[self.pos1] != -1 && self.is_correct[self.pos1] != 1 {
+                self.is_correct[self.pos1] = 2; // Correct
+                self.correct_count += 1;
+            } else if accepted {
+                self.is_correct[self.pos1] = 1; // Corrected
+            } else {
+                self.is_correct[self.pos1] = -1; // Incorrect
+                self.errors_this_second += 1.0;
+                self.error_count += 1;
+            }
+            if accepted && self.extra_chars.get(&self.pos1).is_some_and(|extra| !extra.is_empty()) {
+                self.is_correct[self.pos1] = 5; // Space after extra letters
+            }
+            if advances {
+                self.pos1 += 1;
+            }
+            
+            self.pressed_vec.push(ch);
+            if advances && utils::is_word_end(&reference_chars, self.pos1) {
+                self.words_done += 1;
+            }
+            if !accepted && self.strictness.delete_word_on_error && self.mode != Mode::Practice {
+                self.delete_word(false);
+                return;
+            }
+            if self.mode == Mode::Code && self.code_auto_indent {
+                let indent_end = code::indentation_end(&reference_chars, self.pos1);
+                self.is_correct[self.pos1..indent_end].fill(3); // Skipped indentation
+                self.pos1 = indent_end;
+            }
+            if let Some(reason) = self.strictness.check(&reference_chars, &self.is_correct, self.pos1, self.accuracy()) {
+                self.failed = Some(reason);
+                self.finish_test();
+                return;
+            }
+        }
+        self.config = false;
+
+        if self.pos1 >= self.reference.chars().count() && self.mode != Mode::Code {
+            self.words_done += 1;
+            // batches follow each other without a space, the same way they're typed
+            self.done_batches.push_str(&self.reference);
+            self.done_is_correct.append(&mut self.is_correct);
+            self.reference = utils::get_reference(self.punctuation, self.numbers, &utils::read_first_n_words(500), self.batch_size, self.test_rng.rng());
+            self.is_correct = vec![0; self.reference.chars().count()];
+            self.pos1 = 0;
+            self.extra_chars.clear();
+        }
+    }
+
     pub fn handle_key_event(
         &mut self,
         key_event: KeyEvent,
-        reference: String,
     ) -> io::Result<()> {
         use crossterm::event::KeyCode;
 
+        let mode = self.mode;
         let button_states = vec![
-            ("! punctuation", self.punctuation, !self.quote && !self.practice_mode),
-            ("# numbers", self.numbers, !self.quote && !self.practice_mode),
+            ("! punctuation", self.punctuation, mode.allows_punctuation()),
+            ("# numbers", self.numbers, mode.allows_punctuation()),
+            ("sudden death", self.strictness.sudden_death, mode.allows_strictness()),
+            ("word reset", self.strictness.delete_word_on_error, mode.allows_error_settings()),
+            ("min acc", self.strictness.min_accuracy.is_some(), mode.allows_strictness()),
+            ("errors", self.strictness.error_mode != utils::ErrorMode::Off, mode.allows_error_settings()),
+            ("blind", self.blind_mode, mode.allows_error_settings()),
+            ("memory", self.memory_mode, mode.allows_error_settings()),
+            ("live wpm", self.indicators.wpm, true),
+            ("live acc", self.indicators.accuracy, true),
+            ("progress", self.indicators.progress, true),
+            ("pace", self.pace.mode != PaceMode::Off, mode.allows_strictness()),
+            ("theme", true, true),
+            ("markers", theme::markers() != Markers::Off, mode.allows_strictness()),
             ("|", true, true),
-            ("time", self.time_mode, true),
-            ("words", self.word_mode, true),
-            ("quote", self.quote, true),
-            ("practice", self.practice_mode, true),
+            ("time", self.mode == Mode::Time, true),
+            ("words", self.mode == Mode::Words, true),
+            ("quote", self.mode == Mode::Quote, true),
+            ("custom", self.mode == Mode::Custom, true),
+            ("code", self.mode == Mode::Code, true),
+            ("zen", self.mode == Mode::Zen, true),
+            ("daily", self.mode == Mode::Daily, true),
+            ("practice", self.mode == Mode::Practice, true),
             ("|", true, true),
-            ("15", self.test_time == 15.0, self.time_mode),
-            ("30", self.test_time == 30.0, self.time_mode),
-            ("60", self.test_time == 60.0, self.time_mode),
-            ("120", self.test_time == 120.0, self.time_mode),
-            ("25", self.batch_size == 25, self.word_mode),
-            ("50", self.batch_size == 50, self.word_mode),
-            ("100", self.batch_size == 100, self.word_mode),
+            ("15", self.test_time == 15.0, self.mode == Mode::Time),
+            ("30", self.test_time == 30.0, self.mode == Mode::Time),
+            ("60", self.test_time == 60.0, self.mode == Mode::Time),
+            ("120", self.test_time == 120.0, self.mode == Mode::Time),
+            ("25", self.batch_size == 25, self.mode == Mode::Words),
+            ("50", self.batch_size == 50, self.mode == Mode::Words),
+            ("100", self.batch_size == 100, self.mode == Mode::Words),
+            ("edit", !self.is_preset_length(), matches!(self.mode, Mode::Time | Mode::Words)),
+            ("rust", self.code_language == "rust", self.mode == Mode::Code),
+            ("python", self.code_language == "python", self.mode == Mode::Code),
+            ("javascript", self.code_language == "javascript", self.mode == Mode::Code),
+            ("go", self.code_language == "go", self.mode == Mode::Code),
+            ("c", self.code_language == "c", self.mode == Mode::Code),
+            ("auto indent", self.code_auto_indent, self.mode == Mode::Code),
+            ("80%", self.strictness.min_accuracy == Some(80.0), self.strictness.min_accuracy.is_some() && self.mode != Mode::Zen),
+            ("90%", self.strictness.min_accuracy == Some(90.0), self.strictness.min_accuracy.is_some() && self.mode != Mode::Zen),
+            ("95%", self.strictness.min_accuracy == Some(95.0), self.strictness.min_accuracy.is_some() && self.mode != Mode::Zen),
         ];
 
-        let reference_chars: Vec<char> = reference.chars().collect();
+        if key_event.kind == crossterm::event::KeyEventKind::Press && self.custom_menu {
+            self.handle_custom_menu_key(key_event);
+            return Ok(());
+        }
+        if key_event.kind == crossterm::event::KeyEventKind::Press && self.length_input.is_some() {
+            self.handle_length_input(key_event);
+            return Ok(());
+        }
 
         if key_event.kind == crossterm::event::KeyEventKind::Press {
+            let delete_word_key = (key_event.code == KeyCode::Backspace && key_event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT))
+                || (matches!(key_event.code, KeyCode::Char('w' | 'h')) && key_event.modifiers.contains(KeyModifiers::CONTROL));
             match key_event.code {
                 KeyCode::Esc => self.exit = true,
+                // a finished test can end with the cursor past the last letter, there's nothing to delete
+                _ if (delete_word_key || key_event.code == KeyCode::Backspace) && self.game_state == GameState::Results => {}
+                _ if delete_word_key && self.mode == Mode::Zen => {
+                    self.log_key('\u{17}');
+                    self.delete_zen_word();
+                }
+                _ if delete_word_key && !self.strictness.error_mode.allows_backspace() => {}
+                _ if delete_word_key => {
+                    self.log_key('\u{17}');
+                    self.delete_word(true);
+                    self.config = false;
+                }
+                KeyCode::Backspace if self.mode == Mode::Zen => {
+                    self.log_key('\u{8}');
+                    self.delete_zen_char();
+                }
+                KeyCode::Char('d') if self.mode == Mode::Zen && key_event.modifiers.contains(KeyModifiers::CONTROL) => self.finish_zen(),
+                KeyCode::Backspace if !self.strictness.error_mode.allows_backspace() => {}
                 KeyCode::Backspace => {
-                    if !self.pressed_vec.is_empty() && reference_chars.get(self.pos1) == Some(&' ') {
-                        self.words_done = self.words_done.saturating_sub(1);
-                    }
-                    if self.is_correct[self.pos1] == 2 || self.is_correct[self.pos1] == 1 {
-                        self.correct_count = self.correct_count.saturating_sub(1);
-                    } else if self.is_correct[self.pos1] == -1 {
-                        self.error_count = self.error_count.saturating_sub(1);
-                    }
-                    self.pressed_vec.pop();
-                    if self.pos1 > 0 {
-                        self.pos1 -= 1;
-                    }
+                    self.log_key('\u{8}');
+                    self.backspace();
                     self.config = false;
                 }
                 KeyCode::Up => {
@@ -205,78 +712,136 @@ impl App {
                     }
                 }
                 KeyCode::Tab => {
-                    self.tab_pressed = Instant::now();
+                    let reference_chars: Vec<char> = self.reference.chars().collect();
+                    let indent = code::tab_spaces(&reference_chars, self.pos1);
+                    if self.mode == Mode::Code && !self.code_auto_indent && indent > 0 && self.game_state != GameState::Results {
+                        for _ in 0..indent {
+                            self.type_char(' ');
+                        }
+                    } else {
+                        self.tab_pressed = Instant::now();
+                    }
                 },
+                KeyCode::Enter if self.mode == Mode::Code
+                    && !self.config
+                    && !self.practice_menu
+                    && self.game_state != GameState::Results
+                    && self.tab_pressed.elapsed() >= Duration::from_secs(1) => {
+                    self.type_char('\n');
+                }
                 KeyCode::Enter => {
                     if self.tab_pressed.elapsed() < Duration::from_secs(1) {
-                        if self.word_mode || self.time_mode {
-                            self.reference = utils::get_reference(self.punctuation, self.numbers, &utils::read_first_n_words(500), self.batch_size);
-                        } else if self.quote {
-                            self.reference = utils::get_random_quote();
+                        if matches!(self.mode, Mode::Words | Mode::Time) {
+                            self.reference = utils::get_reference(self.punctuation, self.numbers, &utils::read_first_n_words(500), self.batch_size, self.test_rng.restart());
+                        } else if self.mode == Mode::Quote {
+                            self.reference = utils::get_random_quote(self.test_rng.restart());
+                            self.batch_size = self.reference.split_whitespace().count();
+                        } else if self.mode == Mode::Practice {
+                            self.reference = practice::create_words(TYPING_LEVELS[self.selected_level].1, 5, self.test_rng.restart());
+                        } else if let Some(custom) = self.custom.as_ref().filter(|_| self.mode == Mode::Custom) {
+                            self.reference = custom.current_chunk();
+                            self.batch_size = self.reference.split_whitespace().count();
+                        } else if self.mode == Mode::Code {
+                            self.reference = code::get_snippet(self.code_language, self.test_rng.restart());
+                        } else if self.mode == Mode::Zen {
+                            self.reference.clear();
+                        } else if self.mode == Mode::Daily {
+                            self.reference = daily::reference(daily::today());
                             self.batch_size = self.reference.split_whitespace().count();
-                        } else if self.practice_mode {
-                            self.reference = practice::create_words(TYPING_LEVELS[self.selected_level].1, 5);
                         }
-                        self.is_correct = vec![0; self.reference.chars().count()];
-                        self.pressed_vec.clear();
-                        self.pos1 = 0;
-                        self.words_done = 0;
-                        self.errors_this_second = 0.0;
-                        self.start_time = None;
-                        self.game_state = GameState::NotStarted;
-                        self.speed_per_second.clear();
-                        self.char_number = 0;
-                        self.errors_per_second.clear();
-                        self.tab_pressed = Instant::now() - Duration::from_secs(5);
-                        self.correct_count = 0;
-                        self.error_count = 0;
+                        self.reset_state();
                     }
                     if self.practice_menu {
                         self.practice_menu = false;
-                        self.practice_mode = true;
-                        self.time_mode = false;
-                        self.word_mode = false;
-                        self.quote = false;
+                        self.mode = Mode::Practice;
                         self.batch_size = 50;
-                        self.pressed_vec.clear();
-                        self.pos1 = 0;
-                        self.words_done = 0;
-                        self.errors_this_second = 0.0;
-                        self.start_time = None;
-                        self.game_state = GameState::NotStarted;
-                        self.speed_per_second.clear();
-                        self.char_number = 0;
-                        self.errors_per_second.clear();
-                        self.tab_pressed = Instant::now() - Duration::from_secs(5);
-                        self.correct_count = 0;
-                        self.error_count = 0;
                         self.config = false;
-                        self.reference = practice::create_words(TYPING_LEVELS[self.selected_level].1, 5);
-                        self.is_correct = vec![0; self.reference.chars().count()];
+                        self.reference = practice::create_words(TYPING_LEVELS[self.selected_level].1, 5, self.test_rng.restart());
+                        self.reset_state();
                     }
                     if self.config {
+                        let mut theme_errors = Vec::new();
                         match self.selected_config {
                             "time" => {
-                                self.time_mode = true;
-                                self.word_mode = false;
-                                self.quote = false;
+                                self.mode = Mode::Time;
                                 self.batch_size = 50;
-                                self.practice_mode = false;
                             }
                             "words" => {
-                                if !self.word_mode {
+                                if self.mode != Mode::Words {
                                     self.batch_size = 50;
                                 }
-                                self.time_mode = false;
-                                self.word_mode = true;
-                                self.quote = false;
-                                self.practice_mode = false;
+                                self.mode = Mode::Words;
                             }
                             "quote" => {
-                                self.quote = true;
-                                self.time_mode = false;
-                                self.word_mode = false;
-                                self.practice_mode = false;
+                                self.mode = Mode::Quote;
+                            }
+                            "daily" => {
+                                self.mode = Mode::Daily;
+                                self.punctuation = false;
+                                self.numbers = false;
+                            }
+                            "custom" => {
+                                self.open_custom_menu();
+                                return Ok(());
+                            }
+                            "code" => {
+                                self.mode = Mode::Code;
+                            }
+                            "zen" => {
+                                self.mode = Mode::Zen;
+                            }
+                            "rust" | "python" | "javascript" | "go" | "c" => {
+                                self.code_language = self.selected_config;
+                            }
+                            "auto indent" => {
+                                self.code_auto_indent = !self.code_auto_indent;
+                            }
+                            "edit" => {
+                                self.length_input = Some(String::new());
+                                return Ok(());
+                            }
+                            "sudden death" => {
+                                self.strictness.sudden_death = !self.strictness.sudden_death;
+                            }
+                            "errors" => {
+                                self.strictness.error_mode = self.strictness.error_mode.next();
+                            }
+                            "word reset" => {
+                                self.strictness.delete_word_on_error = !self.strictness.delete_word_on_error;
+                            }
+                            "blind" => {
+                                self.blind_mode = !self.blind_mode;
+                            }
+                            "memory" => {
+                                self.memory_mode = !self.memory_mode;
+                            }
+                            "live wpm" => {
+                                self.indicators.wpm = !self.indicators.wpm;
+                            }
+                            "live acc" => {
+                                self.indicators.accuracy = !self.indicators.accuracy;
+                            }
+                            "progress" => {
+                                self.indicators.progress = !self.indicators.progress;
+                            }
+                            "pace" => {
+                                self.pace = self.pace.next();
+                                self.refresh_pace();
+                            }
+                            "theme" => {
+                                theme_errors = theme::next();
+                            }
+                            "markers" => {
+                                theme::set_markers(theme::markers().next());
+                            }
+                            "min acc" => {
+                                self.strictness.min_accuracy = match self.strictness.min_accuracy {
+                                    Some(_) => None,
+                                    None => Some(90.0),
+                                };
+                            }
+                            "80%" | "90%" | "95%" => {
+                                self.strictness.min_accuracy = self.selected_config.trim_end_matches('%').parse().ok();
                             }
                             "practice" => {
                                 self.practice_menu = !self.practice_menu;
@@ -312,25 +877,28 @@ impl App {
                             _ => {}
                         }
                         if self.selected_config == "quote" {
-                            self.reference = utils::get_random_quote();
+                            self.reference = utils::get_random_quote(self.test_rng.restart());
+                            self.batch_size = self.reference.split_whitespace().count();
+                        }
+                        else if let Some(custom) = self.custom.as_ref().filter(|_| self.mode == Mode::Custom) {
+                            self.reference = custom.current_chunk();
+                            self.batch_size = self.reference.split_whitespace().count();
+                        }
+                        else if self.mode == Mode::Code {
+                            self.reference = code::get_snippet(self.code_language, self.test_rng.restart());
+                        }
+                        else if self.mode == Mode::Zen {
+                            self.reference.clear();
+                        }
+                        else if self.mode == Mode::Daily {
+                            self.reference = daily::reference(daily::today());
                             self.batch_size = self.reference.split_whitespace().count();
                         }
                         else {
-                            self.reference = utils::get_reference(self.punctuation, self.numbers, &utils::read_first_n_words(500), self.batch_size);
+                            self.reference = utils::get_reference(self.punctuation, self.numbers, &utils::read_first_n_words(500), self.batch_size, self.test_rng.restart());
                         }
-                        self.is_correct = vec![0; self.reference.chars().count()];
-                        self.pressed_vec.clear();
-                        self.pos1 = 0;
-                        self.words_done = 0;
-                        self.errors_this_second = 0.0;
-                        self.start_time = None;
-                        self.game_state = GameState::NotStarted;
-                        self.speed_per_second.clear();
-                        self.char_number = 0;
-                        self.errors_per_second.clear();
-                        self.tab_pressed = Instant::now() - Duration::from_secs(5);
-                        self.correct_count = 0;
-                        self.error_count = 0;
+                        self.reset_state();
+                        self.notice = (!theme_errors.is_empty()).then(|| Err(theme_errors.join(", ")));
                         self.config = false;
                     }
                 }
@@ -395,50 +963,7 @@ impl App {
                         self.practice_menu = false;
                         return Ok(());
                     }
-                    if self.is_correct[0] == 0 && ch == ' ' {
-                        return Ok(());
-                    }
-                    let reference_chars: Vec<char> = self.reference.chars().collect();
-                    if let Some(&ref_char) = reference_chars.get(self.pos1) {
-                        if self.game_state == GameState::Results {
-                            return Ok(());
-                        }
-                        if self.game_state == GameState::NotStarted {
-                            self.game_state = GameState::Started;
-                            self.start_time = Some(Instant::now());
-                        }
-                        if self.is_correct.len() > self.pos1 {
-                            
-                            if ref_char == ch && self.is_correct[self.pos1] != -1 && self.is_correct[self.pos1] != 1 {
-                                self.is_correct[self.pos1] = 2; // Correct
-                                self.correct_count += 1;
-                                self.pos1 += 1;
-                            } else if ref_char == ch && (self.is_correct[self.pos1] == -1 || self.is_correct[self.pos1] == 1) {
-                                self.is_correct[self.pos1] = 1; // Corrected
-                                self.pos1 += 1;
-                            } else {
-                                self.is_correct[self.pos1] = -1; // Incorrect
-                                self.errors_this_second += 1.0;
-                                self.error_count += 1;
-                                if !self.practice_mode {
-                                    self.pos1 += 1;
-                                }
-                            }
-                        }
-                        
-                        self.pressed_vec.push(ch);
-                        if (reference_chars.get(self.pos1) == Some(&' ') && !self.practice_mode) || (reference_chars.get(self.pos1) == Some(&' ') && self.is_correct[self.pos1] != -1) {
-                            self.words_done += 1;
-                        }
-                    }
-                    self.config = false;
-
-                    if self.pos1 >= self.reference.chars().count() {
-                        self.words_done += 1;
-                        self.reference = utils::get_reference(self.punctuation, self.numbers, &utils::read_first_n_words(500), self.batch_size);
-                        self.is_correct = vec![0; self.reference.chars().count()];
-                        self.pos1 = 0;
-                    }
+                    self.type_char(ch);
                 }
                 _ => {}
             }
@@ -446,3 +971,23 @@ impl App {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn backspace_on_finished_code_test() {
+        // code tests don't roll over into a new batch, the cursor stays past the last letter
+        let mut app = App::new();
+        app.mode = Mode::Code;
+        app.reference = "fn main() {}".to_string();
+        app.is_correct = vec![2; app.reference.chars().count()];
+        app.pos1 = app.is_correct.len();
+        app.game_state = GameState::Results;
+        app.handle_key_event(KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE)).unwrap();
+        app.handle_key_event(KeyEvent::new(KeyCode::Backspace, KeyModifiers::CONTROL)).unwrap();
+        assert_eq!(app.pos1, app.is_correct.len());
+    }
+}
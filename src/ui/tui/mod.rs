@@ -1,12 +1,24 @@
 use std::io;
+use crossterm::event::{DisableBracketedPaste, EnableBracketedPaste};
+use crossterm::execute;
 use crate ::ui::tui::app::App;
+use crate::leaderboard::{self, Submitter};
+use crate::pace::Pace;
+use crate::utils::{Indicators, Strictness, TestRng};
 
 
-pub fn main() -> io::Result<()> {
+pub fn main(strictness: Strictness, indicators: Indicators, pace: Pace, seed: Option<u64>) -> io::Result<()> {
     let mut terminal = ratatui::init();
+    execute!(io::stdout(), EnableBracketedPaste)?;
     let mut app = App::new();
+    app.strictness = strictness;
+    app.indicators = indicators;
+    app.pace = pace;
+    app.test_rng = TestRng::new(seed);
+    app.leaderboard = Submitter::new(leaderboard::load_config());
 
     let app_result = app.run(&mut terminal);
+    execute!(io::stdout(), DisableBracketedPaste)?;
     ratatui::restore();
     app_result
-}
\ No newline at end of file
+}
@@ -0,0 +1,207 @@
+use std::io;
+use crossterm::event::{self, Event as CEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::DefaultTerminal;
+use std::time::{Duration, Instant};
+
+use crate::ui::tui::app::{App, GameState};
+use crate::ui::tui::ui::render_race;
+use crate::race::{Client, Message, Player};
+use crate::{history, leaderboard};
+use crate::utils::Mode;
+
+
+// Progress is sent at most this often while typing.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(PartialEq, Eq)]
+pub enum Phase {
+    Lobby,
+    Countdown,
+    Racing,
+    Finished,
+    Results,
+}
+
+pub struct Race {
+    pub client: Client,
+    pub address: String,
+    pub id: Option<usize>,
+    pub host: bool,
+    pub phase: Phase,
+    pub lobby: Vec<String>,
+    pub players: Vec<Player>,
+    // typing state, the same as in a word test
+    pub app: App,
+    pub reference_len: usize,
+    pub seed: u64,
+    pub go: Instant,
+    pub error: Option<String>,
+    pub exit: bool,
+    sent_position: usize,
+    sent_at: Instant,
+}
+
+impl Race {
+    pub fn new(client: Client, address: String) -> Self {
+        Self {
+            client,
+            address,
+            id: None,
+            host: false,
+            phase: Phase::Lobby,
+            lobby: Vec::new(),
+            players: Vec::new(),
+            app: App::new(),
+            reference_len: 0,
+            seed: 0,
+            go: Instant::now(),
+            error: None,
+            exit: false,
+            sent_position: 0,
+            sent_at: Instant::now(),
+        }
+    }
+
+    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.exit {
+            for message in self.client.poll() {
+                self.handle_message(message);
+            }
+            if event::poll(Duration::from_millis(16))?
+                && let CEvent::Key(key) = event::read()?
+            {
+                self.handle_key_event(key);
+            }
+            self.update();
+            terminal.draw(|frame| render_race(frame, self))?;
+        }
+        Ok(())
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.host
+    }
+
+    pub fn countdown(&self) -> u64 {
+        self.go.saturating_duration_since(Instant::now()).as_secs_f64().ceil() as u64
+    }
+
+    fn handle_message(&mut self, message: Message) {
+        match message {
+            Message::Welcome { id, host } => {
+                self.id = Some(id);
+                self.host = host;
+            }
+            Message::Lobby { players } => self.lobby = players,
+            Message::Race { seed, reference, countdown } => {
+                self.app.reference = reference;
+                self.app.batch_size = self.app.reference.split_whitespace().count();
+                self.app.mode = Mode::Words;
+                self.app.reset_state();
+                self.reference_len = self.app.reference.chars().count();
+                self.seed = seed;
+                self.go = Instant::now() + Duration::from_secs(countdown);
+                self.phase = Phase::Countdown;
+            }
+            Message::Players { players } => self.players = players,
+            Message::Results { players } => {
+                self.players = players;
+                self.phase = Phase::Results;
+            }
+            Message::Error { message } => self.error = Some(message),
+            _ => {}
+        }
+    }
+
+    fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if key_event.kind != KeyEventKind::Press {
+            return;
+        }
+        let delete_word_key = (key_event.code == KeyCode::Backspace && key_event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT))
+            || (matches!(key_event.code, KeyCode::Char('w' | 'h')) && key_event.modifiers.contains(KeyModifiers::CONTROL));
+        match key_event.code {
+            KeyCode::Esc => self.exit = true,
+            KeyCode::Enter if self.phase == Phase::Lobby && self.is_host() => {
+                if let Err(err) = self.client.send(&Message::Start) {
+                    self.error = Some(err);
+                }
+            }
+            _ if self.phase != Phase::Racing => {}
+            _ if delete_word_key => {
+                self.app.log_key('\u{17}');
+                self.app.delete_word(true);
+            }
+            KeyCode::Backspace => {
+                self.app.log_key('\u{8}');
+                self.app.backspace();
+            }
+            KeyCode::Char(ch) => self.app.type_char(ch),
+            _ => {}
+        }
+    }
+
+    fn update(&mut self) {
+        if self.phase == Phase::Countdown && Instant::now() >= self.go {
+            self.phase = Phase::Racing;
+            self.app.game_state = GameState::Started;
+            self.app.start_time = Some(self.go);
+        }
+        // the race status only has room for what went wrong
+        if let Some(Err(err)) = self.app.leaderboard.outcome() {
+            self.error = Some(err);
+        }
+        if self.phase != Phase::Racing {
+            return;
+        }
+        let seconds = self.go.elapsed().as_secs_f64();
+        let wpm = if seconds > 0.0 { self.app.words_done as f64 / seconds * 60.0 } else { 0.0 };
+        if self.app.words_done >= self.app.batch_size {
+            self.finish(seconds);
+        } else if self.app.pos1 != self.sent_position && self.sent_at.elapsed() >= PROGRESS_INTERVAL {
+            self.send_progress(self.app.pos1, wpm);
+        }
+    }
+
+    fn send_progress(&mut self, position: usize, wpm: f64) {
+        if let Err(err) = self.client.send(&Message::Progress { position, wpm }) {
+            self.error = Some(err);
+        }
+        self.sent_position = position;
+        self.sent_at = Instant::now();
+    }
+
+    fn finish(&mut self, seconds: f64) {
+        self.phase = Phase::Finished;
+        let (wpm, raw_wpm, accuracy) = self.app.stats(seconds);
+        self.send_progress(self.reference_len, wpm);
+        if let Err(err) = self.client.send(&Message::Finish { wpm, accuracy, keys: self.app.keys.clone() }) {
+            self.error = Some(err);
+        }
+        let saved = history::add(history::Record {
+            frontend: "tui".to_string(),
+            mode: "race".to_string(),
+            seed: Some(self.seed),
+            seconds,
+            wpm,
+            raw_wpm,
+            accuracy,
+            error_mode: self.app.strictness.error_mode.name().to_string(),
+            reference: self.app.done_batches.clone(),
+            keys: self.app.keys.clone(),
+            ..Default::default()
+        });
+        match saved {
+            Ok(record) => self.app.leaderboard.submit(&record),
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+pub fn main(client: Client, address: String) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let mut race = Race::new(client, address);
+    race.app.leaderboard = leaderboard::Submitter::new(leaderboard::load_config());
+    let race_result = race.run(&mut terminal);
+    ratatui::restore();
+    race_result
+}
@@ -4,34 +4,97 @@ use ratatui::{
     Frame,
 };
 use std::time::Duration;
-use std::collections::HashMap;
 
 use ratatui::widgets::canvas::Canvas;
 use crate::ui::tui::app::{App, GameState};
+use crate::ui::tui::race::{Phase, Race};
+use crate::race::Player;
 use crate::practice::TYPING_LEVELS;
 use crate::practice;
+use crate::code::{self, Syntax};
+use crate::utils::{self, Mode};
+use crate::pace::PaceMode;
+use crate::theme::{self, Mark, Markers, Rgb};
+use crate::color_support::{self, ColorSupport, TermColor};
+
+fn rgb(color: Rgb) -> Color {
+    Color::Rgb(color.0, color.1, color.2)
+}
 
-const BORDER_COLOR: Color = Color::Rgb(100, 60, 0);
-const REF_COLOR: Color = Color::Rgb(100, 100, 100);
-const BG_COLOR: Color = Color::Rgb(10, 10, 10);
-const MAIN_COLOR: Color = Color::Rgb(255, 155, 0);
-const DIMMER_MAIN: Color = Color::Rgb(180, 100, 0);
+fn reduce_color(color: Color, support: ColorSupport) -> Color {
+    match color {
+        Color::Rgb(r, g, b) => match color_support::reduce(Rgb(r, g, b)) {
+            TermColor::Rgb(_) => color,
+            TermColor::Indexed(index) => Color::Indexed(index),
+            TermColor::Default => Color::Reset,
+        },
+        _ if support == ColorSupport::NoColor => Color::Reset,
+        _ => color,
+    }
+}
 
-fn render_instructions(frame: &mut Frame, area: Rect, show: bool, practice_menu: bool) {
-    let mut lines = Vec::new();
-    if show {
-        lines.push(Line::from("  \u{2191} - enter config, \u{2190}/\u{2192} - toggle config, ↵ - apply config"));
-    } else if practice_menu {
-        lines.push(Line::from("  ↑ or ↓ to navigate, ↵ to select"));
-        lines.push(Line::from("  q - quit menu"));
+fn mark_modifier(mark: Option<Mark>) -> Modifier {
+    match mark {
+        Some(Mark::Bold) => Modifier::BOLD,
+        Some(Mark::Underline) => Modifier::UNDERLINED,
+        Some(Mark::Strikethrough) => Modifier::CROSSED_OUT,
+        None => Modifier::empty(),
     }
-    if !practice_menu {
-        lines.push(Line::from("  Tab + Enter - restart"));
+}
+
+// Brings the drawn frame down to the colours the terminal can show.
+// Without colours anything highlighted by its background (cursor, selected buttons) is shown reversed instead.
+fn reduce_colors(frame: &mut Frame) {
+    let support = color_support::support();
+    if support == ColorSupport::TrueColor {
+        return;
+    }
+    let background = bg_color();
+    for cell in frame.buffer_mut().content.iter_mut() {
+        if support == ColorSupport::NoColor && cell.bg != background && cell.bg != Color::Reset {
+            cell.modifier.insert(Modifier::REVERSED);
+        }
+        cell.fg = reduce_color(cell.fg, support);
+        cell.bg = reduce_color(cell.bg, support);
+    }
+}
+
+// The colours of the current theme.
+fn border_color() -> Color { rgb(theme::colors().border) }
+fn ref_color() -> Color { rgb(theme::colors().reference) }
+fn bg_color() -> Color { rgb(theme::colors().background) }
+fn main_color() -> Color { rgb(theme::colors().main) }
+fn dim_main_color() -> Color { rgb(theme::colors().dim_main) }
+fn text_color() -> Color { rgb(theme::colors().text) }
+fn error_color() -> Color { rgb(theme::colors().error) }
+fn extra_color() -> Color { rgb(theme::colors().extra) }
+fn missed_color() -> Color { rgb(theme::colors().missed) }
+fn success_color() -> Color { rgb(theme::colors().success) }
+fn pace_color() -> Color { rgb(theme::colors().pace) }
+
+fn render_instructions(frame: &mut Frame, area: Rect, show: bool, practice_menu: bool, custom_menu: bool, zen_mode: bool) {
+    let mut lines = Vec::new();
+    if custom_menu {
+        lines.push(Line::from("  type a file path or paste text, ↵ - load"));
+        lines.push(Line::from("  ↑ or ↓ to choose a recent text, ↵ to select"));
+        lines.push(Line::from("  Esc - close menu"));
+    } else {
+        if show {
+            lines.push(Line::from("  \u{2191} - enter config, \u{2190}/\u{2192} - toggle config, ↵ - apply config"));
+        } else if practice_menu {
+            lines.push(Line::from("  ↑ or ↓ to navigate, ↵ to select"));
+            lines.push(Line::from("  q - quit menu"));
+        }
+        if zen_mode && show {
+            lines.push(Line::from("  Tab + Enter - restart, Ctrl + D - finish"));
+        } else if !practice_menu {
+            lines.push(Line::from("  Tab + Enter - restart"));
+        }
+        lines.push(Line::from("  Esc - exit"));
     }
-    lines.push(Line::from("  Esc - exit"));
 
     let text = Paragraph::new(lines)
-        .style(Style::default().fg(BORDER_COLOR).bg(BG_COLOR))
+        .style(Style::default().fg(border_color()).bg(bg_color()))
         .alignment(Alignment::Left);
     frame.render_widget(text, area);
 }
@@ -54,11 +117,176 @@ pub fn render_app(frame: &mut Frame, app: &App, timer: Duration) {
         render_results(frame, chunks[0], app);
     } else if app.practice_menu {
         render_practice_menu(frame, chunks[0], app);
+    } else if app.custom_menu {
+        render_custom_menu(frame, chunks[0], app);
     }
     else {
         render_reference_frame(frame, chunks[0], app, timer);
     }
-    render_instructions(frame, chunks[1], app.game_state != GameState::Results && !app.practice_menu, app.practice_menu);
+    render_instructions(frame, chunks[1], app.game_state != GameState::Results && !app.practice_menu, app.practice_menu, app.custom_menu, app.mode == Mode::Zen);
+    reduce_colors(frame);
+}
+
+pub fn render_race(frame: &mut Frame, race: &Race) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(2)])
+        .split(frame.area());
+    let area = chunks[0];
+    let max_ref_width = calculate_max_ref_width(area);
+    let ref_padding = calculate_ref_padding(area, max_ref_width);
+
+    let body = match race.phase {
+        Phase::Lobby => race.lobby.iter()
+            .map(|name| Line::from(name.clone()).style(Style::default().fg(text_color()).bg(bg_color())))
+            .collect(),
+        Phase::Results => create_race_results(race),
+        Phase::Countdown | Phase::Racing => {
+            let mut lines = create_progress_bars(race, max_ref_width);
+            lines.push(Line::from(""));
+            lines.extend(create_colored_lines(&race.app, max_ref_width));
+            lines
+        }
+        // the text is already replaced by the next batch, only the bars are left
+        Phase::Finished => create_progress_bars(race, max_ref_width),
+    };
+    let empty_space = calculate_vertical_padding(area, body.len());
+    let content = assemble_content(create_race_status(race), create_horizontal_line(area), Line::from(""), body, empty_space);
+
+    let paragraph = Paragraph::new(content)
+        .block(create_reference_block(ref_padding))
+        .style(Style::default().bg(bg_color()));
+    frame.render_widget(paragraph, area);
+
+    let instructions = if race.phase == Phase::Lobby && race.is_host() {
+        "  ↵ - start the race, Esc - quit"
+    } else {
+        "  Esc - quit"
+    };
+    let instructions = Paragraph::new(Line::from(instructions))
+        .style(Style::default().fg(ref_color()).bg(bg_color()));
+    frame.render_widget(instructions, chunks[1]);
+    reduce_colors(frame);
+}
+
+fn create_race_status(race: &Race) -> Line<'static> {
+    if let Some(error) = &race.error {
+        return Line::from(error.clone()).style(Style::default().fg(error_color()).bg(bg_color()));
+    }
+    let status = match race.phase {
+        Phase::Lobby if race.is_host() => format!("race on {}, {} joined, ↵ to start", race.address, race.lobby.len()),
+        Phase::Lobby => format!("race on {}, {} joined, waiting for the host", race.address, race.lobby.len()),
+        Phase::Countdown => format!("starts in {}", race.countdown()),
+        Phase::Racing => format!("{}/{}", race.app.words_done, race.app.batch_size),
+        Phase::Finished => "finished, waiting for the others".to_string(),
+        Phase::Results => "results".to_string(),
+    };
+    Line::from(status).style(Style::default().fg(main_color()).bg(bg_color()))
+}
+
+fn player_color(race: &Race, player: &Player) -> Color {
+    if Some(player.id) == race.id { main_color() } else { text_color() }
+}
+
+fn create_progress_bars(race: &Race, max_ref_width: usize) -> Vec<Line<'static>> {
+    let width = max_ref_width.saturating_sub(40).clamp(10, 60);
+    race.players.iter()
+        .map(|player| {
+            let filled = (player.position.min(race.reference_len) * width).checked_div(race.reference_len).unwrap_or(0);
+            let name: String = player.name.chars().take(16).collect();
+            let state = if player.left {
+                "  left".to_string()
+            } else if player.flagged.is_some() {
+                "  flagged".to_string()
+            } else if let Some(seconds) = player.seconds {
+                format!("  {:.1}s", seconds)
+            } else {
+                String::new()
+            };
+            Line::from(vec![
+                Span::styled(format!("{:<17}", name), Style::default().fg(player_color(race, player)).bg(bg_color())),
+                Span::styled("█".repeat(filled), Style::default().fg(player_color(race, player)).bg(bg_color())),
+                Span::styled("░".repeat(width - filled), Style::default().fg(ref_color()).bg(bg_color())),
+                Span::styled(format!(" {:>4.0} wpm{}", player.wpm, state), Style::default().fg(ref_color()).bg(bg_color())),
+            ])
+        })
+        .collect()
+}
+
+fn create_race_results(race: &Race) -> Vec<Line<'static>> {
+    let mut lines = vec![
+        Line::from(format!("{:>3}  {:<17}{:>8}{:>10}{:>9}", "#", "name", "wpm", "accuracy", "time"))
+            .style(Style::default().fg(ref_color()).bg(bg_color())),
+    ];
+    lines.extend(race.players.iter().enumerate().map(|(place, player)| {
+        let name: String = player.name.chars().take(16).collect();
+        let time = if player.left {
+            "left".to_string()
+        } else if player.flagged.is_some() {
+            "flagged".to_string()
+        } else {
+            player.seconds.map_or("-".to_string(), |seconds| format!("{:.1}s", seconds))
+        };
+        Line::from(format!("{:>3}. {:<17}{:>8.0}{:>9.1}%{:>9}", place + 1, name, player.wpm, player.accuracy, time))
+            .style(Style::default().fg(player_color(race, player)).bg(bg_color()))
+    }));
+    lines
+}
+
+fn render_custom_menu(frame: &mut Frame, area: Rect, app: &App) {
+    let block = create_reference_block(3);
+    let inner_area = block.inner(area);
+    let chunks = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Length(2),
+        Constraint::Length(2),
+        Constraint::Min(0),
+    ]).split(inner_area);
+
+    let title = Line::from("Custom text")
+        .style(Style::default().fg(main_color()).bg(bg_color()))
+        .alignment(Alignment::Center);
+
+    let input = Line::from(vec![
+        Span::styled("  file: ", Style::default().fg(ref_color()).bg(bg_color())),
+        Span::styled(app.custom_input.clone(), Style::default().fg(text_color()).bg(bg_color())),
+        Span::styled(" ", Style::default().bg(main_color())),
+    ]);
+
+    let message = Line::from(format!("  {}", app.custom_message))
+        .style(Style::default().fg(error_color()).bg(bg_color()));
+
+    let mut lines: Vec<Line> = vec![
+        Line::from("  recent:").style(Style::default().fg(ref_color()).bg(bg_color())),
+    ];
+    if app.custom_texts.is_empty() {
+        lines.push(Line::from("    nothing here yet").style(Style::default().fg(ref_color()).bg(bg_color())));
+    }
+    let to_skip = (app.selected_custom + 2).saturating_sub(chunks[3].height as usize);
+    for (i, text) in app.custom_texts.iter().enumerate().skip(to_skip) {
+        let (fg_color, row_bg) = if app.selected_custom == i && app.custom_input.is_empty() {
+            (bg_color(), dim_main_color())
+        } else {
+            (ref_color(), bg_color())
+        };
+        lines.push(Line::from(vec![
+            Span::styled("    ", Style::default().bg(bg_color())),
+            Span::styled(
+                format!(" {} (part {}/{}) ", text.name, text.chunk + 1, text.chunk_count()),
+                Style::default().fg(fg_color).bg(row_bg),
+            ),
+        ]));
+    }
+
+    let recent = Paragraph::new(lines)
+        .style(Style::default())
+        .alignment(Alignment::Left);
+
+    frame.render_widget(block, area);
+    frame.render_widget(title, chunks[0]);
+    frame.render_widget(input, chunks[1]);
+    frame.render_widget(message, chunks[2]);
+    frame.render_widget(recent, chunks[3]);
 }
 
 fn render_practice_menu(frame: &mut Frame, area: Rect, app: &App) {
@@ -77,28 +305,28 @@ fn render_practice_menu(frame: &mut Frame, area: Rect, app: &App) {
         0
     };
     for level in TYPING_LEVELS.iter().enumerate().skip(to_skip as usize) {
-        let mut fg_color = REF_COLOR;
-        let mut bg_color = BG_COLOR;
+        let mut fg_color = ref_color();
+        let mut row_bg = bg_color();
         if app.selected_level == level.0 {
-            fg_color = BG_COLOR;
-            bg_color = Color::Rgb(150, 90, 0);
+            fg_color = bg_color();
+            row_bg = dim_main_color();
         }
         let line = if practice::check_if_completed(&format!("practice_results/level_{}.txt", level.0 + 1)) {
             Line::from(vec![
-                Span::styled("✔ ", Style::default().fg(Color::Rgb(0, 255, 0)).bg(BG_COLOR)),
+                Span::styled("✔ ", Style::default().fg(success_color()).bg(bg_color())),
                 if level.0 < 9 {
-                    Span::styled(format!("  {}. {} ", level.0 + 1, level.1.0), Style::default().fg(fg_color).bg(bg_color))
+                    Span::styled(format!("  {}. {} ", level.0 + 1, level.1.0), Style::default().fg(fg_color).bg(row_bg))
                 } else {
-                    Span::styled(format!(" {}. {} ", level.0 + 1, level.1.0), Style::default().fg(fg_color).bg(bg_color))
+                    Span::styled(format!(" {}. {} ", level.0 + 1, level.1.0), Style::default().fg(fg_color).bg(row_bg))
                 }
             ])
         } else {
             Line::from(vec![
-                Span::styled("  ", Style::default().fg(Color::Rgb(0, 255, 0)).bg(BG_COLOR)),
+                Span::styled("  ", Style::default().fg(success_color()).bg(bg_color())),
                 if level.0 < 9 {
-                    Span::styled(format!("  {}. {} ", level.0 + 1, level.1.0), Style::default().fg(fg_color).bg(bg_color))
+                    Span::styled(format!("  {}. {} ", level.0 + 1, level.1.0), Style::default().fg(fg_color).bg(row_bg))
                 } else {
-                    Span::styled(format!(" {}. {} ", level.0 + 1, level.1.0), Style::default().fg(fg_color).bg(bg_color))
+                    Span::styled(format!(" {}. {} ", level.0 + 1, level.1.0), Style::default().fg(fg_color).bg(row_bg))
                 }
             ])
         };
@@ -110,7 +338,7 @@ fn render_practice_menu(frame: &mut Frame, area: Rect, app: &App) {
         .alignment(Alignment::Left);
 
     let title = Line::from("Select practice level")
-        .style(Style::default().fg(MAIN_COLOR).bg(BG_COLOR))
+        .style(Style::default().fg(main_color()).bg(bg_color()))
         .alignment(Alignment::Center);
 
     frame.render_widget(block, area);
@@ -122,7 +350,6 @@ fn smooth(
     values: &[f64],
     average_word_length: f64,
     extra_columns: usize,
-    columns_to_delete: usize,
 ) -> Vec<f64> {
     let len = values.len();
     let mut smoothed = Vec::with_capacity((extra_columns + 1) * len);
@@ -140,11 +367,6 @@ fn smooth(
     };
 
     for i in 0..len - 1 {
-        if (columns_to_delete == 1 && i % 2 == 0) ||
-            (columns_to_delete == 2 && (i % 3 == 0 || i % 3 == 1)) ||
-            (columns_to_delete == 3 && (i % 4 == 0 || i % 4 == 1 || i % 4 == 2)) {
-            continue;
-        }
         let p0 = get(i as isize - 1);
         let p1 = get(i as isize);
         let p2 = get(i as isize + 1);
@@ -178,23 +400,14 @@ fn calc_standard_deviation(values: &[f64], average_word_length: f64) -> f64 {
 }
 
 fn get_stats(app: &App) -> (Line<'static>, Line<'static>) {
-    let wpm = (app.words_done as f32 / app.timer.as_secs_f32()) * 60.0;
+    let (wpm, raw, accuracy) = app.stats(app.timer.as_secs_f64());
     let wpm_str = format!("{}", wpm as i32);
-
-    let accuracy = if app.words_done > 0 {
-        (app.correct_count as f32 / app.pressed_vec.len() as f32) * 100.0
-    } else {
-        0.0
-    };
     let acc_str = format!("{}%", accuracy.round());
-
-    let raw = app.words_done as f32 / (app.timer.as_secs_f32() / 60.0);
-
     let raw_str = format!("{}", raw.round());
 
     let standard_deviation = calc_standard_deviation(&app.speed_per_second, 6.0);
     let consistency = if wpm > 0.0 {
-        (100.0 - (standard_deviation / wpm as f64 * 100.0).round()).max(0.0)
+        (100.0 - (standard_deviation / wpm * 100.0).round()).max(0.0)
     } else {
         0.0
     };
@@ -202,14 +415,10 @@ fn get_stats(app: &App) -> (Line<'static>, Line<'static>) {
 
     let time_str = format!("{:.0}s", app.timer.as_secs_f32());
 
-    let mut mode_str = if app.time_mode {
-        "time".to_string()
-    } else if app.word_mode {
-        "words".to_string()
-    } else if app.quote {
-        "quote".to_string()
+    let mut mode_str = if app.mode == Mode::Code {
+        format!("code {}", app.code_language)
     } else {
-        "practice".to_string()
+        app.mode_name().to_string()
     };
     if app.punctuation {
         mode_str += " !";
@@ -217,10 +426,23 @@ fn get_stats(app: &App) -> (Line<'static>, Line<'static>) {
     if app.numbers {
         mode_str += " #";
     }
+    mode_str += &app.strictness.label();
+    if app.blind_mode && !matches!(app.mode, Mode::Practice | Mode::Zen) {
+        mode_str += " blind";
+    }
+    if app.memory_mode && !matches!(app.mode, Mode::Practice | Mode::Zen) {
+        mode_str += " memory";
+    }
+    if app.failed.is_some() {
+        mode_str += " failed";
+    }
+    if let Some(status) = &app.daily_status {
+        mode_str += &format!(" {}", status);
+    }
 
-    let label_style = Style::default().fg(REF_COLOR).bg(BG_COLOR);
-    let value_style = Style::default().fg(MAIN_COLOR).bg(BG_COLOR);
-    let space_style = Style::default().bg(BG_COLOR);
+    let label_style = Style::default().fg(ref_color()).bg(bg_color());
+    let value_style = Style::default().fg(main_color()).bg(bg_color());
+    let space_style = Style::default().bg(bg_color());
 
     let col_widths = [3, 4, 4, 4, 4, 8];
 
@@ -286,22 +508,22 @@ fn get_chart(smoothed_speeds: &[f64], app: &App, step: usize) -> Chart<'static>
 
     let bar_dataset = Dataset::default()
         .graph_type(GraphType::Bar)
-        .style(Style::default().fg(Color::Rgb(150, 80, 0)).bg(BG_COLOR))
+        .style(Style::default().fg(dim_main_color()).bg(bg_color()))
         .marker(symbols::Marker::HalfBlock)
         .data(data);
     
     let chart = Chart::new(vec![bar_dataset])
-    .block(Block::default().style(Style::default().bg(BG_COLOR)))
-        .bg(BG_COLOR)
-        .style(Style::default().bg(BG_COLOR))
+    .block(Block::default().style(Style::default().bg(bg_color())))
+        .bg(bg_color())
+        .style(Style::default().bg(bg_color()))
         .x_axis(
             Axis::default()
-                .style(Style::default().fg(REF_COLOR))
+                .style(Style::default().fg(ref_color()))
                 .bounds([0.0, smoothed_speeds.len() as f64])
                 .labels(
                     (0..=max_time as usize)
                         .step_by(step)
-                        .map(|i| Span::styled(format!("{i}s"), Style::default().fg(REF_COLOR)))
+                        .map(|i| Span::styled(format!("{i}s"), Style::default().fg(ref_color())))
                         .collect::<Vec<Span>>(),
                 ),
         )
@@ -309,12 +531,12 @@ fn get_chart(smoothed_speeds: &[f64], app: &App, step: usize) -> Chart<'static>
             Axis::default()
                 .title("wpm")
                 .labels_alignment(ratatui::layout::Alignment::Left)
-                .style(Style::default().fg(REF_COLOR))
+                .style(Style::default().fg(ref_color()))
                 .bounds([0.0, max_speed * 1.1])
                 .labels(vec![
-                    Span::from("0").style(Style::default().fg(REF_COLOR)),
-                    Span::from(format!("{:.0}", max_speed / 2.0)).style(Style::default().fg(REF_COLOR)),
-                    Span::from(format!("{:.0}", max_speed)).style(Style::default().fg(REF_COLOR)),
+                    Span::from("0").style(Style::default().fg(ref_color())),
+                    Span::from(format!("{:.0}", max_speed / 2.0)).style(Style::default().fg(ref_color())),
+                    Span::from(format!("{:.0}", max_speed)).style(Style::default().fg(ref_color())),
                 ]),
         );
     chart
@@ -322,91 +544,32 @@ fn get_chart(smoothed_speeds: &[f64], app: &App, step: usize) -> Chart<'static>
 
 fn render_results(frame: &mut Frame, area: Rect, app: &App) {
     frame.render_widget(
-        Block::default().style(Style::default().bg(BG_COLOR)),
+        Block::default().style(Style::default().bg(bg_color())),
         area,
     );
 
     let (wpm_line, acc_line) = get_stats(app);
 
-    let columns_for_sec: HashMap<u32, usize> = [(5, 4), (15, 3), (30, 2), (60, 1)]
-        .iter()
-        .cloned()
-        .collect();
+    let test_time = app.timer.as_secs_f32().round().max(1.0) as usize;
 
-    let test_time = app.timer.as_secs_f32().round() as u32;
-    let mut extra_columns = columns_for_sec
-        .keys()
-        .filter(|&&k| k >= test_time)
-        .min()
-        .or_else(|| columns_for_sec.keys().max())
-        .map(|k| columns_for_sec[k])
-        .unwrap_or(1);
-
-    let mut step = 5;
-    let mut columns_to_delete = 0;
-
-    if area.width < 70 {
-        if test_time >= 120 {
-            extra_columns = 1;
-            step = 30;
-            columns_to_delete = 3;
-        } else if test_time >= 60 {
-            extra_columns = 1;
-            step = 15;
-            columns_to_delete = 2;
-        } else if test_time >= 15 {
-            extra_columns = 1;
-            step = 10;
-            columns_to_delete = 1;
-        } else if test_time >= 5 {
-            extra_columns = 1;
-        }
-    } else if area.width < 100 {
-        if test_time >= 60 {
-            extra_columns = 1;
-            step = 20;
-            columns_to_delete = 2;
-        } else if test_time >= 30 {
-            step = 10;
-            columns_to_delete = 1;
-        } else if test_time >= 15 {
-            extra_columns = 1;
-        } else if test_time >= 5 {
-            extra_columns = 2;
-        }
-    } else if area.width < 150 {
-        if test_time >= 60 {
-            step = 20;
-            columns_to_delete = 1;
-        } else if test_time >= 30 {
-            extra_columns = 1;
-        } else if test_time >= 15 {
-            extra_columns = 2;
-        } else if test_time >= 5 {
-            extra_columns = 3;
-        }
-    } else if test_time >= 120 {
-        step = 10;
-    }
+    // Every chart point takes two columns, long tests are averaged over several seconds per point so they still fit
+    let max_points = (area.width.saturating_sub(20) / 2).max(10) as usize;
+    let bucket = app.speed_per_second.len().div_ceil(max_points).max(1);
+    let speed_per_second: Vec<f64> = app.speed_per_second
+        .chunks(bucket)
+        .map(|chunk| chunk.iter().sum::<f64>() / chunk.len() as f64)
+        .collect();
+    let mut errors_per_second: Vec<f32> = app.errors_per_second
+        .chunks(bucket)
+        .map(|chunk| chunk.iter().sum())
+        .collect();
+    let extra_columns = (max_points / speed_per_second.len().max(1)).clamp(1, 4);
+    let step = [5, 10, 15, 30, 60, 120, 300, 600, 1800]
+        .into_iter()
+        .find(|&step| test_time / step <= 8)
+        .unwrap_or(3600);
 
-    let mut errors_per_second: Vec<f32> = Vec::new();
-    let mut speed_per_second: Vec<f64> = Vec::new();
     let mut prev = 0.0;
-
-    if test_time >= 120 {
-        let mut errs = app.errors_per_second.clone();
-        for (i, err) in errs.iter_mut().enumerate() {
-            if i % 2 == 0 {
-                errors_per_second.push(*err);
-                speed_per_second.push(app.speed_per_second[i]);
-            }
-            prev = *err;
-        }
-    } else {
-        errors_per_second = app.errors_per_second.clone();
-        speed_per_second = app.speed_per_second.clone();
-    }
-
     for (i, err) in errors_per_second.iter_mut().enumerate() {
         let prev_val = prev;
         if i > 0 && *err > 0.0 && prev_val > 0.0 {
@@ -414,16 +577,14 @@ fn render_results(frame: &mut Frame, area: Rect, app: &App) {
         }
         prev = *err;
     }
-    errors_per_second[0] = 0.0;
-    if errors_per_second.len() > 1 {
-        errors_per_second[1] = 0.0;
+    for err in errors_per_second.iter_mut().take(2) {
+        *err = 0.0;
     }
 
     let smoothed_speeds = smooth(
         &speed_per_second,
         6.0,
         extra_columns,
-        columns_to_delete,
     );
 
     let chart = get_chart(&smoothed_speeds, app, step);
@@ -463,14 +624,19 @@ fn render_results(frame: &mut Frame, area: Rect, app: &App) {
     };
 
     let stats = Paragraph::new(vec![wpm_line, acc_line])
-        .style(Style::default().bg(BG_COLOR))
+        .style(Style::default().bg(bg_color()))
         .alignment(Alignment::Center);
 
-    let empty_line = Line::from("");
+    let notice_line = match &app.notice {
+        Some(Ok(message)) => Line::from(message.clone()).style(Style::default().fg(text_color()).bg(bg_color())),
+        Some(Err(err)) => Line::from(err.clone()).style(Style::default().fg(error_color()).bg(bg_color())),
+        None => Line::from("").style(Style::default().bg(bg_color())),
+    }
+    .alignment(Alignment::Center);
 
     frame.render_widget(block, area);
     frame.render_widget(
-        Block::default().style(Style::default().bg(BG_COLOR)),
+        Block::default().style(Style::default().bg(bg_color())),
         chart_area,
     );
     frame.render_widget(chart, chart_area);
@@ -485,10 +651,10 @@ fn render_results(frame: &mut Frame, area: Rect, app: &App) {
     );
 
     let canvas = Canvas::default()
-        .block(Block::default().style(Style::default().bg(BG_COLOR)))
+        .block(Block::default().style(Style::default().bg(bg_color())))
         .x_bounds([0.0, smoothed_speeds.len() as f64])
         .y_bounds([0.0, max_speed * 1.1])
-        .background_color(BG_COLOR)
+        .background_color(bg_color())
         .paint(|ctx| {
             for (i, err) in errors_per_second.iter().enumerate() {
                 let cross: &str;
@@ -503,9 +669,9 @@ fn render_results(frame: &mut Frame, area: Rect, app: &App) {
                 }
                 if *err > 0.0 {
                     ctx.print(
-                        (i as f64 + 0.8) * (smoothed_speeds.len() as f64 / test_time as f64),
+                        (i as f64 + 0.8) * extra_columns as f64,
                         1.0,
-                        Span::styled(cross, Style::default().fg(Color::Red).bg(BG_COLOR)),
+                        Span::styled(cross, Style::default().fg(error_color()).bg(bg_color())),
                     );
                 }
             }
@@ -513,7 +679,7 @@ fn render_results(frame: &mut Frame, area: Rect, app: &App) {
 
     frame.render_widget(canvas, chart_area);
 
-    frame.render_widget(empty_line, chunks[1]);
+    frame.render_widget(notice_line, chunks[1]);
     frame.render_widget(stats, chunks[2]);
 }
 
@@ -523,11 +689,20 @@ fn render_reference_frame(frame: &mut Frame, area: Rect, app: &App, timer: Durat
 
     let instruction_line = create_config_line(app);
     let horizontal_line = create_horizontal_line(area);
-    let time_words = if app.time_mode {
+    let time_words = if app.mode == Mode::Time {
         create_timer(timer, app.test_time)
+    } else if app.mode == Mode::Code {
+        create_words_count(code::count_lines(&app.reference), code::lines_done(&app.reference, app.pos1))
+    } else if app.mode == Mode::Zen {
+        create_live_wpm(timer, app.words_done)
     } else {
         create_words_count(app.batch_size, app.words_done)
     };
+    let mut time_words = add_live_indicators(time_words, app, timer);
+    // a theme file that couldn't be read, the results screen shows its own notices
+    if let Some(Err(err)) = &app.notice {
+        time_words.push_span(Span::styled(format!("    {}", err), Style::default().fg(error_color()).bg(bg_color())));
+    }
     let colored_lines = create_colored_lines(app, max_ref_width);
     let empty_space = calculate_vertical_padding(area, colored_lines.len());
 
@@ -543,7 +718,7 @@ fn render_reference_frame(frame: &mut Frame, area: Rect, app: &App, timer: Durat
     let block = create_reference_block(ref_padding);
     let paragraph = Paragraph::new(content)
         .block(block)
-        .style(Style::default().bg(BG_COLOR));
+        .style(Style::default().bg(bg_color()));
 
     frame.render_widget(paragraph, area);
 }
@@ -565,56 +740,120 @@ fn create_timer(timer: Duration, test_time: f32) -> Line<'static> {
     let formatted_time = format!("{:?}", seconds as i32);
     
     Line::from(formatted_time)
-        .style(Style::default().fg(MAIN_COLOR).bg(BG_COLOR))
+        .style(Style::default().fg(main_color()).bg(bg_color()))
         .alignment(Alignment::Left)
 }
 
 fn create_words_count(all_words: usize, typed_words: usize) -> Line<'static> {
     let words_text = format!("{}/{}", typed_words, all_words);
     Line::from(words_text)
-        .style(Style::default().fg(MAIN_COLOR).bg(BG_COLOR))
+        .style(Style::default().fg(main_color()).bg(bg_color()))
         .alignment(Alignment::Left)
 }
 
+fn create_live_wpm(timer: Duration, words_done: usize) -> Line<'static> {
+    let wpm = utils::live_wpm(words_done, timer.as_secs_f64());
+    Line::from(format!("{:.0} wpm  {}s", wpm, timer.as_secs()))
+        .style(Style::default().fg(main_color()).bg(bg_color()))
+        .alignment(Alignment::Left)
+}
+
+// Live wpm, accuracy and progress after the timer or word count, whichever are turned on.
+fn add_live_indicators(line: Line<'static>, app: &App, timer: Duration) -> Line<'static> {
+    let seconds = timer.as_secs_f64();
+    let mut live = app.indicators.text(utils::live_wpm(app.words_done, seconds), app.accuracy());
+    if app.indicators.progress && app.mode != Mode::Zen {
+        let time_limit = (app.mode == Mode::Time).then_some(app.test_time as f64);
+        let progress = utils::live_progress(time_limit, seconds, app.pos1, app.reference.chars().count());
+        live = format!("{}  {}", live, utils::progress_bar(progress, 20)).trim_start().to_string();
+    }
+    let mut line = line;
+    if !live.is_empty() {
+        line.push_span(Span::styled(format!("    {}", live), Style::default().fg(ref_color()).bg(bg_color())));
+    }
+    line
+}
+
 fn create_config_line( app: &App) -> Line<'static> {
     let divider = true;
+    let mode = app.mode;
     let mut button_states = vec![
-        ("! punctuation", app.punctuation, !app.quote && !app.practice_mode),
-        ("# numbers", app.numbers, !app.quote && !app.practice_mode),
-        ("|", divider, app.word_mode || app.time_mode),
-        ("time", app.time_mode, true),
-        ("words", app.word_mode, true),
-        ("quote", app.quote, true),
-        ("practice", app.practice_mode, true),
-        ("|", divider, app.word_mode || app.time_mode),
-        ("15", app.test_time == 15.0, app.time_mode),
-        ("30", app.test_time == 30.0, app.time_mode),
-        ("60", app.test_time == 60.0, app.time_mode),
-        ("120", app.test_time == 120.0, app.time_mode),
-        ("25", app.batch_size == 25, app.word_mode),
-        ("50", app.batch_size == 50, app.word_mode),
-        ("100", app.batch_size == 100, app.word_mode),
+        ("! punctuation", app.punctuation, mode.allows_punctuation()),
+        ("# numbers", app.numbers, mode.allows_punctuation()),
+        ("sudden death", app.strictness.sudden_death, mode.allows_strictness()),
+        ("word reset", app.strictness.delete_word_on_error, mode.allows_error_settings()),
+        ("min acc", app.strictness.min_accuracy.is_some(), mode.allows_strictness()),
+        ("errors", app.strictness.error_mode != utils::ErrorMode::Off, mode.allows_error_settings()),
+        ("blind", app.blind_mode, mode.allows_error_settings()),
+        ("memory", app.memory_mode, mode.allows_error_settings()),
+        ("live wpm", app.indicators.wpm, true),
+        ("live acc", app.indicators.accuracy, true),
+        ("progress", app.indicators.progress, true),
+        ("pace", app.pace.mode != PaceMode::Off, mode.allows_strictness()),
+        ("theme", true, true),
+        ("markers", theme::markers() != Markers::Off, mode.allows_strictness()),
+        ("|", divider, matches!(app.mode, Mode::Words | Mode::Time)),
+        ("time", app.mode == Mode::Time, true),
+        ("words", app.mode == Mode::Words, true),
+        ("quote", app.mode == Mode::Quote, true),
+        ("custom", app.mode == Mode::Custom, true),
+        ("code", app.mode == Mode::Code, true),
+        ("zen", app.mode == Mode::Zen, true),
+        ("daily", app.mode == Mode::Daily, true),
+        ("practice", app.mode == Mode::Practice, true),
+        ("|", divider, matches!(app.mode, Mode::Words | Mode::Time | Mode::Code)),
+        ("15", app.test_time == 15.0, app.mode == Mode::Time),
+        ("30", app.test_time == 30.0, app.mode == Mode::Time),
+        ("60", app.test_time == 60.0, app.mode == Mode::Time),
+        ("120", app.test_time == 120.0, app.mode == Mode::Time),
+        ("25", app.batch_size == 25, app.mode == Mode::Words),
+        ("50", app.batch_size == 50, app.mode == Mode::Words),
+        ("100", app.batch_size == 100, app.mode == Mode::Words),
+        ("edit", !app.is_preset_length(), matches!(app.mode, Mode::Time | Mode::Words)),
+        ("rust", app.code_language == "rust", app.mode == Mode::Code),
+        ("python", app.code_language == "python", app.mode == Mode::Code),
+        ("javascript", app.code_language == "javascript", app.mode == Mode::Code),
+        ("go", app.code_language == "go", app.mode == Mode::Code),
+        ("c", app.code_language == "c", app.mode == Mode::Code),
+        ("auto indent", app.code_auto_indent, app.mode == Mode::Code),
+        ("80%", app.strictness.min_accuracy == Some(80.0), app.strictness.min_accuracy.is_some() && app.mode != Mode::Zen),
+        ("90%", app.strictness.min_accuracy == Some(90.0), app.strictness.min_accuracy.is_some() && app.mode != Mode::Zen),
+        ("95%", app.strictness.min_accuracy == Some(95.0), app.strictness.min_accuracy.is_some() && app.mode != Mode::Zen),
     ];
 
     let mut spans: Vec<Span<'static>> = vec![];
 
-    let mut fg_colors = vec![REF_COLOR; button_states.len()];
-    let mut bg_colors = vec![BG_COLOR; button_states.len()];
+    let mut fg_colors = vec![ref_color(); button_states.len()];
+    let mut bg_colors = vec![bg_color(); button_states.len()];
     for (i, (label, state_val, visible)) in button_states.iter_mut().enumerate() {
         if !*visible {
             continue;
         }
         if *state_val && app.selected_config == *label && app.config && *label != "|" {
-            bg_colors[i] = DIMMER_MAIN;
-            fg_colors[i] = BG_COLOR;
+            bg_colors[i] = dim_main_color();
+            fg_colors[i] = bg_color();
         } else if app.selected_config == *label && app.config && *label != "|" {
-            bg_colors[i] = BORDER_COLOR;
-            fg_colors[i] = BG_COLOR;
+            bg_colors[i] = border_color();
+            fg_colors[i] = bg_color();
         } else if *state_val {
-            fg_colors[i] = MAIN_COLOR;
+            fg_colors[i] = main_color();
         } else {
-            fg_colors[i] = REF_COLOR;
+            fg_colors[i] = ref_color();
         }
+        let label = if *label == "edit" {
+            let value = if app.mode == Mode::Time { app.test_time as usize } else { app.batch_size };
+            utils::length_label(app.length_input.as_deref(), value, app.is_preset_length())
+        } else if *label == "errors" {
+            app.strictness.error_mode.label().to_string()
+        } else if *label == "pace" {
+            app.pace.label()
+        } else if *label == "theme" {
+            format!("theme {}", theme::name())
+        } else if *label == "markers" {
+            theme::markers().label().to_string()
+        } else {
+            label.to_string()
+        };
         spans.push(Span::styled(
             format!(" {} ", label),
             Style::default().fg(fg_colors[i]).bg(bg_colors[i]),
@@ -626,53 +865,108 @@ fn create_config_line( app: &App) -> Line<'static> {
 
 fn create_horizontal_line(area: Rect) -> Line<'static> {
     Line::from("─".repeat(area.width.saturating_sub(15) as usize)
-        .fg(BORDER_COLOR)
-        .bg(BG_COLOR))
+        .fg(border_color())
+        .bg(bg_color()))
 }
 
 fn create_colored_lines<'a>(app: &App, max_ref_width: usize) -> Vec<Line<'a>> {
-    let mut fg_colors: Vec<Color> = vec![REF_COLOR; app.reference.chars().count()];
-    let mut bg_colors: Vec<Color> = vec![BG_COLOR; app.reference.chars().count()];
+    let mut fg_colors: Vec<Color> = vec![ref_color(); app.reference.chars().count()];
+    let mut bg_colors: Vec<Color> = vec![bg_color(); app.reference.chars().count()];
+    let mut modifiers: Vec<Modifier> = vec![Modifier::empty(); app.reference.chars().count()];
+    let markers = theme::markers();
+    let syntax = if app.mode == Mode::Code {
+        code::highlight(&app.reference, app.code_language)
+    } else {
+        Vec::new()
+    };
 
     for i in 0..app.is_correct.len() {
         if app.pos1 == i {
-            fg_colors[i] = BG_COLOR;
-            bg_colors[i] = MAIN_COLOR
+            fg_colors[i] = bg_color();
+            bg_colors[i] = main_color()
         } else if app.is_correct[i] == 0 || i >= app.pos1{
-            fg_colors[i] = REF_COLOR;
+            fg_colors[i] = syntax_color(syntax.get(i));
+        } else if app.blind_mode && app.mode != Mode::Practice {
+            // no right or wrong until the results
+            fg_colors[i] = text_color();
         } else if app.is_correct[i] == 2 {
-            fg_colors[i] = Color::White;
+            fg_colors[i] = text_color();
         } else if app.is_correct[i] == 1 {
-            fg_colors[i] = MAIN_COLOR;
+            fg_colors[i] = main_color();
+            modifiers[i] = mark_modifier(markers.corrected());
         } else if app.is_correct[i] == -1 {
-            fg_colors[i] = Color::Rgb(255, 0, 0);
+            fg_colors[i] = error_color();
+            modifiers[i] = mark_modifier(markers.error());
+        } else if app.is_correct[i] == 4 {
+            fg_colors[i] = missed_color();
+            modifiers[i] = mark_modifier(markers.error());
         } else {
-            fg_colors[i] = REF_COLOR;
+            fg_colors[i] = ref_color();
         }
     }
 
-    let split = split_lines(&app.reference, max_ref_width);
+    if let Some(pace) = app.pace_position().filter(|&pace| pace != app.pos1) {
+        bg_colors[pace] = pace_color();
+    }
+
+    let split = if app.mode == Mode::Code {
+        code::split_lines(&app.reference, max_ref_width)
+    } else {
+        split_lines(&app.reference, max_ref_width)
+    };
+
+    let reference_chars: Vec<char> = app.reference.chars().collect();
+    let memory_mode = app.memory_mode && !matches!(app.mode, Mode::Zen | Mode::Practice) && app.game_state != GameState::Results;
 
     let mut char_index = 0;
-    split.into_iter()
+    let mut lines: Vec<Line<'a>> = split.into_iter()
         .map(|line| {
-            let spans: Vec<Span<'a>> = line
-                .chars()
-                .map(|c| {
-                    let fg_color = fg_colors.get(char_index).cloned().unwrap_or(REF_COLOR);
-                    let bg_color = bg_colors.get(char_index).cloned().unwrap_or(BG_COLOR);
-                    char_index += 1;
-                    Span::styled(c.to_string(), Style::default().fg(fg_color).bg(bg_color))
-                })
-                .collect();
+            let mut spans: Vec<Span<'a>> = vec![];
+            for c in line.chars() {
+                // letters typed past the end of the word overflow inline before its space
+                if let Some(extra) = app.extra_chars.get(&char_index) {
+                    let (extra_fg, extra_modifier) = if app.blind_mode {
+                        (text_color(), Modifier::empty())
+                    } else {
+                        (extra_color(), mark_modifier(markers.error()))
+                    };
+                    spans.extend(extra.chars().map(|e| Span::styled(e.to_string(), Style::default().fg(extra_fg).bg(bg_color()).add_modifier(extra_modifier))));
+                }
+                let fg_color = fg_colors.get(char_index).cloned().unwrap_or(ref_color());
+                let char_bg = bg_colors.get(char_index).cloned().unwrap_or(bg_color());
+                let modifier = modifiers.get(char_index).cloned().unwrap_or(Modifier::empty());
+                let hidden = memory_mode && app.memory.hidden(&reference_chars, app.pos1, char_index);
+                char_index += 1;
+                // the newline itself has to be typed, so it gets a visible cell
+                let c = if c == '\n' { '↵' } else if hidden { '_' } else { c };
+                spans.push(Span::styled(c.to_string(), Style::default().fg(fg_color).bg(char_bg).add_modifier(modifier)));
+            }
             Line::from(spans)
         })
-        .collect()
+        .collect();
+
+    if app.mode == Mode::Zen {
+        // Nothing to follow in zen mode, keep the cursor after the typed text and show only the latest lines
+        let cursor = Span::styled(" ", Style::default().fg(bg_color()).bg(main_color()));
+        match lines.last_mut() {
+            Some(last) => {
+                // split_lines puts a space after every word, drop it until one is actually typed
+                if !app.reference.ends_with(' ') {
+                    last.spans.pop();
+                }
+                last.spans.push(cursor);
+            }
+            None => lines.push(Line::from(cursor)),
+        }
+        let hidden = lines.len().saturating_sub(5);
+        lines.drain(..hidden);
+    }
+    lines
 }
 
 fn calculate_vertical_padding(area: Rect, content_lines: usize) -> usize {
     let empty_space = area.height.saturating_sub(3) as usize / 2;
-    empty_space.saturating_sub(content_lines / 2) - 3
+    empty_space.saturating_sub(content_lines / 2).saturating_sub(3)
 }
 
 fn assemble_content<'a>(
@@ -700,11 +994,11 @@ fn assemble_content<'a>(
 fn create_reference_block(ref_padding: u16) -> Block<'static> {
     Block::default()
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(BORDER_COLOR).bg(BG_COLOR))
-        .style(Style::default().bg(BG_COLOR))
+        .border_style(Style::default().fg(border_color()).bg(bg_color()))
+        .style(Style::default().bg(bg_color()))
         .title(Line::from(vec![
-            " Type".fg(MAIN_COLOR).bg(BG_COLOR),
-            "Man ".fg(Color::White).bg(BG_COLOR),
+            " Type".fg(main_color()).bg(bg_color()),
+            "Man ".fg(text_color()).bg(bg_color()),
         ]))
         .padding(Padding {
             left: ref_padding,
@@ -715,6 +1009,17 @@ fn create_reference_block(ref_padding: u16) -> Block<'static> {
         .title_alignment(Alignment::Left)
 }
 
+fn syntax_color(syntax: Option<&Syntax>) -> Color {
+    match syntax {
+        Some(Syntax::Keyword) => rgb(theme::colors().keyword),
+        Some(Syntax::String) => rgb(theme::colors().string),
+        Some(Syntax::Comment) => rgb(theme::colors().comment),
+        Some(Syntax::Number) => rgb(theme::colors().number),
+        Some(Syntax::Bracket) => rgb(theme::colors().bracket),
+        _ => ref_color(),
+    }
+}
+
 fn split_lines(text: &str, width: usize) -> Vec<String> {
     text.lines()
         .flat_map(|line| {
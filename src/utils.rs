@@ -1,14 +1,21 @@
-use std::path::PathBuf;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 use rand::prelude::IndexedRandom;
 use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
 use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
-use crate::Quote;
+use serde::Deserialize;
 
 const COMMON_WORDS: &str = include_str!("../assets/common_eng_words.txt");
 const QUOTES: &str = include_str!("../assets/quotes.json");
 
+#[derive(Debug, Deserialize)]
+pub struct Quote {
+    pub author: String,
+    pub text: String,
+}
+
 
 pub fn read_first_n_words(n: usize) -> Vec<String> {
     COMMON_WORDS
@@ -18,17 +25,34 @@ pub fn read_first_n_words(n: usize) -> Vec<String> {
         .collect()
 }
 
-pub fn validate_custom_file(path: &PathBuf) -> Result<(), String> {
-    if path.exists() && path.is_file() {
-        Ok(())
-    } else {
-        Err(format!("Custom file does not exist or is not a file: {:?}", path))
+// Random source of one test: the seed it was generated from and the generator later batches continue with.
+// A fixed seed (--seed) gives every test the same one, so the same settings always produce the same text.
+#[derive(Debug, Clone)]
+pub struct TestRng {
+    pub seed: u64,
+    fixed: Option<u64>,
+    rng: StdRng,
+}
+
+impl TestRng {
+    pub fn new(fixed: Option<u64>) -> Self {
+        let seed = fixed.unwrap_or_else(rand::random);
+        TestRng { seed, fixed, rng: StdRng::seed_from_u64(seed) }
+    }
+
+    // For a new test, picks a new seed unless it's fixed.
+    pub fn restart(&mut self) -> &mut StdRng {
+        *self = TestRng::new(self.fixed);
+        &mut self.rng
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
     }
 }
 
-pub fn get_reference(punctuation: bool, digits: bool, word_list: &[String], batch_size: usize) -> String {
+pub fn get_reference(punctuation: bool, digits: bool, word_list: &[String], batch_size: usize, rng: &mut impl Rng) -> String {
     let mut items = Vec::new();
-    let mut rng = rand::rng();
 
     let num_digits = if digits {
         let max_digits = batch_size.min(batch_size / 3).max(1);
@@ -40,7 +64,7 @@ pub fn get_reference(punctuation: bool, digits: bool, word_list: &[String], batc
     let num_words = batch_size - num_digits;
 
     for _ in 0..num_words {
-        let mut word = word_list.choose(&mut rng).unwrap().clone();
+        let mut word = word_list.choose(rng).unwrap().clone();
         if punctuation {
             let punctuations = [".", ",", "!", "?", ";", ":"];
             if rng.random_bool(0.2) {
@@ -50,7 +74,7 @@ pub fn get_reference(punctuation: bool, digits: bool, word_list: &[String], batc
                 }
             }
             if rng.random_bool(0.2) {
-                word.push_str(punctuations.choose(&mut rng).unwrap());
+                word.push_str(punctuations.choose(rng).unwrap());
             }
         }
         items.push(word);
@@ -71,26 +95,383 @@ pub fn get_reference(punctuation: bool, digits: bool, word_list: &[String], batc
         continue;
     }
 
-    items.shuffle(&mut rng);
+    items.shuffle(rng);
 
     items.join(" ").replace('\n', " ")
 }
 
-pub fn get_random_quote() -> String {
+pub fn get_random_quote(rng: &mut impl Rng) -> String {
     let quotes: Vec<Quote> = serde_json::from_str(QUOTES).unwrap_or_default();
 
-    let mut rng = rand::rng();
-
     let fallback = Quote {
         text: "Welcome to TypeMan!".to_string(),
         author: "mzums".to_string(),
     };
 
-    let random_quote = quotes.choose(&mut rng).unwrap_or(&fallback);
+    let random_quote = quotes.choose(rng).unwrap_or(&fallback);
 
     format!("\"{}\" - {}", random_quote.text, random_quote.author)
 }
 
+// True when `pos` is the whitespace right after a word, newlines and indentation included.
+pub fn is_word_end(reference_chars: &[char], pos: usize) -> bool {
+    pos > 0
+        && reference_chars.get(pos).is_some_and(|c| c.is_whitespace())
+        && !reference_chars[pos - 1].is_whitespace()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ErrorMode {
+    #[default]
+    Off,
+    StopOnLetter,
+    StopOnWord,
+    NoBackspace,
+}
+
+impl ErrorMode {
+    pub const NAMES: [&'static str; 4] = ["off", "letter", "word", "no-backspace"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(ErrorMode::Off),
+            "letter" => Some(ErrorMode::StopOnLetter),
+            "word" => Some(ErrorMode::StopOnWord),
+            "no-backspace" => Some(ErrorMode::NoBackspace),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorMode::Off => "off",
+            ErrorMode::StopOnLetter => "letter",
+            ErrorMode::StopOnWord => "word",
+            ErrorMode::NoBackspace => "no-backspace",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorMode::Off => "errors off",
+            ErrorMode::StopOnLetter => "stop on letter",
+            ErrorMode::StopOnWord => "stop on word",
+            ErrorMode::NoBackspace => "no backspace",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            ErrorMode::Off => ErrorMode::StopOnLetter,
+            ErrorMode::StopOnLetter => ErrorMode::StopOnWord,
+            ErrorMode::StopOnWord => ErrorMode::NoBackspace,
+            ErrorMode::NoBackspace => ErrorMode::Off,
+        }
+    }
+
+    // A correct space is refused in stop on word while the word still has uncorrected errors.
+    pub fn accepts<'a>(self, reference_chars: &[char], is_correct: impl IntoIterator<Item = &'a i32>, pos: usize, ch: char) -> bool {
+        if reference_chars.get(pos) != Some(&ch) {
+            return false;
+        }
+        if self == ErrorMode::StopOnWord && ch.is_whitespace() {
+            let word_start = reference_chars[..pos].iter().rposition(|c| c.is_whitespace()).map_or(0, |p| p + 1);
+            return !is_correct.into_iter().skip(word_start).take(pos - word_start).any(|&v| v == -1);
+        }
+        true
+    }
+
+    // Whether the cursor moves on after a key press that was or wasn't accepted.
+    pub fn advances(self, reference_chars: &[char], pos: usize, accepted: bool) -> bool {
+        accepted || match self {
+            ErrorMode::Off | ErrorMode::NoBackspace => true,
+            ErrorMode::StopOnLetter => false,
+            ErrorMode::StopOnWord => !reference_chars.get(pos).is_some_and(|c| c.is_whitespace()),
+        }
+    }
+
+    pub fn allows_backspace(self) -> bool {
+        self != ErrorMode::NoBackspace
+    }
+}
+
+// The kind of test, its name is what results are saved and compared under in every frontend.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Time,
+    Words,
+    Quote,
+    Custom,
+    Code,
+    Zen,
+    Daily,
+    Practice,
+    Book,
+}
+
+impl Mode {
+    // In the order the mode flags are passed to `from_flags`.
+    const FLAGGED: [Mode; 7] = [Mode::Time, Mode::Words, Mode::Quote, Mode::Custom, Mode::Code, Mode::Zen, Mode::Daily];
+
+    // The first set flag wins, practice when none is.
+    pub fn from_flags(flags: [bool; 7]) -> Self {
+        Self::FLAGGED.into_iter().zip(flags).find(|&(_, set)| set).map_or(Mode::Practice, |(mode, _)| mode)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Time => "time",
+            Mode::Words => "words",
+            Mode::Quote => "quote",
+            Mode::Custom => "custom",
+            Mode::Code => "code",
+            Mode::Zen => "zen",
+            Mode::Daily => crate::daily::MODE,
+            Mode::Practice => "practice",
+            Mode::Book => "book",
+        }
+    }
+
+    // Results saved before the frontends shared these names called the words mode "word".
+    pub fn canonical_name(name: &str) -> &str {
+        match name {
+            "word" => Mode::Words.name(),
+            _ => name,
+        }
+    }
+
+    // Only generated word lists can take punctuation and numbers.
+    pub fn allows_punctuation(self) -> bool {
+        matches!(self, Mode::Time | Mode::Words)
+    }
+
+    // Zen has no reference to fail against or pace along.
+    pub fn allows_strictness(self) -> bool {
+        self != Mode::Zen
+    }
+
+    // Practice drills keep their own error handling.
+    pub fn allows_error_settings(self) -> bool {
+        self != Mode::Zen && self != Mode::Practice
+    }
+}
+
+// Letters typed past the end of a word, kept per position of the space that ends it.
+pub const MAX_EXTRA_CHARS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WordKey {
+    Normal,
+    Ignore,
+    Extra,
+    Skip(usize),
+}
+
+// Letters past the end of a word overflow it and space in the middle of a word skips the rest of it.
+// Stop on letter keeps the cursor on the mistake instead and space at the start of a word does nothing.
+pub fn word_key(error_mode: ErrorMode, reference_chars: &[char], pos: usize, ch: char) -> WordKey {
+    let Some(&ref_char) = reference_chars.get(pos) else {
+        return WordKey::Normal;
+    };
+    if error_mode == ErrorMode::StopOnLetter {
+        return WordKey::Normal;
+    }
+    if ref_char == ' ' && !ch.is_whitespace() && is_word_end(reference_chars, pos) {
+        return WordKey::Extra;
+    }
+    if ch != ' ' || ref_char.is_whitespace() {
+        return WordKey::Normal;
+    }
+    if pos == 0 || reference_chars[pos - 1].is_whitespace() {
+        return WordKey::Ignore;
+    }
+    let word_end = reference_chars[pos..].iter().position(|c| c.is_whitespace()).map(|p| p + pos);
+    match word_end {
+        Some(word_end) if reference_chars[word_end] == ' ' && error_mode != ErrorMode::StopOnWord => WordKey::Skip(word_end),
+        _ => WordKey::Normal,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Strictness {
+    pub sudden_death: bool,
+    pub min_accuracy: Option<f64>,
+    pub error_mode: ErrorMode,
+    pub delete_word_on_error: bool,
+}
+
+impl Strictness {
+    // Sudden death fails on an error left in an already finished word, the current word can still be fixed.
+    // Accuracy is only checked when a word is finished so a single typo in the first word doesn't end the test.
+    pub fn check<'a>(&self, reference_chars: &[char], is_correct: impl IntoIterator<Item = &'a i32>, pos: usize, accuracy: f64) -> Option<&'static str> {
+        let word_start = if pos >= reference_chars.len() {
+            reference_chars.len()
+        } else {
+            reference_chars[..pos].iter().rposition(|c| c.is_whitespace()).map_or(0, |p| p + 1)
+        };
+        if self.sudden_death && is_correct.into_iter().take(word_start).any(|&v| matches!(v, -1 | 4 | 5)) {
+            return Some("uncorrected error");
+        }
+        if self.min_accuracy.is_some_and(|min| pos > 0 && word_start == pos && accuracy < min) {
+            return Some("accuracy below minimum");
+        }
+        None
+    }
+
+    pub fn label(&self) -> String {
+        let mut label = String::new();
+        if self.sudden_death {
+            label += " sudden death";
+        }
+        if let Some(min) = self.min_accuracy {
+            label += &format!(" min {:.0}%", min);
+        }
+        if self.error_mode != ErrorMode::Off {
+            label += &format!(" {}", self.error_mode.label());
+        }
+        if self.delete_word_on_error {
+            label += " word reset";
+        }
+        label
+    }
+}
+
+// Live stats shown next to the timer or word count while typing, set with --live or in the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Indicators {
+    pub wpm: bool,
+    pub accuracy: bool,
+    pub progress: bool,
+}
+
+impl Indicators {
+    pub const NAMES: [&'static str; 3] = ["wpm", "accuracy", "progress"];
+
+    // A comma separated list of names, e.g. "wpm,progress".
+    pub fn from_names(names: &str) -> Result<Self, String> {
+        let mut indicators = Indicators::default();
+        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "wpm" => indicators.wpm = true,
+                "accuracy" => indicators.accuracy = true,
+                "progress" => indicators.progress = true,
+                _ => return Err(format!("Unknown live indicator '{}', use any of: {}.", name, Indicators::NAMES.join(", "))),
+            }
+        }
+        Ok(indicators)
+    }
+
+    // The enabled numbers as one line, the progress bar is drawn by each frontend.
+    pub fn text(&self, wpm: f64, accuracy: f64) -> String {
+        let mut parts = Vec::new();
+        if self.wpm {
+            parts.push(format!("{:.0} wpm", wpm));
+        }
+        if self.accuracy {
+            parts.push(format!("{:.0}%", accuracy));
+        }
+        parts.join("  ")
+    }
+}
+
+pub fn live_wpm(words_done: usize, seconds: f64) -> f64 {
+    if seconds > 0.0 { words_done as f64 / seconds * 60.0 } else { 0.0 }
+}
+
+// Share of the test done: the time used in time tests, otherwise the part of the text typed.
+pub fn live_progress(time_limit: Option<f64>, seconds: f64, pos: usize, len: usize) -> f64 {
+    let progress = match time_limit {
+        Some(limit) if limit > 0.0 => seconds / limit,
+        _ if len > 0 => pos as f64 / len as f64,
+        _ => 0.0,
+    };
+    progress.clamp(0.0, 1.0)
+}
+
+pub fn progress_bar(progress: f64, width: usize) -> String {
+    let filled = ((progress * width as f64).round() as usize).min(width);
+    format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+// Label of the config button for a custom duration or word count, shows what's being typed or the value in use.
+pub fn length_label(input: Option<&str>, value: usize, is_preset: bool) -> String {
+    match input {
+        Some(input) => format!("{}_", input),
+        None if !is_preset => value.to_string(),
+        None => "edit".to_string(),
+    }
+}
+
+pub fn parse_length(input: &str) -> Option<usize> {
+    input.parse().ok().filter(|&n| n > 0)
+}
+
+// How long memory mode shows a word after the cursor reaches it.
+pub const MEMORY_PEEK: Duration = Duration::from_millis(1000);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Memory {
+    pub word_start: usize,
+    pub shown_at: Instant,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory { word_start: 0, shown_at: Instant::now() }
+    }
+
+    // Restarts the peek whenever the cursor enters a new word, the first word stays visible until the test starts.
+    pub fn update(&mut self, reference_chars: &[char], pos: usize, started: bool) {
+        let word_start = reference_chars[..pos.min(reference_chars.len())].iter().rposition(|c| c.is_whitespace()).map_or(0, |p| p + 1);
+        if word_start != self.word_start || !started {
+            self.word_start = word_start;
+            self.shown_at = Instant::now();
+        }
+    }
+
+    // Untyped characters are hidden except for the current word while it's still being shown, whitespace stays so the word lengths remain.
+    pub fn hidden(&self, reference_chars: &[char], pos: usize, i: usize) -> bool {
+        if i < pos || reference_chars.get(i).is_none_or(|c| c.is_whitespace()) {
+            return false;
+        }
+        let in_current_word = i >= self.word_start && !reference_chars[self.word_start..i].iter().any(|c| c.is_whitespace());
+        !(in_current_word && self.shown_at.elapsed() < MEMORY_PEEK)
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn calc_accuracy(is_correct: &VecDeque<i32>) -> f64 {
+    let correct_count = is_correct.iter().filter(|&&v| v == 1 || v == 2).count();
+    let all_pressed_count = is_correct.iter().filter(|&&v| v != 0 && v != 3).count();
+    if correct_count > 0 {
+        (correct_count as f64 / all_pressed_count as f64) * 100.0
+    } else {
+        0.0
+    }
+}
+
+// Net and raw WPM from words typed correctly and all words typed.
+pub fn calc_wpm(reference: &str, is_correct: &VecDeque<i32>, seconds: f64) -> (f64, f64) {
+    let (_corrected_words, correct_words, all_words) = count_correct_words(reference, is_correct);
+    let minutes = seconds / 60.0;
+    (correct_words as f64 / minutes, all_words as f64 / minutes)
+}
+
+// 100% when every second was typed at the same speed.
+pub fn calc_consistency(wpm_per_second: &[f64], wpm: f64) -> f64 {
+    if wpm_per_second.is_empty() || wpm <= 0.0 {
+        return 0.0;
+    }
+    let mean = wpm_per_second.iter().sum::<f64>() / wpm_per_second.len() as f64;
+    let variance = wpm_per_second.iter().map(|&x| (x - mean).powi(2)).sum::<f64>() / wpm_per_second.len() as f64;
+    (100.0 - (variance.sqrt() / wpm * 100.0).round()).max(0.0)
+}
+
 pub fn count_correct_words(reference: &str, is_correct: &VecDeque<i32>) -> (usize, usize, usize) {
     let mut correct_words = 0;
     let mut no_corrected_words = 0;
@@ -98,32 +479,39 @@ pub fn count_correct_words(reference: &str, is_correct: &VecDeque<i32>) -> (usiz
     let mut word_correct = true;
     let mut word_corrected = true;
     let mut char_idx = 0;
+    let mut prev_whitespace = true;
 
+    // is_correct: 0 - not typed, 2 - correct, 1 - corrected, -1 - wrong, 3 - indentation skipped in code mode,
+    // 4 - missed by skipping the word, 5 - space typed after extra letters
     for c in reference.chars() {
         if is_correct[char_idx] == 0 {
             break;
         }
-        if c == ' ' {
-            if word_correct && char_idx > 0 && (is_correct[char_idx] == 1 || is_correct[char_idx] == 2) {
-                correct_words += 1;
-            }
-            if word_corrected && char_idx > 0 && is_correct[char_idx] == 2 {
-                no_corrected_words += 1;
+        if c.is_whitespace() {
+            if !prev_whitespace {
+                if word_correct && char_idx > 0 && matches!(is_correct[char_idx], 1..=3) {
+                    correct_words += 1;
+                }
+                if word_corrected && char_idx > 0 && matches!(is_correct[char_idx], 2 | 3) {
+                    no_corrected_words += 1;
+                }
+                all_words += 1;
             }
-            all_words += 1;
             word_correct = true;
             word_corrected = true;
+            prev_whitespace = true;
         } else {
-            if char_idx < is_correct.len() && is_correct[char_idx] <= 0 {
+            if char_idx < is_correct.len() && (is_correct[char_idx] <= 0 || is_correct[char_idx] == 4) {
                 word_correct = false;
             }
-            if char_idx < is_correct.len() && is_correct[char_idx] == 1 || is_correct[char_idx] == -1 {
+            if char_idx < is_correct.len() && matches!(is_correct[char_idx], -1 | 1 | 4) {
                 word_corrected = false;
             }
+            prev_whitespace = false;
         }
         char_idx += 1;
     }
-    if !reference.ends_with(' ') && char_idx > 0 {
+    if !reference.ends_with(char::is_whitespace) && char_idx > 0 && !prev_whitespace {
         if word_correct {
             correct_words += 1;
         }
@@ -133,4 +521,22 @@ pub fn count_correct_words(reference: &str, is_correct: &VecDeque<i32>) -> (usiz
         all_words += 1;
     }
     (no_corrected_words, correct_words, all_words)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_follows_the_first_set_flag() {
+        assert_eq!(Mode::from_flags([false; 7]), Mode::Practice);
+        assert_eq!(Mode::from_flags([false, false, false, false, true, false, false]), Mode::Code);
+        assert!(Mode::Words.allows_punctuation());
+        assert!(!Mode::Quote.allows_punctuation());
+        assert!(Mode::Practice.allows_strictness());
+        assert!(!Mode::Practice.allows_error_settings());
+        assert!(!Mode::Zen.allows_strictness());
+        assert_eq!(Mode::canonical_name("word"), Mode::Words.name());
+        assert_eq!(Mode::canonical_name("quote"), "quote");
+    }
+}
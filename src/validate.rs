@@ -0,0 +1,240 @@
+use crate::history::{self, Record};
+use crate::simulate::{self, Script};
+use crate::utils;
+
+
+// Well above the fastest recorded typists, anything over it is not a person typing.
+pub const MAX_WPM: f64 = 300.0;
+// Separate key presses are hardly ever this close, a few rollovers are fine but not a whole test of them.
+const MIN_INTERVAL_MS: u64 = 10;
+const MAX_FAST_SHARE: f64 = 0.2;
+// People's rhythm varies by tens of milliseconds, a macro's doesn't.
+const MIN_DEVIATION_MS: f64 = 3.0;
+const MIN_KEYS: usize = 20;
+// How far the claimed result may be off what the keystroke log gives.
+const WPM_TOLERANCE: f64 = 1.15;
+const ACCURACY_TOLERANCE: f64 = 15.0;
+
+// Everything wrong with a result, empty when it looks like it was really typed.
+// Results without a keystroke log (older history) only get the range checks, any stats inside the range pass.
+pub fn check(record: &Record) -> Vec<String> {
+    let mut issues = Vec::new();
+    if !record.wpm.is_finite() || record.wpm < 0.0 || record.wpm > MAX_WPM {
+        issues.push(format!("{:.1} wpm is not humanly possible", record.wpm));
+    }
+    if !(0.0..=100.0).contains(&record.accuracy) {
+        issues.push(format!("accuracy of {:.1}% is out of range", record.accuracy));
+    }
+    if !record.seconds.is_finite() || record.seconds < 0.0 {
+        issues.push(format!("test time of {}s is out of range", record.seconds));
+    }
+    if record.raw_wpm > 0.0 && record.wpm > record.raw_wpm + 0.5 {
+        issues.push(format!("{:.1} wpm is more than the raw {:.1} wpm", record.wpm, record.raw_wpm));
+    }
+    if record.keys.is_empty() {
+        return issues;
+    }
+
+    if record.keys.windows(2).any(|pair| pair[1].at < pair[0].at) {
+        issues.push("the keystroke log is out of order".to_string());
+        return issues;
+    }
+    let intervals: Vec<u64> = record.keys.windows(2).map(|pair| pair[1].at - pair[0].at).collect();
+    if !intervals.is_empty() {
+        let fast = intervals.iter().filter(|&&interval| interval < MIN_INTERVAL_MS).count();
+        if fast as f64 / intervals.len() as f64 > MAX_FAST_SHARE {
+            issues.push(format!("{} of {} keys came less than {}ms after the previous one", fast, intervals.len(), MIN_INTERVAL_MS));
+        }
+    }
+    if record.keys.len() >= MIN_KEYS {
+        let mean = intervals.iter().sum::<u64>() as f64 / intervals.len() as f64;
+        let variance = intervals.iter().map(|&interval| (interval as f64 - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+        if variance.sqrt() < MIN_DEVIATION_MS {
+            issues.push(format!("the keys came at a constant {:.0}ms apart, like a macro", mean));
+        }
+    }
+    let span = record.keys.last().map(|key| key.at).unwrap_or(0) as f64 / 1000.0;
+    if span > record.seconds + 1.0 {
+        issues.push(format!("the keystroke log runs {:.1}s but the test took {:.1}s", span, record.seconds));
+    }
+
+    if record.reference.is_empty() {
+        return issues;
+    }
+    let mode = match record.mode.as_str() {
+        "time" | "practice" => record.mode.as_str(),
+        _ => utils::Mode::Words.name(),
+    };
+    let script = Script {
+        reference: record.reference.clone(),
+        mode: mode.to_string(),
+        time_limit: (mode == "time").then(|| record.seconds.round() as u64),
+        sudden_death: record.sudden_death,
+        min_accuracy: record.min_accuracy,
+        errors: Some(record.error_mode.clone()).filter(|name| !name.is_empty()),
+        delete_word_on_error: record.delete_word_on_error,
+        keys: record.keys.clone(),
+    };
+    // a log the simulator can't replay (keys it doesn't know) says nothing either way
+    if let Ok(replayed) = simulate::run(&script) {
+        if record.wpm > replayed.raw_wpm * WPM_TOLERANCE + 5.0 {
+            issues.push(format!("{:.1} wpm claimed but the keystroke log gives {:.1} wpm", record.wpm, replayed.raw_wpm));
+        }
+        if record.accuracy > replayed.accuracy + ACCURACY_TOLERANCE {
+            issues.push(format!("{:.1}% accuracy claimed but the keystroke log gives {:.1}%", record.accuracy, replayed.accuracy));
+        }
+    }
+    issues
+}
+
+// One line per flagged result and a summary, for `typeman validate`.
+pub fn report(records: &[Record]) -> String {
+    let mut lines = Vec::new();
+    let mut flagged = 0;
+    for record in records {
+        let issues = check(record);
+        if issues.is_empty() {
+            continue;
+        }
+        flagged += 1;
+        lines.push(format!(
+            "{} {} {} {:.1} wpm: {}",
+            history::date(record.timestamp),
+            record.frontend,
+            record.mode,
+            record.wpm,
+            issues.join(", "),
+        ));
+    }
+    lines.push(format!("{} of {} results flagged", flagged, records.len()));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::{key_name, Keystroke};
+
+    const REFERENCE: &str = "the quick brown fox jumps over the lazy dog";
+
+    // The reference typed without mistakes at a human, uneven rhythm, with the stats the log gives.
+    fn typed() -> Record {
+        let mut at = 0;
+        let keys: Vec<Keystroke> = REFERENCE
+            .chars()
+            .enumerate()
+            .map(|(i, key)| {
+                at += 120 + (i as u64 * 37) % 80;
+                Keystroke { at, key: key_name(key) }
+            })
+            .collect();
+        let mut record = Record {
+            frontend: "cli".to_string(),
+            mode: "words".to_string(),
+            seconds: (at - keys[0].at) as f64 / 1000.0,
+            reference: REFERENCE.to_string(),
+            keys,
+            ..Default::default()
+        };
+        let replayed = replay(&record);
+        record.wpm = replayed.wpm;
+        record.raw_wpm = replayed.raw_wpm;
+        record.accuracy = replayed.accuracy;
+        record
+    }
+
+    fn replay(record: &Record) -> crate::ui::cli::output::Report {
+        simulate::run(&Script { reference: record.reference.clone(), keys: record.keys.clone(), ..Default::default() }).unwrap()
+    }
+
+    // The same keys at new times, `interval` gives the gap before the i-th key.
+    fn retimed(interval: impl Fn(usize) -> u64) -> Record {
+        let mut record = typed();
+        let mut at = 0;
+        for (i, key) in record.keys.iter_mut().enumerate() {
+            at += interval(i);
+            key.at = at;
+        }
+        record.seconds = at as f64 / 1000.0;
+        record
+    }
+
+    #[test]
+    fn real_typing_passes() {
+        let record = typed();
+        assert!(record.wpm > 50.0 && record.wpm < 120.0, "{} wpm", record.wpm);
+        assert_eq!(check(&record), Vec::<String>::new());
+    }
+
+    #[test]
+    fn impossible_values_are_flagged() {
+        let record = Record { wpm: MAX_WPM + 1.0, raw_wpm: MAX_WPM + 1.0, accuracy: 100.0, ..Default::default() };
+        assert_eq!(check(&record), ["301.0 wpm is not humanly possible"]);
+        let record = Record { wpm: MAX_WPM, raw_wpm: MAX_WPM, accuracy: 100.0, ..Default::default() };
+        assert!(check(&record).is_empty());
+
+        let record = Record { wpm: 60.0, raw_wpm: 50.0, accuracy: 101.0, ..Default::default() };
+        assert_eq!(check(&record), ["accuracy of 101.0% is out of range", "60.0 wpm is more than the raw 50.0 wpm"]);
+    }
+
+    #[test]
+    fn pasted_input_is_flagged() {
+        // one key in four straight after the previous one is more than the fifth allowed
+        let record = retimed(|i| if i % 4 == 0 { 5 } else { 150 + (i as u64 * 37) % 80 });
+        assert!(check(&record).iter().any(|issue| issue.contains("less than 10ms after the previous one")), "{:?}", check(&record));
+
+        // one in six is within it
+        let record = retimed(|i| if i % 6 == 0 { 5 } else { 150 + (i as u64 * 37) % 80 });
+        assert!(!check(&record).iter().any(|issue| issue.contains("less than 10ms")), "{:?}", check(&record));
+    }
+
+    #[test]
+    fn constant_rhythm_is_flagged() {
+        let record = retimed(|i| 100 + (i % 2) as u64 * 2);
+        assert!(check(&record).iter().any(|issue| issue.contains("like a macro")), "{:?}", check(&record));
+
+        // a deviation of 4ms is enough
+        let record = retimed(|i| 100 + (i % 2) as u64 * 8);
+        assert!(!check(&record).iter().any(|issue| issue.contains("like a macro")), "{:?}", check(&record));
+    }
+
+    #[test]
+    fn out_of_order_log_is_flagged() {
+        let mut record = typed();
+        record.keys.swap(3, 4);
+        assert_eq!(check(&record), ["the keystroke log is out of order"]);
+    }
+
+    #[test]
+    fn stats_must_match_the_replayed_log() {
+        let record = typed();
+        let limit = record.raw_wpm * WPM_TOLERANCE + 5.0;
+
+        let claimed = Record { wpm: limit - 0.5, raw_wpm: limit, ..record.clone() };
+        assert!(check(&claimed).is_empty(), "{:?}", check(&claimed));
+        let claimed = Record { wpm: limit + 0.5, raw_wpm: limit + 0.5, ..record.clone() };
+        assert!(check(&claimed).iter().any(|issue| issue.contains("the keystroke log gives")), "{:?}", check(&claimed));
+
+        // ten wrong letters in the log, so it gives about 77%
+        let mut mistyped = record.clone();
+        for key in mistyped.keys.iter_mut().skip(1).step_by(4).take(10) {
+            key.key = "x".to_string();
+        }
+        let replayed = replay(&mistyped);
+        let claimed = Record { accuracy: replayed.accuracy + ACCURACY_TOLERANCE - 1.0, ..mistyped.clone() };
+        assert!(!check(&claimed).iter().any(|issue| issue.contains("accuracy claimed")), "{:?}", check(&claimed));
+        let claimed = Record { accuracy: replayed.accuracy + ACCURACY_TOLERANCE + 1.0, ..mistyped };
+        assert!(check(&claimed).iter().any(|issue| issue.contains("accuracy claimed")), "{:?}", check(&claimed));
+
+        let mut long = record.clone();
+        long.seconds = 2.0;
+        assert!(check(&long).iter().any(|issue| issue.contains("the keystroke log runs")), "{:?}", check(&long));
+    }
+
+    #[test]
+    fn results_without_a_log_only_get_the_range_checks() {
+        // an old result has no keys, so a made-up score inside the range can't be told apart
+        let record = Record { frontend: "gui".to_string(), mode: "words".to_string(), seconds: 10.0, wpm: 250.0, raw_wpm: 250.0, accuracy: 100.0, ..Default::default() };
+        assert!(check(&record).is_empty());
+    }
+}